@@ -0,0 +1,301 @@
+//! Benchmarks for the Intcode VM and every day's solution.
+//!
+//! Run with `cargo bench`, or `cargo bench -- <filter>` to only run the
+//! benchmarks whose name contains the filter. Results are written to
+//! `bench_output.txt` and compared with the previous run, so benchmarking
+//! two commits one after the other shows what got slower.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::hint::black_box;
+use std::path::PathBuf;
+use std::process::{self, Command};
+use std::time::{Duration, Instant};
+
+use advent_of_code::input;
+use advent_of_code::intcode_vm::Backend;
+use advent_of_code::solutions;
+use advent_of_code::{IntcodeVM, VMState};
+
+const SAMPLES: usize = 20;
+const SAMPLE_TIME: Duration = Duration::from_millis(25);
+/// How much slower a benchmark's fastest sample can get before it's
+/// reported as a regression. Medians are too noisy to compare on a busy
+/// machine.
+const THRESHOLD: f64 = 0.10;
+
+#[derive(Copy, Clone, Debug)]
+struct Measurement {
+    median: Duration,
+    min: Duration,
+}
+
+struct Bencher {
+    filter: Option<String>,
+    results: BTreeMap<String, Measurement>,
+}
+
+impl Bencher {
+    fn bench<T, F: FnMut() -> T>(&mut self, name: &str, mut f: F) {
+        if let Some(filter) = &self.filter {
+            if !name.contains(filter.as_str()) {
+                return;
+            }
+        }
+
+        // Warm up, and find how many iterations fill a sample.
+        let mut iterations = 1;
+        loop {
+            let start = Instant::now();
+            for _ in 0..iterations {
+                black_box(f());
+            }
+            if start.elapsed() >= SAMPLE_TIME / 2 {
+                break;
+            }
+            iterations *= 2;
+        }
+
+        let mut samples = (0..SAMPLES)
+            .map(|_| {
+                let start = Instant::now();
+                for _ in 0..iterations {
+                    black_box(f());
+                }
+                start.elapsed() / iterations
+            })
+            .collect::<Vec<_>>();
+        samples.sort();
+
+        let measurement = Measurement {
+            median: samples[SAMPLES / 2],
+            min: samples[0],
+        };
+        println!(
+            "{:<32} median {:>12?}  min {:>12?}",
+            name, measurement.median, measurement.min
+        );
+        self.results.insert(name.to_string(), measurement);
+    }
+}
+
+fn intcode_program(day: u8) -> Vec<i64> {
+    let input = input::Source::Day(day).read().unwrap();
+    input::parse_intcode(&input).unwrap()
+}
+
+fn vm(program: &[i64], backend: Backend) -> IntcodeVM {
+    let mut vm = IntcodeVM::new();
+    vm.set_backend(backend);
+    vm.set_program(program.to_vec());
+    vm
+}
+
+fn run(program: &[i64], backend: Backend, input: i64) -> Vec<i64> {
+    let mut vm = vm(program, backend);
+    vm.push_input(input);
+    vm.run_once().unwrap()
+}
+
+/// One run of day 7's amplifier feedback loop.
+fn feedback_loop(program: &[i64], backend: Backend, phases: &[i64]) -> i64 {
+    let mut amps = phases
+        .iter()
+        .map(|phase| {
+            let mut amp = vm(program, backend);
+            amp.push_input(*phase);
+            amp
+        })
+        .collect::<Vec<_>>();
+
+    let mut signal = 0;
+    for index in (0..amps.len()).cycle() {
+        amps[index].push_input(signal);
+        match amps[index].execute().unwrap() {
+            VMState::Output(output) => signal = output,
+            VMState::Halted => return signal,
+            state => panic!("amplifier {} stopped: {:?}", index, state),
+        }
+    }
+
+    unreachable!()
+}
+
+fn bench_intcode(bencher: &mut Bencher) {
+    let day05 = intcode_program(5);
+    let day07 = intcode_program(7);
+    let day09 = intcode_program(9);
+
+    for (backend, name) in [
+        (Backend::Interpreter, "interpreter"),
+        (Backend::Compiled, "compiled"),
+    ]
+    .iter()
+    {
+        bencher.bench(&format!("intcode/day05/{}", name), || {
+            run(&day05, *backend, 5)
+        });
+        bencher.bench(&format!("intcode/day07/{}", name), || {
+            feedback_loop(&day07, *backend, &[9, 8, 7, 6, 5])
+        });
+        bencher.bench(&format!("intcode/day09/{}", name), || {
+            run(&day09, *backend, 2)
+        });
+    }
+}
+
+fn bench_solutions(bencher: &mut Bencher) {
+    for solution in solutions::all() {
+        let input = input::Source::Day(solution.day()).read().unwrap();
+        for part in 1..=2 {
+            let name = format!("solve/day{:02}/part{}", solution.day(), part);
+            bencher.bench(&name, || solution.part(part, &input).unwrap().to_string());
+        }
+    }
+}
+
+fn output_path() -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "bench_output.txt"]
+        .iter()
+        .collect()
+}
+
+/// The checked out commit, marked dirty if there are uncommitted changes.
+fn commit() -> String {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+
+    match (
+        git(&["rev-parse", "--short", "HEAD"]),
+        git(&["status", "--porcelain"]),
+    ) {
+        (Some(hash), Some(status)) if !status.is_empty() => format!("{}-dirty", hash),
+        (Some(hash), _) => hash,
+        (None, _) => "unknown".to_string(),
+    }
+}
+
+/// Reads results saved by `save`: a `# commit` line followed by one
+/// `name median_ns min_ns` line per benchmark.
+fn load(text: &str) -> (String, BTreeMap<String, Measurement>) {
+    let mut commit = "unknown".to_string();
+    let mut results = BTreeMap::new();
+
+    for line in text.lines() {
+        if let Some(hash) = line.strip_prefix("# commit ") {
+            commit = hash.to_string();
+            continue;
+        }
+
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        if let [name, median, min] = fields.as_slice() {
+            if let (Ok(median), Ok(min)) = (median.parse(), min.parse()) {
+                let measurement = Measurement {
+                    median: Duration::from_nanos(median),
+                    min: Duration::from_nanos(min),
+                };
+                results.insert(name.to_string(), measurement);
+            }
+        }
+    }
+
+    (commit, results)
+}
+
+fn save(commit: &str, results: &BTreeMap<String, Measurement>) -> String {
+    let mut text = format!("# commit {}\n", commit);
+    for (name, measurement) in results {
+        text += &format!(
+            "{} {} {}\n",
+            name,
+            measurement.median.as_nanos(),
+            measurement.min.as_nanos()
+        );
+    }
+
+    text
+}
+
+/// Prints how every benchmark changed since the previous run and returns
+/// the number of regressions.
+fn compare(
+    previous_commit: &str,
+    previous: &BTreeMap<String, Measurement>,
+    results: &BTreeMap<String, Measurement>,
+) -> usize {
+    println!("\nCompared with {}:", previous_commit);
+
+    let mut regressions = 0;
+    for (name, measurement) in results {
+        let before = match previous.get(name) {
+            Some(before) => before,
+            None => {
+                println!("{:<32} new", name);
+                continue;
+            }
+        };
+
+        let change = measurement.min.as_secs_f64() / before.min.as_secs_f64() - 1.0;
+        let verdict = if change > THRESHOLD {
+            regressions += 1;
+            "REGRESSED"
+        } else if change < -THRESHOLD {
+            "improved"
+        } else {
+            ""
+        };
+        println!(
+            "{:<32} {:>12?} -> {:>12?} {:>+7.1}% {}",
+            name,
+            before.min,
+            measurement.min,
+            change * 100.0,
+            verdict
+        );
+    }
+
+    regressions
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`; anything else is a filter.
+    let filter = env::args().skip(1).find(|arg| !arg.starts_with("--"));
+    let mut bencher = Bencher {
+        filter,
+        results: BTreeMap::new(),
+    };
+
+    bench_intcode(&mut bencher);
+    bench_solutions(&mut bencher);
+
+    let path = output_path();
+    let (previous_commit, previous) = match fs::read_to_string(&path) {
+        Ok(text) => load(&text),
+        Err(_) => ("nothing".to_string(), BTreeMap::new()),
+    };
+    let regressions = compare(&previous_commit, &previous, &bencher.results);
+
+    // Keep the results of benchmarks that were filtered out.
+    let mut saved = previous;
+    saved.extend(bencher.results);
+    if let Err(error) = fs::write(&path, save(&commit(), &saved)) {
+        eprintln!("couldn't write {}: {}", path.display(), error);
+        process::exit(1);
+    }
+
+    if regressions > 0 {
+        println!(
+            "\n{} benchmark(s) got more than {}% slower",
+            regressions,
+            THRESHOLD * 100.0
+        );
+    }
+}
@@ -0,0 +1,128 @@
+use std::env;
+use std::error::Error;
+use std::process;
+use std::time::{Duration, Instant};
+
+use advent_of_code::input::Source;
+use advent_of_code::solutions::{self, Solution};
+
+const USAGE: &str = "\
+Usage: aoc run <day|all> [--part <1|2>] [--input <path|->]
+
+  --part   only run the given part
+  --input  read the input from a file, or stdin for -, instead of inputs/dayNN.txt";
+
+struct Options {
+    days: Vec<&'static dyn Solution>,
+    parts: Vec<u8>,
+    input: Option<Source>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let (day, mut rest) = match args {
+        [command, day, rest @ ..] if command == "run" => (day, rest),
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let days = if day == "all" {
+        solutions::all().collect()
+    } else {
+        let day = day
+            .parse::<u8>()
+            .map_err(|_| format!("`{}` is not a day", day))?;
+        let solution = solutions::get(day).ok_or_else(|| format!("day {} isn't solved", day))?;
+        vec![solution]
+    };
+
+    let mut parts = vec![1, 2];
+    let mut input = None;
+    while let [flag, value, tail @ ..] = rest {
+        match flag.as_str() {
+            "--part" => match value.as_str() {
+                "1" => parts = vec![1],
+                "2" => parts = vec![2],
+                _ => return Err(format!("`{}` is not a part", value)),
+            },
+            "--input" => input = Some(Source::from_arg(value)),
+            _ => return Err(format!("unknown option `{}`\n\n{}", flag, USAGE)),
+        }
+        rest = tail;
+    }
+    if !rest.is_empty() {
+        return Err(format!("`{}` needs a value\n\n{}", rest[0], USAGE));
+    }
+
+    if input.is_some() && days.len() > 1 {
+        return Err("--input can only be used with a single day".to_string());
+    }
+
+    Ok(Options { days, parts, input })
+}
+
+fn format_duration(duration: Duration) -> String {
+    let micros = duration.as_micros();
+    if micros < 1_000 {
+        format!("{}µs", micros)
+    } else if micros < 1_000_000 {
+        format!("{:.2}ms", micros as f64 / 1_000.0)
+    } else {
+        format!("{:.2}s", duration.as_secs_f64())
+    }
+}
+
+fn run(options: Options) -> Result<(), Box<dyn Error>> {
+    let mut failed = false;
+
+    for solution in options.days {
+        let source = options
+            .input
+            .clone()
+            .unwrap_or_else(|| Source::Day(solution.day()));
+        let input = source.read()?;
+
+        for part in options.parts.iter() {
+            let start = Instant::now();
+            let answer = solution.part(*part, &input);
+            let elapsed = format_duration(start.elapsed());
+
+            match answer {
+                Ok(answer) => println!(
+                    "Day {:>2} part {}: {} ({})",
+                    solution.day(),
+                    part,
+                    answer,
+                    elapsed
+                ),
+                Err(error) => {
+                    failed = true;
+                    println!(
+                        "Day {:>2} part {}: error: {} ({})",
+                        solution.day(),
+                        part,
+                        error,
+                        elapsed
+                    );
+                }
+            }
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(2);
+        }
+    };
+
+    run(options)
+}
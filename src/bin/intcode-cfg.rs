@@ -0,0 +1,21 @@
+use std::env;
+use std::error::Error;
+use std::process;
+
+use advent_of_code::input;
+use advent_of_code::intcode_vm::cfg::Cfg;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = env::args().collect::<Vec<_>>();
+    if args.len() < 2 {
+        eprintln!("Usage: {} <program>", args[0]);
+        process::exit(2);
+    }
+
+    let input = input::Source::from_arg(&args[1]).read()?;
+    let program = input::parse_intcode(&input)?;
+
+    print!("{}", Cfg::build(&program).to_dot());
+
+    Ok(())
+}
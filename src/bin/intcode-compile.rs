@@ -0,0 +1,26 @@
+use std::env;
+use std::error::Error;
+use std::process;
+
+use advent_of_code::input;
+use advent_of_code::intcode_vm::lang::{compile, compile_to_asm};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = env::args().collect::<Vec<_>>();
+    if args.len() < 2 {
+        eprintln!("Usage: {} <source> [--asm]", args[0]);
+        process::exit(2);
+    }
+
+    let source = input::Source::from_arg(&args[1]).read()?;
+
+    if args.get(2).map(String::as_str) == Some("--asm") {
+        print!("{}", compile_to_asm(&source)?);
+    } else {
+        let program = compile(&source)?;
+        let words = program.iter().map(i64::to_string).collect::<Vec<_>>();
+        println!("{}", words.join(","));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,199 @@
+use std::env;
+use std::error::Error;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+use advent_of_code::input;
+use advent_of_code::intcode_vm::debugger::{Debugger, StopReason};
+use advent_of_code::intcode_vm::disassemble_memory;
+use advent_of_code::{IntcodeVM, VMState};
+
+/// The most words `print` shows at once.
+const MAX_PRINT: usize = 4096;
+
+const HELP: &str = "\
+Commands:
+  step [n]              execute n instructions (default 1)
+  continue              run until a breakpoint, watchpoint, output, input or halt
+  break [addr]          set a breakpoint at addr, or list breakpoints
+  break del <addr>      remove a breakpoint
+  watch [addr]          stop when addr is written to, or list watchpoints
+  watch del <addr>      remove a watchpoint
+  print                 show the instruction pointer, relative base and pending inputs
+  print <addr> [count]  show count memory words starting at addr
+  set <addr> <value>    write value to addr
+  set ip|rb <value>     move the instruction pointer or relative base
+  input <value>...      queue input values
+  disasm [addr] [count] disassemble count instructions from addr (default: ip, 10)
+  quit                  exit the debugger";
+
+fn parse_number<T: std::str::FromStr>(text: Option<&&str>) -> Result<T, String> {
+    let text = text.ok_or_else(|| "missing argument".to_string())?;
+    text.parse::<T>()
+        .map_err(|_| format!("`{}` is not a valid number", text))
+}
+
+fn report(debugger: &Debugger, reason: StopReason) {
+    match reason {
+        StopReason::Stepped => {}
+        StopReason::Breakpoint(address) => println!("breakpoint at {}", address),
+        StopReason::Watchpoint(write) => println!(
+            "watchpoint: [{}] {} -> {}",
+            write.address, write.old_value, write.new_value
+        ),
+        StopReason::State(VMState::Output(value)) => println!("output: {}", value),
+        StopReason::State(VMState::NeedsInput) => println!("waiting for input"),
+        StopReason::State(VMState::Halted) => println!("halted"),
+        StopReason::State(VMState::OutOfFuel) => println!("out of fuel"),
+        StopReason::State(VMState::TimedOut) => println!("timed out"),
+    }
+
+    let vm = debugger.vm();
+    if let Some(instruction) = disassemble_memory(vm.memory(), vm.instruction_pointer(), 1).first()
+    {
+        println!("{}", instruction);
+    }
+}
+
+fn run_command(debugger: &mut Debugger, args: &[&str]) -> Result<(), String> {
+    match args {
+        ["step"] | ["s"] => {
+            let reason = debugger.step().map_err(|e| e.to_string())?;
+            report(debugger, reason);
+        }
+        ["step", count] | ["s", count] => {
+            let count = parse_number::<usize>(Some(count))?;
+            for _ in 0..count {
+                let reason = debugger.step().map_err(|e| e.to_string())?;
+                if reason != StopReason::Stepped || count == 1 {
+                    report(debugger, reason);
+                    return Ok(());
+                }
+            }
+            report(debugger, StopReason::Stepped);
+        }
+        ["continue"] | ["c"] => {
+            let reason = debugger.resume().map_err(|e| e.to_string())?;
+            report(debugger, reason);
+        }
+        ["break"] => {
+            for address in debugger.breakpoints() {
+                println!("breakpoint at {}", address);
+            }
+        }
+        ["break", "del", address] => {
+            debugger.remove_breakpoint(parse_number(Some(address))?);
+        }
+        ["break", address] => {
+            debugger.add_breakpoint(parse_number(Some(address))?);
+        }
+        ["watch"] => {
+            for address in debugger.watchpoints() {
+                println!("watchpoint at {}", address);
+            }
+        }
+        ["watch", "del", address] => {
+            debugger.remove_watchpoint(parse_number(Some(address))?);
+        }
+        ["watch", address] => {
+            debugger.add_watchpoint(parse_number(Some(address))?);
+        }
+        ["print"] | ["p"] => {
+            let vm = debugger.vm();
+            println!("ip: {}", vm.instruction_pointer());
+            println!("rb: {}", vm.relative_pointer());
+            println!("inputs: {:?}", vm.inputs());
+        }
+        ["print", rest @ ..] | ["p", rest @ ..] => {
+            let address = parse_number::<usize>(rest.first())?;
+            let count = rest
+                .get(1)
+                .map(|count| parse_number::<usize>(Some(count)))
+                .unwrap_or(Ok(1))?;
+            if count > MAX_PRINT {
+                return Err(format!("can't print more than {} words", MAX_PRINT));
+            }
+            for address in (0..count).map_while(|offset| address.checked_add(offset)) {
+                println!("[{}] {}", address, debugger.vm().read_memory(address));
+            }
+        }
+        ["set", "ip", value] => {
+            let value = parse_number(Some(value))?;
+            debugger.vm_mut().set_instruction_pointer(value);
+        }
+        ["set", "rb", value] => {
+            let value = parse_number(Some(value))?;
+            debugger.vm_mut().set_relative_pointer(value);
+        }
+        ["set", address, value] => {
+            let address = parse_number(Some(address))?;
+            let value = parse_number(Some(value))?;
+            debugger
+                .vm_mut()
+                .write_memory(address, value)
+                .map_err(|e| e.to_string())?;
+        }
+        ["input", values @ ..] if !values.is_empty() => {
+            for value in values {
+                let value = parse_number(Some(value))?;
+                debugger.vm_mut().push_input(value);
+            }
+        }
+        ["disasm", rest @ ..] => {
+            let address = match rest.first() {
+                Some(_) => parse_number::<usize>(rest.first())?,
+                None => debugger.vm().instruction_pointer(),
+            };
+            let count = match rest.get(1) {
+                Some(_) => parse_number::<usize>(rest.get(1))?,
+                None => 10,
+            };
+            for instruction in disassemble_memory(debugger.vm().memory(), address, count) {
+                println!("{}", instruction);
+            }
+        }
+        ["help"] | ["h"] => println!("{}", HELP),
+        _ => return Err("unknown command, try `help`".to_string()),
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = env::args().collect::<Vec<_>>();
+    if args.len() < 2 {
+        eprintln!("Usage: {} <program>", args[0]);
+        process::exit(2);
+    }
+
+    let input = input::Source::from_arg(&args[1]).read()?;
+    let program = input::parse_intcode(&input)?;
+
+    let mut vm = IntcodeVM::new();
+    vm.set_program(program);
+    let mut debugger = Debugger::new(vm);
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("(icdb) ");
+        io::stdout().flush()?;
+
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        let args = line.split_whitespace().collect::<Vec<_>>();
+        match args.as_slice() {
+            [] => continue,
+            ["quit"] | ["q"] => break,
+            args => {
+                if let Err(error) = run_command(&mut debugger, args) {
+                    println!("error: {}", error);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
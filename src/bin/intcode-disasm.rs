@@ -0,0 +1,28 @@
+use std::env;
+use std::error::Error;
+use std::process;
+
+use advent_of_code::input;
+use advent_of_code::intcode_vm::disassemble_from;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = env::args().collect::<Vec<_>>();
+    if args.len() < 2 {
+        eprintln!("Usage: {} <program> [entry]", args[0]);
+        process::exit(2);
+    }
+
+    let input = input::Source::from_arg(&args[1]).read()?;
+    let program = input::parse_intcode(&input)?;
+
+    let entry = args
+        .get(2)
+        .map(|entry| entry.parse::<usize>())
+        .unwrap_or(Ok(0))?;
+
+    for instruction in disassemble_from(&program, entry) {
+        println!("{}", instruction);
+    }
+
+    Ok(())
+}
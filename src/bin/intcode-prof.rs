@@ -0,0 +1,67 @@
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::process;
+
+use advent_of_code::input;
+use advent_of_code::intcode_vm::profile::Profiler;
+use advent_of_code::{IntcodeVM, VMState};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = env::args().collect::<Vec<_>>();
+    if args.len() < 2 {
+        eprintln!("Usage: {} <program> [inputs...] [--folded <file>]", args[0]);
+        process::exit(2);
+    }
+
+    let input = input::Source::from_arg(&args[1]).read()?;
+    let program = input::parse_intcode(&input)?;
+
+    let mut vm = IntcodeVM::new();
+    vm.set_program(program.clone());
+
+    let mut folded = None;
+    let mut rest = args[2..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--folded" => folded = Some(rest.next().ok_or("--folded needs a file")?),
+            value => vm.push_input(value.parse()?),
+        }
+    }
+
+    let mut profiler = Profiler::new();
+    loop {
+        match vm.execute_traced(&mut profiler)? {
+            VMState::Output(value) => println!("output: {}", value),
+            VMState::Halted => break,
+            state => {
+                println!("stopped: {:?}", state);
+                break;
+            }
+        }
+    }
+
+    println!();
+    println!("{} instructions", profiler.instructions());
+    for (mnemonic, count) in profiler.opcode_counts() {
+        println!("{:>10} {}", count, mnemonic);
+    }
+
+    println!();
+    for hot_loop in profiler.hot_loops().iter().take(10) {
+        println!(
+            "loop {}..={}: {} iterations, {} instructions",
+            hot_loop.head, hot_loop.tail, hot_loop.iterations, hot_loop.instructions
+        );
+    }
+
+    println!();
+    print!("{}", profiler.annotate(&program));
+
+    if let Some(path) = folded {
+        profiler.write_folded(BufWriter::new(File::create(path)?))?;
+    }
+
+    Ok(())
+}
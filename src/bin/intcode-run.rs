@@ -0,0 +1,37 @@
+use std::env;
+use std::error::Error;
+use std::io;
+use std::process;
+
+use advent_of_code::input;
+use advent_of_code::intcode_vm::ascii::TerminalMode;
+use advent_of_code::IntcodeVM;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = env::args().collect::<Vec<_>>();
+    let mode = match args.get(2).map(String::as_str) {
+        _ if args.len() < 2 => None,
+        None => Some(TerminalMode::Ascii),
+        Some("--numeric") => Some(TerminalMode::Numeric),
+        Some(_) => None,
+    };
+    let mode = match mode {
+        Some(mode) => mode,
+        None => {
+            eprintln!("Usage: {} <program> [--numeric]", args[0]);
+            process::exit(2);
+        }
+    };
+
+    let input = input::Source::from_arg(&args[1]).read()?;
+    let program = input::parse_intcode(&input)?;
+
+    let mut vm = IntcodeVM::new();
+    vm.set_program(program);
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    vm.run_terminal(mode, stdin.lock(), stdout.lock())?;
+
+    Ok(())
+}
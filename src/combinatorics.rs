@@ -0,0 +1,358 @@
+//! Permutations, combinations and cartesian products, for puzzles that
+//! search every arrangement of their inputs.
+//!
+//! The sequential iterators are lazy and yield each arrangement as a new
+//! `Vec`. The `par_` functions split the same arrangements across rayon's
+//! threads by computing the `n`th one directly, so they yield them in
+//! lexicographic order of item positions, which for permutations differs
+//! from the order `permutations` yields them in.
+
+use rayon::prelude::*;
+
+/// Every ordering of `items`, generated with Heap's algorithm, so each one
+/// differs from the one before by a single swap.
+pub fn permutations<T: Clone, I: IntoIterator<Item = T>>(items: I) -> Permutations<T> {
+    let items = items.into_iter().collect::<Vec<_>>();
+    Permutations {
+        counters: vec![0; items.len()],
+        items,
+        index: 1,
+        first: true,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Permutations<T> {
+    items: Vec<T>,
+    /// Heap's algorithm's per-level loop counters.
+    counters: Vec<usize>,
+    index: usize,
+    first: bool,
+}
+
+impl<T: Clone> Iterator for Permutations<T> {
+    type Item = Vec<T>;
+
+    fn next(&mut self) -> Option<Vec<T>> {
+        if self.first {
+            self.first = false;
+            return Some(self.items.clone());
+        }
+
+        while self.index < self.items.len() {
+            let index = self.index;
+            if self.counters[index] < index {
+                if index.is_multiple_of(2) {
+                    self.items.swap(0, index);
+                } else {
+                    self.items.swap(self.counters[index], index);
+                }
+                self.counters[index] += 1;
+                self.index = 1;
+                return Some(self.items.clone());
+            }
+
+            self.counters[index] = 0;
+            self.index += 1;
+        }
+
+        None
+    }
+}
+
+/// Every way of choosing `k` of `items`, keeping their order.
+pub fn combinations<T: Clone, I: IntoIterator<Item = T>>(items: I, k: usize) -> Combinations<T> {
+    let items = items.into_iter().collect::<Vec<_>>();
+    Combinations {
+        done: k > items.len(),
+        items,
+        indices: (0..k).collect(),
+        first: true,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Combinations<T> {
+    items: Vec<T>,
+    /// The positions of the chosen items, in increasing order.
+    indices: Vec<usize>,
+    first: bool,
+    done: bool,
+}
+
+impl<T: Clone> Combinations<T> {
+    fn current(&self) -> Vec<T> {
+        self.indices
+            .iter()
+            .map(|&i| self.items[i].clone())
+            .collect()
+    }
+}
+
+impl<T: Clone> Iterator for Combinations<T> {
+    type Item = Vec<T>;
+
+    fn next(&mut self) -> Option<Vec<T>> {
+        if self.done {
+            return None;
+        }
+        if self.first {
+            self.first = false;
+            return Some(self.current());
+        }
+
+        // Move the last index that isn't as far right as it can go, and put
+        // the ones after it straight after it.
+        let (n, k) = (self.items.len(), self.indices.len());
+        match (0..k).rev().find(|&i| self.indices[i] != i + n - k) {
+            Some(i) => {
+                self.indices[i] += 1;
+                for j in i + 1..k {
+                    self.indices[j] = self.indices[j - 1] + 1;
+                }
+                Some(self.current())
+            }
+            None => {
+                self.done = true;
+                None
+            }
+        }
+    }
+}
+
+/// Every way of taking one item from each pool, varying the last pool's
+/// item fastest.
+pub fn product<T, I, P>(pools: I) -> Product<T>
+where
+    T: Clone,
+    I: IntoIterator<Item = P>,
+    P: IntoIterator<Item = T>,
+{
+    let pools = collect_pools(pools);
+    Product {
+        done: pools.iter().any(Vec::is_empty),
+        indices: vec![0; pools.len()],
+        pools,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Product<T> {
+    pools: Vec<Vec<T>>,
+    indices: Vec<usize>,
+    done: bool,
+}
+
+impl<T: Clone> Iterator for Product<T> {
+    type Item = Vec<T>;
+
+    fn next(&mut self) -> Option<Vec<T>> {
+        if self.done {
+            return None;
+        }
+
+        let item = self
+            .indices
+            .iter()
+            .zip(&self.pools)
+            .map(|(&i, pool)| pool[i].clone())
+            .collect();
+
+        self.done = true;
+        for (index, pool) in self.indices.iter_mut().zip(&self.pools).rev() {
+            *index += 1;
+            if *index < pool.len() {
+                self.done = false;
+                break;
+            }
+            *index = 0;
+        }
+
+        Some(item)
+    }
+}
+
+fn collect_pools<T, I, P>(pools: I) -> Vec<Vec<T>>
+where
+    I: IntoIterator<Item = P>,
+    P: IntoIterator<Item = T>,
+{
+    pools
+        .into_iter()
+        .map(|pool| pool.into_iter().collect())
+        .collect()
+}
+
+/// `n!`. Overflows past `20!` on 64 bit targets.
+pub fn factorial(n: usize) -> usize {
+    (1..=n).product()
+}
+
+/// The number of ways of choosing `k` of `n` items.
+pub fn binomial(n: usize, k: usize) -> usize {
+    if k > n {
+        return 0;
+    }
+
+    let k = k.min(n - k);
+    (0..k).fold(1, |result, i| result * (n - i) / (i + 1))
+}
+
+/// The `index`th ordering of `items`, counting in lexicographic order of
+/// their positions.
+pub fn nth_permutation<T: Clone>(items: &[T], mut index: usize) -> Vec<T> {
+    let mut remaining = items.to_vec();
+    let mut permutation = Vec::with_capacity(items.len());
+
+    for position in 0..items.len() {
+        let block = factorial(items.len() - position - 1);
+        permutation.push(remaining.remove(index / block));
+        index %= block;
+    }
+
+    permutation
+}
+
+/// The `index`th way of choosing `k` of `items`, in the order
+/// `combinations` yields them.
+pub fn nth_combination<T: Clone>(items: &[T], k: usize, mut index: usize) -> Vec<T> {
+    let mut combination = Vec::with_capacity(k);
+    let mut candidate = 0;
+
+    while combination.len() < k {
+        // The number of combinations that start with `candidate`.
+        let count = binomial(items.len() - candidate - 1, k - combination.len() - 1);
+        if index < count {
+            combination.push(items[candidate].clone());
+        } else {
+            index -= count;
+        }
+        candidate += 1;
+    }
+
+    combination
+}
+
+/// The `index`th item of `product(pools)`.
+pub fn nth_product<T: Clone>(pools: &[Vec<T>], mut index: usize) -> Vec<T> {
+    let mut item = pools
+        .iter()
+        .rev()
+        .map(|pool| {
+            let value = pool[index % pool.len()].clone();
+            index /= pool.len();
+            value
+        })
+        .collect::<Vec<_>>();
+    item.reverse();
+    item
+}
+
+/// `permutations`, split across threads.
+pub fn par_permutations<T, I>(items: I) -> impl IndexedParallelIterator<Item = Vec<T>>
+where
+    T: Clone + Send + Sync,
+    I: IntoIterator<Item = T>,
+{
+    let items = items.into_iter().collect::<Vec<_>>();
+    (0..factorial(items.len()))
+        .into_par_iter()
+        .map(move |index| nth_permutation(&items, index))
+}
+
+/// `combinations`, split across threads.
+pub fn par_combinations<T, I>(items: I, k: usize) -> impl IndexedParallelIterator<Item = Vec<T>>
+where
+    T: Clone + Send + Sync,
+    I: IntoIterator<Item = T>,
+{
+    let items = items.into_iter().collect::<Vec<_>>();
+    (0..binomial(items.len(), k))
+        .into_par_iter()
+        .map(move |index| nth_combination(&items, k, index))
+}
+
+/// `product`, split across threads.
+pub fn par_product<T, I, P>(pools: I) -> impl IndexedParallelIterator<Item = Vec<T>>
+where
+    T: Clone + Send + Sync,
+    I: IntoIterator<Item = P>,
+    P: IntoIterator<Item = T>,
+{
+    let pools = collect_pools(pools);
+    let count = pools.iter().map(Vec::len).product();
+    (0..count)
+        .into_par_iter()
+        .map(move |index| nth_product(&pools, index))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rayon::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_permutations_are_all_distinct() {
+        for n in 0..=6 {
+            let all = permutations(0..n).collect::<Vec<_>>();
+            assert_eq!(all.len(), factorial(n as usize));
+            assert_eq!(all.iter().collect::<HashSet<_>>().len(), all.len());
+        }
+        assert_eq!(
+            permutations(vec!['a', 'b', 'c']).collect::<Vec<_>>(),
+            vec![
+                vec!['a', 'b', 'c'],
+                vec!['b', 'a', 'c'],
+                vec!['c', 'a', 'b'],
+                vec!['a', 'c', 'b'],
+                vec!['b', 'c', 'a'],
+                vec!['c', 'b', 'a'],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_combinations() {
+        assert_eq!(
+            combinations(1..=4, 2).collect::<Vec<_>>(),
+            vec![
+                vec![1, 2],
+                vec![1, 3],
+                vec![1, 4],
+                vec![2, 3],
+                vec![2, 4],
+                vec![3, 4],
+            ]
+        );
+        assert_eq!(combinations(1..=3, 0).collect::<Vec<_>>(), vec![vec![]]);
+        assert_eq!(combinations(1..=3, 4).count(), 0);
+    }
+
+    #[test]
+    fn test_product() {
+        assert_eq!(
+            product(vec![vec![1, 2], vec![3], vec![4, 5]]).collect::<Vec<_>>(),
+            vec![vec![1, 3, 4], vec![1, 3, 5], vec![2, 3, 4], vec![2, 3, 5]]
+        );
+        assert_eq!(product(vec![0..2, 0..0]).count(), 0);
+    }
+
+    #[test]
+    fn test_parallel_variants_match_sequential() {
+        let mut sequential = permutations(0..5).collect::<Vec<_>>();
+        sequential.sort();
+        assert_eq!(par_permutations(0..5).collect::<Vec<_>>(), sequential);
+
+        assert_eq!(
+            par_combinations(0..7, 3).collect::<Vec<_>>(),
+            combinations(0..7, 3).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            par_product(vec![0..3, 0..4, 0..2]).collect::<Vec<_>>(),
+            product(vec![0..3, 0..4, 0..2]).collect::<Vec<_>>()
+        );
+    }
+}
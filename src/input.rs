@@ -0,0 +1,354 @@
+//! Loading and parsing puzzle inputs.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ParseErrorKind {
+    InvalidNumber(String),
+    InvalidDigit(char),
+    RaggedRow { expected: usize, found: usize },
+    EmptyField,
+    Empty,
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseErrorKind::InvalidNumber(text) => write!(f, "`{}` is not a valid number", text),
+            ParseErrorKind::InvalidDigit(c) => write!(f, "`{}` is not a digit", c),
+            ParseErrorKind::RaggedRow { expected, found } => {
+                write!(f, "row is {} characters wide, expected {}", found, expected)
+            }
+            ParseErrorKind::EmptyField => write!(f, "expected a value"),
+            ParseErrorKind::Empty => write!(f, "input is empty"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum InputError {
+    Io {
+        source: String,
+        error: io::Error,
+    },
+    /// A parse error at a 1-based line and column.
+    Parse {
+        line: usize,
+        column: usize,
+        kind: ParseErrorKind,
+    },
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InputError::Io { source, error } => write!(f, "couldn't read {}: {}", source, error),
+            InputError::Parse { line, column, kind } => {
+                write!(f, "line {}, column {}: {}", line, column, kind)
+            }
+        }
+    }
+}
+
+impl Error for InputError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            InputError::Io { error, .. } => Some(error),
+            InputError::Parse { .. } => None,
+        }
+    }
+}
+
+/// Where to read a puzzle input from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Source {
+    Path(PathBuf),
+    Stdin,
+    /// The checked in `inputs/dayNN.txt` for the given day.
+    Day(u8),
+}
+
+impl Source {
+    /// `-` for stdin, any other value as a path.
+    pub fn from_arg(arg: &str) -> Self {
+        if arg == "-" {
+            Source::Stdin
+        } else {
+            Source::Path(PathBuf::from(arg))
+        }
+    }
+
+    pub fn read(&self) -> Result<String, InputError> {
+        let result = match self {
+            Source::Path(path) => fs::read_to_string(path),
+            Source::Day(day) => fs::read_to_string(default_path(*day)),
+            Source::Stdin => {
+                let mut input = String::new();
+                io::stdin().read_to_string(&mut input).map(|_| input)
+            }
+        };
+
+        result.map_err(|error| InputError::Io {
+            source: self.to_string(),
+            error,
+        })
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Path(path) => write!(f, "{}", path.display()),
+            Source::Stdin => write!(f, "stdin"),
+            Source::Day(day) => write!(f, "{}", default_path(*day).display()),
+        }
+    }
+}
+
+/// The path of the checked in input for `day`.
+pub fn default_path(day: u8) -> PathBuf {
+    [
+        env!("CARGO_MANIFEST_DIR"),
+        "inputs",
+        &format!("day{:02}.txt", day),
+    ]
+    .iter()
+    .collect()
+}
+
+/// The 1-based line and column of the byte at `offset`.
+fn position(input: &str, offset: usize) -> (usize, usize) {
+    let before = &input[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+    (line, before[line_start..].chars().count() + 1)
+}
+
+fn parse_error(input: &str, offset: usize, kind: ParseErrorKind) -> InputError {
+    let (line, column) = position(input, offset);
+    InputError::Parse { line, column, kind }
+}
+
+/// Splits `input` on `separator`, yielding each trimmed field with its byte
+/// offset. Fields between adjacent separators are empty.
+fn fields(input: &str, separator: char) -> impl Iterator<Item = (usize, &str)> {
+    let mut offset = 0;
+    input.split(separator).map(move |field| {
+        let start = offset;
+        offset += field.len() + separator.len_utf8();
+
+        let trimmed = field.trim_start();
+        (start + field.len() - trimmed.len(), trimmed.trim_end())
+    })
+}
+
+/// Like `fields`, but skipping empty fields.
+fn tokens(input: &str, separator: char) -> impl Iterator<Item = (usize, &str)> {
+    fields(input, separator).filter(|(_, token)| !token.is_empty())
+}
+
+/// Parses every token, failing on empty ones or if there are none.
+fn parse_tokens<'a, T: FromStr>(
+    input: &str,
+    tokens: impl Iterator<Item = (usize, &'a str)>,
+) -> Result<Vec<T>, InputError> {
+    let values = tokens
+        .map(|(offset, token)| {
+            let kind = match token {
+                "" => ParseErrorKind::EmptyField,
+                _ => ParseErrorKind::InvalidNumber(token.to_string()),
+            };
+            token
+                .parse::<T>()
+                .map_err(|_| parse_error(input, offset, kind))
+        })
+        .collect::<Result<Vec<T>, _>>()?;
+
+    if values.is_empty() {
+        return Err(parse_error(input, 0, ParseErrorKind::Empty));
+    }
+
+    Ok(values)
+}
+
+/// Parses values separated by `separator`, ignoring whitespace around them.
+/// Every field must hold a value, so `1,,2` is an error.
+pub fn parse_separated<T: FromStr>(input: &str, separator: char) -> Result<Vec<T>, InputError> {
+    let start = input.len() - input.trim_start().len();
+    let trimmed = input.trim();
+    if trimmed.is_empty() {
+        return Err(parse_error(input, 0, ParseErrorKind::Empty));
+    }
+
+    let fields = fields(trimmed, separator).map(|(offset, field)| (start + offset, field));
+    parse_tokens(input, fields)
+}
+
+/// Parses a comma separated Intcode program.
+pub fn parse_intcode(input: &str) -> Result<Vec<i64>, InputError> {
+    parse_separated(input, ',')
+}
+
+/// Parses one value per line, skipping blank lines.
+pub fn parse_lines<T: FromStr>(input: &str) -> Result<Vec<T>, InputError> {
+    parse_tokens(input, tokens(input, '\n'))
+}
+
+/// Parses a string of decimal digits, ignoring surrounding whitespace.
+pub fn parse_digits(input: &str) -> Result<Vec<u8>, InputError> {
+    let trimmed = input.trim_start();
+    let start = input.len() - trimmed.len();
+
+    let digits = trimmed
+        .trim_end()
+        .char_indices()
+        .map(|(offset, c)| match c.to_digit(10) {
+            Some(digit) => Ok(digit as u8),
+            None => Err(parse_error(
+                input,
+                start + offset,
+                ParseErrorKind::InvalidDigit(c),
+            )),
+        })
+        .collect::<Result<Vec<u8>, _>>()?;
+
+    if digits.is_empty() {
+        return Err(parse_error(input, 0, ParseErrorKind::Empty));
+    }
+
+    Ok(digits)
+}
+
+/// A rectangular grid of characters.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Grid {
+    pub width: usize,
+    pub height: usize,
+    cells: Vec<char>,
+}
+
+impl Grid {
+    pub fn get(&self, x: usize, y: usize) -> Option<char> {
+        if x < self.width && y < self.height {
+            Some(self.cells[x + y * self.width])
+        } else {
+            None
+        }
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[char]> {
+        self.cells.chunks(self.width)
+    }
+}
+
+/// Parses a grid with one row per line, skipping blank lines. Every row must
+/// be the same width.
+pub fn parse_grid(input: &str) -> Result<Grid, InputError> {
+    let mut cells = vec![];
+    let mut width = None;
+    let mut height = 0;
+
+    for (offset, row) in tokens(input, '\n') {
+        let found = row.chars().count();
+        let expected = *width.get_or_insert(found);
+        if found != expected {
+            return Err(parse_error(
+                input,
+                offset,
+                ParseErrorKind::RaggedRow { expected, found },
+            ));
+        }
+
+        cells.extend(row.chars());
+        height += 1;
+    }
+
+    match width {
+        Some(width) => Ok(Grid {
+            width,
+            height,
+            cells,
+        }),
+        None => Err(parse_error(input, 0, ParseErrorKind::Empty)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_digits, parse_grid, parse_intcode, parse_lines, InputError, ParseErrorKind};
+
+    fn error_position(error: InputError) -> (usize, usize, ParseErrorKind) {
+        match error {
+            InputError::Parse { line, column, kind } => (line, column, kind),
+            error => panic!("unexpected error {}", error),
+        }
+    }
+
+    #[test]
+    fn test_parse_intcode() {
+        assert_eq!(parse_intcode("1,-2, 3,99\n").unwrap(), vec![1, -2, 3, 99]);
+        assert_eq!(
+            error_position(parse_intcode("1,2,\n3,x4,5").unwrap_err()),
+            (2, 3, ParseErrorKind::InvalidNumber("x4".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_separated_rejects_empty_fields() {
+        assert_eq!(
+            error_position(parse_intcode("1,,2").unwrap_err()),
+            (1, 3, ParseErrorKind::EmptyField)
+        );
+        assert_eq!(
+            error_position(parse_intcode("1,2,\n").unwrap_err()),
+            (1, 5, ParseErrorKind::EmptyField)
+        );
+        assert_eq!(
+            error_position(parse_intcode(" \n").unwrap_err()),
+            (1, 1, ParseErrorKind::Empty)
+        );
+    }
+
+    #[test]
+    fn test_parse_lines() {
+        assert_eq!(parse_lines::<i32>("12\n\n14\n").unwrap(), vec![12, 14]);
+        assert_eq!(
+            error_position(parse_lines::<i32>("12\n  1969a\n").unwrap_err()),
+            (2, 3, ParseErrorKind::InvalidNumber("1969a".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_digits() {
+        assert_eq!(parse_digits("0123\n").unwrap(), vec![0, 1, 2, 3]);
+        assert_eq!(
+            error_position(parse_digits("01a3").unwrap_err()),
+            (1, 3, ParseErrorKind::InvalidDigit('a'))
+        );
+    }
+
+    #[test]
+    fn test_parse_grid() {
+        let grid = parse_grid("#.#\n..#\n").unwrap();
+        assert_eq!((grid.width, grid.height), (3, 2));
+        assert_eq!(grid.get(2, 1), Some('#'));
+        assert_eq!(grid.get(3, 1), None);
+        assert_eq!(
+            error_position(parse_grid("#.#\n.#\n").unwrap_err()),
+            (
+                2,
+                1,
+                ParseErrorKind::RaggedRow {
+                    expected: 3,
+                    found: 2
+                }
+            )
+        );
+    }
+}
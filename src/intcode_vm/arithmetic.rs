@@ -0,0 +1,63 @@
+//! What `add` and `mul` do when the result doesn't fit in a word.
+
+/// How a VM handles `add` and `mul` results that overflow an `i64`. The
+/// policy is the same in debug and release builds.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Arithmetic {
+    /// Results wrap around in two's complement.
+    #[default]
+    Wrapping,
+    /// Overflow faults with `VmErrorKind::Overflow`.
+    Checked,
+    /// Results are clamped to `i64::MIN` or `i64::MAX`.
+    Saturating,
+}
+
+impl Arithmetic {
+    /// `a + b`, or `None` if it overflows under `Checked`.
+    #[inline]
+    pub fn add(self, a: i64, b: i64) -> Option<i64> {
+        match self {
+            Arithmetic::Wrapping => Some(a.wrapping_add(b)),
+            Arithmetic::Checked => a.checked_add(b),
+            Arithmetic::Saturating => Some(a.saturating_add(b)),
+        }
+    }
+
+    /// `a * b`, or `None` if it overflows under `Checked`.
+    #[inline]
+    pub fn mul(self, a: i64, b: i64) -> Option<i64> {
+        match self {
+            Arithmetic::Wrapping => Some(a.wrapping_mul(b)),
+            Arithmetic::Checked => a.checked_mul(b),
+            Arithmetic::Saturating => Some(a.saturating_mul(b)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Arithmetic;
+
+    #[test]
+    fn test_overflow_policies() {
+        assert_eq!(Arithmetic::Wrapping.add(i64::MAX, 1), Some(i64::MIN));
+        assert_eq!(Arithmetic::Checked.add(i64::MAX, 1), None);
+        assert_eq!(Arithmetic::Saturating.add(i64::MAX, 1), Some(i64::MAX));
+
+        assert_eq!(Arithmetic::Wrapping.mul(i64::MIN, -1), Some(i64::MIN));
+        assert_eq!(Arithmetic::Checked.mul(i64::MIN, -1), None);
+        assert_eq!(Arithmetic::Saturating.mul(i64::MIN, 2), Some(i64::MIN));
+
+        for arithmetic in [
+            Arithmetic::Wrapping,
+            Arithmetic::Checked,
+            Arithmetic::Saturating,
+        ]
+        .iter()
+        {
+            assert_eq!(arithmetic.add(2, 3), Some(5));
+            assert_eq!(arithmetic.mul(-4, 3), Some(-12));
+        }
+    }
+}
@@ -0,0 +1,312 @@
+//! Talking to Intcode programs that read and write ASCII text.
+//!
+//! Values from 0 to 127 are characters; anything else a program outputs is
+//! passed through as a number, which is how ASCII programs usually report
+//! their answer.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::mem;
+
+use super::io::OutputSink;
+use super::{IntcodeVM, VMState, VmError};
+
+const NEWLINE: i64 = b'\n' as i64;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AsciiOutput {
+    Line(String),
+    /// An output that isn't an ASCII character.
+    Value(i64),
+}
+
+/// Collects outputs into lines.
+#[derive(Clone, Debug, Default)]
+pub struct AsciiDecoder {
+    line: String,
+    outputs: Vec<AsciiOutput>,
+}
+
+impl AsciiDecoder {
+    pub fn new() -> Self {
+        AsciiDecoder::default()
+    }
+
+    pub fn push(&mut self, value: i64) {
+        match ascii_char(value) {
+            Some('\n') => {
+                let line = mem::take(&mut self.line);
+                self.outputs.push(AsciiOutput::Line(line));
+            }
+            Some(c) => self.line.push(c),
+            None => {
+                // Keeps the value after any text that came before it.
+                self.flush();
+                self.outputs.push(AsciiOutput::Value(value));
+            }
+        }
+    }
+
+    /// Ends the current line, if there is one, without waiting for a
+    /// newline. Programs often prompt for input like this.
+    pub fn flush(&mut self) {
+        if !self.line.is_empty() {
+            let line = mem::take(&mut self.line);
+            self.outputs.push(AsciiOutput::Line(line));
+        }
+    }
+
+    /// Takes every line and value decoded so far.
+    pub fn take(&mut self) -> Vec<AsciiOutput> {
+        mem::take(&mut self.outputs)
+    }
+}
+
+impl OutputSink for AsciiDecoder {
+    fn output(&mut self, value: i64) {
+        self.push(value)
+    }
+}
+
+fn ascii_char(value: i64) -> Option<char> {
+    if (0..=127).contains(&value) {
+        Some(value as u8 as char)
+    } else {
+        None
+    }
+}
+
+/// How `IntcodeVM::run_terminal` translates between the terminal and the
+/// program.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TerminalMode {
+    /// Input lines are sent as text and ASCII outputs printed as text.
+    Ascii,
+    /// Every input line is a number, and every output is printed as one.
+    Numeric,
+}
+
+#[derive(Debug)]
+pub enum TerminalError {
+    Io(io::Error),
+    Vm(VmError),
+}
+
+impl fmt::Display for TerminalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TerminalError::Io(error) => write!(f, "I/O error: {}", error),
+            TerminalError::Vm(error) => write!(f, "Intcode error: {}", error),
+        }
+    }
+}
+
+impl Error for TerminalError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TerminalError::Io(error) => Some(error),
+            TerminalError::Vm(error) => Some(error),
+        }
+    }
+}
+
+impl From<io::Error> for TerminalError {
+    fn from(error: io::Error) -> Self {
+        TerminalError::Io(error)
+    }
+}
+
+impl From<VmError> for TerminalError {
+    fn from(error: VmError) -> Self {
+        TerminalError::Vm(error)
+    }
+}
+
+fn write_output<W: Write>(output: &mut W, mode: TerminalMode, value: i64) -> io::Result<()> {
+    match (mode, ascii_char(value)) {
+        (TerminalMode::Ascii, Some(c)) => write!(output, "{}", c),
+        _ => writeln!(output, "{}", value),
+    }
+}
+
+impl IntcodeVM {
+    /// Queues the character codes of `line`, followed by a newline.
+    pub fn push_line(&mut self, line: &str) {
+        for c in line.chars() {
+            self.push_input(c as i64);
+        }
+        self.push_input(NEWLINE);
+    }
+
+    /// Runs the program until it halts, wants more input than it's been
+    /// given or runs out of fuel or time, and returns what it output.
+    /// Output after the last newline is returned as a line of its own.
+    pub fn run_ascii(&mut self) -> Result<(VMState, Vec<AsciiOutput>), VmError> {
+        let mut decoder = AsciiDecoder::new();
+        let state = self.run_with(&mut || None, &mut decoder)?;
+        decoder.flush();
+        Ok((state, decoder.take()))
+    }
+
+    /// Runs the program interactively, reading a line from `input` whenever
+    /// it wants input and writing its outputs to `output` as they happen.
+    /// Returns when the program halts, runs out of fuel or time, or `input`
+    /// runs out.
+    pub fn run_terminal<R: BufRead, W: Write>(
+        &mut self,
+        mode: TerminalMode,
+        mut input: R,
+        mut output: W,
+    ) -> Result<VMState, TerminalError> {
+        loop {
+            let mut written = Ok(());
+            let state = self.run_with(&mut || None, &mut |value| {
+                if written.is_ok() {
+                    written = write_output(&mut output, mode, value);
+                }
+            })?;
+            written?;
+            output.flush()?;
+
+            if state != VMState::NeedsInput {
+                return Ok(state);
+            }
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(state);
+            }
+            let line = line.trim_end_matches(&['\n', '\r'][..]);
+
+            match mode {
+                TerminalMode::Ascii => self.push_line(line),
+                TerminalMode::Numeric => match line.trim().parse() {
+                    Ok(value) => self.push_input(value),
+                    Err(_) => writeln!(output, "not a number: {}", line)?,
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AsciiDecoder, AsciiOutput, TerminalMode};
+    use crate::intcode_vm::asm::assemble;
+    use crate::{IntcodeVM, VMState};
+
+    /// Echoes a line back, then outputs 1000 and halts.
+    const ECHO: &str = "loop: in [c]
+                             out [c]
+                             eq [c], #10, [done]
+                             jz [done], #loop
+                             out #1000
+                             hlt
+                       c:    data 0
+                       done: data 0";
+
+    fn vm(source: &str) -> IntcodeVM {
+        let mut vm = IntcodeVM::new();
+        vm.set_program(assemble(source).unwrap());
+        vm
+    }
+
+    #[test]
+    fn test_run_ascii() {
+        let mut vm = vm(ECHO);
+        vm.push_line("hello");
+
+        assert_eq!(
+            vm.run_ascii().unwrap(),
+            (
+                VMState::Halted,
+                vec![
+                    AsciiOutput::Line("hello".to_string()),
+                    AsciiOutput::Value(1000),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn test_run_ascii_flushes_prompts() {
+        let mut vm = vm(ECHO);
+        for c in "partial".chars() {
+            vm.push_input(c as i64);
+        }
+
+        assert_eq!(
+            vm.run_ascii().unwrap(),
+            (
+                VMState::NeedsInput,
+                vec![AsciiOutput::Line("partial".to_string())]
+            )
+        );
+    }
+
+    #[test]
+    fn test_values_keep_their_place_in_the_text() {
+        let mut decoder = AsciiDecoder::new();
+        for value in [97, 98, 1000, 10, 99].iter() {
+            decoder.push(*value);
+        }
+        decoder.flush();
+
+        assert_eq!(
+            decoder.take(),
+            vec![
+                AsciiOutput::Line("ab".to_string()),
+                AsciiOutput::Value(1000),
+                AsciiOutput::Line("".to_string()),
+                AsciiOutput::Line("c".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_ascii_interleaves_text_and_values() {
+        let mut vm = vm("out #97\nout #1000\nout #98\nhlt");
+        assert_eq!(
+            vm.run_ascii().unwrap(),
+            (
+                VMState::Halted,
+                vec![
+                    AsciiOutput::Line("a".to_string()),
+                    AsciiOutput::Value(1000),
+                    AsciiOutput::Line("b".to_string()),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn test_ascii_terminal() {
+        let mut vm = vm(ECHO);
+        let mut output = vec![];
+
+        let state = vm.run_terminal(TerminalMode::Ascii, &b"hi there\r\n"[..], &mut output);
+        assert_eq!(state.unwrap(), VMState::Halted);
+        assert_eq!(String::from_utf8(output).unwrap(), "hi there\n1000\n");
+    }
+
+    #[test]
+    fn test_numeric_terminal() {
+        let mut vm = vm("in [x]\nmul [x], #2, [x]\nout [x]\nhlt\nx: data 0");
+        let mut output = vec![];
+
+        let state = vm.run_terminal(TerminalMode::Numeric, &b"abc\n21\n"[..], &mut output);
+        assert_eq!(state.unwrap(), VMState::Halted);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "not a number: abc\n42\n"
+        );
+    }
+
+    #[test]
+    fn test_terminal_stops_when_input_runs_out() {
+        let mut vm = vm("in [x]\nhlt\nx: data 0");
+        let state = vm.run_terminal(TerminalMode::Numeric, &b""[..], vec![]);
+        assert_eq!(state.unwrap(), VMState::NeedsInput);
+    }
+}
@@ -0,0 +1,452 @@
+//! A small assembler for Intcode.
+//!
+//! Each line holds at most one instruction or directive, optionally preceded by
+//! one or more `label:` definitions. Everything after a `;` is a comment.
+//!
+//! ```text
+//! start:  in   [value]
+//!         jz   [value], #done
+//!         mul  [value], #2, rb+0
+//!         out  rb+0
+//! done:   hlt
+//! value:  data 0
+//! ```
+//!
+//! Operands are written as `#expr` (immediate), `[expr]` (position) or
+//! `rb+expr` / `rb-expr` / `rb` (relative). An expression is a sum of integer
+//! literals and labels, e.g. `#loop`, `[table+3]` or `rb-1`.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use super::ParamMode;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AsmErrorKind {
+    UnknownMnemonic(String),
+    WrongOperandCount {
+        mnemonic: String,
+        expected: usize,
+        found: usize,
+    },
+    InvalidOperand(String),
+    InvalidExpression(String),
+    Overflow,
+    ImmediateDestination,
+    DuplicateLabel(String),
+    UndefinedLabel(String),
+}
+
+impl fmt::Display for AsmErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AsmErrorKind::UnknownMnemonic(mnemonic) => write!(f, "unknown mnemonic `{}`", mnemonic),
+            AsmErrorKind::WrongOperandCount {
+                mnemonic,
+                expected,
+                found,
+            } => write!(
+                f,
+                "`{}` takes {} operand(s) but {} were given",
+                mnemonic, expected, found
+            ),
+            AsmErrorKind::InvalidOperand(operand) => write!(
+                f,
+                "invalid operand `{}`, expected `#expr`, `[expr]` or `rb+expr`",
+                operand
+            ),
+            AsmErrorKind::InvalidExpression(expr) => write!(f, "invalid expression `{}`", expr),
+            AsmErrorKind::Overflow => write!(f, "the expression doesn't fit in an i64"),
+            AsmErrorKind::ImmediateDestination => {
+                write!(f, "the destination operand can't be immediate")
+            }
+            AsmErrorKind::DuplicateLabel(label) => {
+                write!(f, "label `{}` is already defined", label)
+            }
+            AsmErrorKind::UndefinedLabel(label) => write!(f, "label `{}` is not defined", label),
+        }
+    }
+}
+
+/// An assembly error and the 1-based source line it was found on.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub kind: AsmErrorKind,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl Error for AsmError {}
+
+/// Mnemonic, opcode number and operand count for every instruction, plus which
+/// operand (if any) is written to.
+const INSTRUCTIONS: [(&str, i64, usize, Option<usize>); 10] = [
+    ("add", 1, 3, Some(2)),
+    ("mul", 2, 3, Some(2)),
+    ("in", 3, 1, Some(0)),
+    ("out", 4, 1, None),
+    ("jnz", 5, 2, None),
+    ("jz", 6, 2, None),
+    ("lt", 7, 3, Some(2)),
+    ("eq", 8, 3, Some(2)),
+    ("arb", 9, 1, None),
+    ("hlt", 99, 0, None),
+];
+
+#[derive(Clone, Debug)]
+enum Term {
+    Number(i64),
+    Label(String),
+}
+
+#[derive(Clone, Debug)]
+struct Expr {
+    terms: Vec<(i64, Term)>,
+}
+
+impl Expr {
+    fn parse(text: &str) -> Result<Self, AsmErrorKind> {
+        let invalid = || AsmErrorKind::InvalidExpression(text.to_string());
+
+        let mut terms = vec![];
+        let mut sign = 1;
+        let mut rest = text.trim();
+        if rest.is_empty() {
+            return Err(invalid());
+        }
+
+        loop {
+            if let Some(stripped) = rest.strip_prefix('-') {
+                sign = -sign;
+                rest = stripped.trim_start();
+                continue;
+            }
+
+            let end = rest.find(['+', '-']).unwrap_or(rest.len());
+            let token = rest[..end].trim();
+            if token.starts_with(|c: char| c.is_ascii_digit()) {
+                // Parsed with its sign, so `-9223372036854775808` fits.
+                let digits = match sign {
+                    1 => token.to_string(),
+                    _ => format!("-{}", token),
+                };
+                let value = digits.parse::<i64>().map_err(|_| invalid())?;
+                terms.push((1, Term::Number(value)));
+            } else if is_identifier(token) {
+                terms.push((sign, Term::Label(token.to_string())));
+            } else {
+                return Err(invalid());
+            }
+
+            rest = rest[end..].trim_start();
+            match rest.chars().next() {
+                None => break,
+                Some('+') => sign = 1,
+                Some(_) => sign = -1,
+            }
+            rest = rest[1..].trim_start();
+        }
+
+        Ok(Expr { terms })
+    }
+
+    fn evaluate(&self, labels: &HashMap<String, i64>) -> Result<i64, AsmErrorKind> {
+        self.terms.iter().try_fold(0i64, |acc, (sign, term)| {
+            let value = match term {
+                Term::Number(value) => *value,
+                Term::Label(label) => *labels
+                    .get(label)
+                    .ok_or_else(|| AsmErrorKind::UndefinedLabel(label.clone()))?,
+            };
+
+            let value = match sign {
+                1 => Some(value),
+                _ => value.checked_neg(),
+            };
+            value
+                .and_then(|value| acc.checked_add(value))
+                .ok_or(AsmErrorKind::Overflow)
+        })
+    }
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_') && text != "rb"
+}
+
+#[derive(Clone, Debug)]
+struct Operand {
+    mode: ParamMode,
+    value: Expr,
+}
+
+impl Operand {
+    fn parse(text: &str) -> Result<Self, AsmErrorKind> {
+        let text = text.trim();
+        let invalid = || AsmErrorKind::InvalidOperand(text.to_string());
+
+        let (mode, value) = if let Some(value) = text.strip_prefix('#') {
+            (ParamMode::Immediate, Expr::parse(value)?)
+        } else if let Some(value) = text.strip_prefix('[') {
+            let value = value.strip_suffix(']').ok_or_else(invalid)?;
+            (ParamMode::Position, Expr::parse(value)?)
+        } else if let Some(offset) = text.strip_prefix("rb") {
+            let offset = offset.trim_start();
+            let value = if offset.is_empty() {
+                Expr {
+                    terms: vec![(1, Term::Number(0))],
+                }
+            } else if offset.starts_with(['+', '-']) {
+                Expr::parse(&format!("0{}", offset))?
+            } else {
+                return Err(invalid());
+            };
+            (ParamMode::Relative, value)
+        } else {
+            return Err(invalid());
+        };
+
+        Ok(Operand { mode, value })
+    }
+
+    fn mode_digit(&self) -> i64 {
+        match self.mode {
+            ParamMode::Position => 0,
+            ParamMode::Immediate => 1,
+            ParamMode::Relative => 2,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Statement {
+    Instruction { opcode: i64, operands: Vec<Operand> },
+    Data(Vec<Expr>),
+}
+
+impl Statement {
+    fn len(&self) -> usize {
+        match self {
+            Statement::Instruction { operands, .. } => operands.len() + 1,
+            Statement::Data(values) => values.len(),
+        }
+    }
+}
+
+fn split_operands(text: &str) -> Vec<&str> {
+    if text.trim().is_empty() {
+        vec![]
+    } else {
+        text.split(',').map(str::trim).collect()
+    }
+}
+
+fn parse_statement(text: &str) -> Result<Statement, AsmErrorKind> {
+    let (mnemonic, rest) = match text.find(char::is_whitespace) {
+        Some(index) => text.split_at(index),
+        None => (text, ""),
+    };
+    let mnemonic = mnemonic.to_ascii_lowercase();
+    let operands = split_operands(rest);
+
+    if mnemonic == "data" {
+        return operands
+            .into_iter()
+            .map(Expr::parse)
+            .collect::<Result<Vec<_>, _>>()
+            .map(Statement::Data);
+    }
+
+    let (_, opcode, arity, destination) = INSTRUCTIONS
+        .iter()
+        .find(|(name, ..)| *name == mnemonic)
+        .ok_or_else(|| AsmErrorKind::UnknownMnemonic(mnemonic.clone()))?;
+
+    if operands.len() != *arity {
+        return Err(AsmErrorKind::WrongOperandCount {
+            mnemonic,
+            expected: *arity,
+            found: operands.len(),
+        });
+    }
+
+    let operands = operands
+        .into_iter()
+        .map(Operand::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if let Some(destination) = destination {
+        if operands[*destination].mode == ParamMode::Immediate {
+            return Err(AsmErrorKind::ImmediateDestination);
+        }
+    }
+
+    Ok(Statement::Instruction {
+        opcode: *opcode,
+        operands,
+    })
+}
+
+/// Assembles `source` into a program ready for `IntcodeVM::set_program`.
+pub fn assemble(source: &str) -> Result<Vec<i64>, AsmError> {
+    let mut labels = HashMap::new();
+    let mut statements = vec![];
+    let mut address = 0;
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |kind| AsmError {
+            line: line_number,
+            kind,
+        };
+
+        let mut text = line.split(';').next().unwrap_or("").trim();
+
+        while let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+            if !is_identifier(label) {
+                return Err(error(AsmErrorKind::InvalidExpression(label.to_string())));
+            }
+            if labels.insert(label.to_string(), address as i64).is_some() {
+                return Err(error(AsmErrorKind::DuplicateLabel(label.to_string())));
+            }
+            text = text[colon + 1..].trim();
+        }
+
+        if text.is_empty() {
+            continue;
+        }
+
+        let statement = parse_statement(text).map_err(error)?;
+        address += statement.len();
+        statements.push((line_number, statement));
+    }
+
+    let mut program = Vec::with_capacity(address);
+    for (line, statement) in statements {
+        let error = |kind| AsmError { line, kind };
+
+        match statement {
+            Statement::Instruction { opcode, operands } => {
+                let modes = operands
+                    .iter()
+                    .rev()
+                    .fold(0, |acc, operand| acc * 10 + operand.mode_digit());
+                program.push(modes * 100 + opcode);
+
+                for operand in operands {
+                    program.push(operand.value.evaluate(&labels).map_err(error)?);
+                }
+            }
+            Statement::Data(values) => {
+                for value in values {
+                    program.push(value.evaluate(&labels).map_err(error)?);
+                }
+            }
+        }
+    }
+
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assemble, AsmError, AsmErrorKind};
+    use crate::IntcodeVM;
+
+    #[test]
+    fn test_assemble_encodes_param_modes() {
+        let program = assemble("add #5, [6], rb+7\nhlt").unwrap();
+        assert_eq!(program, vec![20101, 5, 6, 7, 99]);
+    }
+
+    #[test]
+    fn test_assemble_resolves_labels() {
+        let source = "
+            start: in [value]      ; read a number
+                   jz [value], #done
+                   mul [value], #2, [value]
+                   out [value]
+                   jz #0, #start
+            done:  hlt
+            value: data 0
+        ";
+        let program = assemble(source).unwrap();
+
+        let mut vm = IntcodeVM::new();
+        vm.set_program(program);
+        vm.push_input(21);
+        vm.push_input(0);
+        assert_eq!(vm.run_once().unwrap(), vec![42]);
+    }
+
+    #[test]
+    fn test_assemble_relative_offsets() {
+        let program = assemble("arb #base\nout rb-1\nhlt\ndata 7\nbase: data 0").unwrap();
+
+        let mut vm = IntcodeVM::new();
+        vm.set_program(program);
+        assert_eq!(vm.run_once().unwrap(), vec![7]);
+    }
+
+    #[test]
+    fn test_assemble_rejects_immediate_destination() {
+        assert_eq!(
+            assemble("\nadd #1, #2, #3"),
+            Err(AsmError {
+                line: 2,
+                kind: AsmErrorKind::ImmediateDestination
+            })
+        );
+    }
+
+    #[test]
+    fn test_assemble_rejects_undefined_label() {
+        assert_eq!(
+            assemble("out [nowhere]").unwrap_err().kind,
+            AsmErrorKind::UndefinedLabel("nowhere".to_string())
+        );
+    }
+
+    #[test]
+    fn test_assemble_checks_for_overflow() {
+        assert_eq!(
+            assemble("data 9223372036854775807+1").unwrap_err().kind,
+            AsmErrorKind::Overflow
+        );
+        assert_eq!(
+            assemble("big: data 0\ndata -big-9223372036854775808-1")
+                .unwrap_err()
+                .kind,
+            AsmErrorKind::Overflow
+        );
+        assert_eq!(
+            assemble("data 9223372036854775808").unwrap_err().kind,
+            AsmErrorKind::InvalidExpression("9223372036854775808".to_string())
+        );
+    }
+
+    #[test]
+    fn test_assemble_every_i64() {
+        assert_eq!(
+            assemble("data -9223372036854775808, 9223372036854775807, --3").unwrap(),
+            vec![i64::MIN, i64::MAX, 3]
+        );
+        assert_eq!(
+            assemble("out rb-9223372036854775808\nout #-9223372036854775808").unwrap(),
+            vec![204, i64::MIN, 104, i64::MIN]
+        );
+    }
+}
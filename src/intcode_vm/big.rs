@@ -0,0 +1,378 @@
+//! A VM whose words are `BigInt`s, for checking programs whose values don't
+//! fit in an `i64`.
+//!
+//! It is a plain interpreter with none of `IntcodeVM`'s caching, and is
+//! much slower. It decodes instructions, resolves parameters and reports
+//! faults the same way, except that arithmetic is exact. Memory grows
+//! densely past the program and keeps writes far beyond it sparsely, like
+//! `PagedMemory`, and can be given a limit, like `BoundedMemory`.
+
+use std::collections::{HashMap, VecDeque};
+
+use super::memory::MemoryError;
+use super::{Opcode, ParamMode, VmError, VmErrorKind};
+use crate::bigint::BigInt;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BigVMState {
+    Halted,
+    NeedsInput,
+    Output(BigInt),
+}
+
+/// How far past the end of dense memory a write can be and still grow it.
+const MAX_GROWTH: usize = 1 << 16;
+
+#[derive(Clone, Debug, Default)]
+pub struct BigIntcodeVM {
+    memory: Vec<BigInt>,
+    /// Words written too far past the end of `memory` to grow it.
+    far: HashMap<usize, BigInt>,
+    len: usize,
+    memory_limit: Option<usize>,
+    instruction_pointer: usize,
+    relative_pointer: BigInt,
+    inputs: VecDeque<BigInt>,
+}
+
+impl BigIntcodeVM {
+    pub fn new() -> Self {
+        BigIntcodeVM::default()
+    }
+
+    pub fn set_program(&mut self, program: Vec<BigInt>) {
+        self.len = program.len();
+        self.memory = program;
+        self.far.clear();
+    }
+
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.memory_limit
+    }
+
+    /// Makes writes at or past `limit` fault with `VmErrorKind::MemoryLimit`.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.memory_limit = limit;
+    }
+
+    pub fn push_input<T: Into<BigInt>>(&mut self, value: T) {
+        self.inputs.push_back(value.into());
+    }
+
+    pub fn instruction_pointer(&self) -> usize {
+        self.instruction_pointer
+    }
+
+    /// Reads `address`; memory past the end of the program reads as 0.
+    pub fn read_memory(&self, address: usize) -> BigInt {
+        self.word(address).cloned().unwrap_or_default()
+    }
+
+    fn word(&self, address: usize) -> Option<&BigInt> {
+        self.memory.get(address).or_else(|| self.far.get(&address))
+    }
+
+    fn write_memory(
+        &mut self,
+        opcode: Opcode,
+        address: usize,
+        value: BigInt,
+    ) -> Result<(), VmError> {
+        if let Some(limit) = self.memory_limit {
+            if address >= limit {
+                let error = MemoryError { address, limit };
+                return Err(self.fault(opcode, VmErrorKind::MemoryLimit(error)));
+            }
+        }
+
+        let end = self.memory.len();
+        if address < end {
+            self.memory[address] = value;
+        } else if address - end < MAX_GROWTH {
+            self.memory.resize(address + 1, BigInt::zero());
+            if !self.far.is_empty() {
+                for address in end..address {
+                    if let Some(value) = self.far.remove(&address) {
+                        self.memory[address] = value;
+                    }
+                }
+                self.far.remove(&address);
+            }
+            self.memory[address] = value;
+        } else {
+            self.far.insert(address, value);
+        }
+        self.len = self.len.max(address + 1);
+        Ok(())
+    }
+
+    /// Faults at the instruction pointer. The error's word is 0 if the word
+    /// there doesn't fit in an `i64`.
+    fn fault(&self, opcode: Opcode, kind: VmErrorKind) -> VmError {
+        VmError {
+            instruction_pointer: self.instruction_pointer,
+            word: self
+                .read_memory(self.instruction_pointer)
+                .to_i64()
+                .unwrap_or(0),
+            opcode,
+            kind,
+        }
+    }
+
+    fn address(&self, opcode: Opcode, value: &BigInt) -> Result<usize, VmError> {
+        if value.is_negative() {
+            let address = value.to_i64().unwrap_or(i64::MIN);
+            return Err(self.fault(opcode, VmErrorKind::NegativeAddress(address)));
+        }
+        match value.to_i64() {
+            Some(address) => Ok(address as usize),
+            None => Err(self.fault(opcode, VmErrorKind::AddressTooLarge)),
+        }
+    }
+
+    /// Resolves the value of the `index`th (1-based) parameter.
+    fn param(&self, opcode: Opcode, mode: ParamMode, index: usize) -> Result<BigInt, VmError> {
+        let raw = self.read_memory(self.instruction_pointer + index);
+        match mode {
+            ParamMode::Immediate => Ok(raw),
+            ParamMode::Position => Ok(self.read_memory(self.address(opcode, &raw)?)),
+            ParamMode::Relative => {
+                let address = self.address(opcode, &(&self.relative_pointer + &raw))?;
+                Ok(self.read_memory(address))
+            }
+        }
+    }
+
+    /// Resolves the address the `index`th (1-based) parameter writes to.
+    fn param_address(
+        &self,
+        opcode: Opcode,
+        mode: ParamMode,
+        index: usize,
+    ) -> Result<usize, VmError> {
+        let raw = self.read_memory(self.instruction_pointer + index);
+        match mode {
+            ParamMode::Immediate => Err(self.fault(opcode, VmErrorKind::ImmediateWrite)),
+            ParamMode::Position => self.address(opcode, &raw),
+            ParamMode::Relative => self.address(opcode, &(&self.relative_pointer + &raw)),
+        }
+    }
+
+    /// Runs until the program halts, outputs a value or needs input.
+    pub fn execute(&mut self) -> Result<BigVMState, VmError> {
+        loop {
+            if self.instruction_pointer >= self.len {
+                return Err(self.fault(Opcode::Invalid, VmErrorKind::InstructionPointerOutOfBounds));
+            }
+
+            let word = match self.word(self.instruction_pointer) {
+                Some(word) => word.rem_small(100_000),
+                None => 0,
+            };
+            let opcode = match Opcode::decode(word) {
+                Ok(opcode) => opcode,
+                Err(kind) => return Err(self.fault(Opcode::Invalid, kind)),
+            };
+            let mut next = self.instruction_pointer + opcode.size();
+
+            match opcode {
+                Opcode::Add(in1, in2, out)
+                | Opcode::Mul(in1, in2, out)
+                | Opcode::LessThen(in1, in2, out)
+                | Opcode::Equals(in1, in2, out) => {
+                    let a = self.param(opcode, in1, 1)?;
+                    let b = self.param(opcode, in2, 2)?;
+                    let target = self.param_address(opcode, out, 3)?;
+                    let result = match opcode {
+                        Opcode::Add(..) => &a + &b,
+                        Opcode::Mul(..) => &a * &b,
+                        Opcode::LessThen(..) => BigInt::from((a < b) as i64),
+                        _ => BigInt::from((a == b) as i64),
+                    };
+                    self.write_memory(opcode, target, result)?;
+                }
+                Opcode::Input(out) => {
+                    if self.inputs.is_empty() {
+                        return Ok(BigVMState::NeedsInput);
+                    }
+                    let target = self.param_address(opcode, out, 1)?;
+                    let value = self.inputs.pop_front().unwrap();
+                    self.write_memory(opcode, target, value)?;
+                }
+                Opcode::Output(in1) => {
+                    let value = self.param(opcode, in1, 1)?;
+                    self.instruction_pointer = next;
+                    return Ok(BigVMState::Output(value));
+                }
+                Opcode::JmpIfZero(in1, in2) | Opcode::JmpIfNotZero(in1, in2) => {
+                    let condition = self.param(opcode, in1, 1)?;
+                    let target = self.param(opcode, in2, 2)?;
+                    if condition.is_zero() == matches!(opcode, Opcode::JmpIfZero(..)) {
+                        // Targets that aren't addresses run off the end of
+                        // memory, as they do in `IntcodeVM`.
+                        next = match target.to_i64() {
+                            Some(target) if target >= 0 => target as usize,
+                            _ => usize::MAX,
+                        };
+                    }
+                }
+                Opcode::SetRB(in1) => {
+                    // The base may go negative; only the addresses
+                    // relative parameters resolve to are checked.
+                    let offset = self.param(opcode, in1, 1)?;
+                    self.relative_pointer = &self.relative_pointer + &offset;
+                }
+                Opcode::Halt => return Ok(BigVMState::Halted),
+                Opcode::Invalid => unreachable!("rejected by Opcode::decode"),
+            }
+
+            self.instruction_pointer = next;
+        }
+    }
+
+    /// Runs the program to completion and returns everything it output.
+    pub fn run_once(&mut self) -> Result<Vec<BigInt>, VmError> {
+        let mut output = vec![];
+
+        loop {
+            match self.execute()? {
+                BigVMState::Halted => break,
+                BigVMState::NeedsInput => {
+                    return Err(self.fault(Opcode::Invalid, VmErrorKind::NotEnoughInput))
+                }
+                BigVMState::Output(value) => output.push(value),
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BigIntcodeVM;
+    use crate::bigint::BigInt;
+    use crate::input;
+    use crate::intcode_vm::memory::MemoryError;
+    use crate::intcode_vm::{Arithmetic, VmErrorKind};
+    use crate::IntcodeVM;
+
+    fn run(program: &str, inputs: &[i64]) -> Vec<String> {
+        let mut vm = BigIntcodeVM::new();
+        vm.set_program(input::parse_separated::<BigInt>(program, ',').unwrap());
+        for input in inputs {
+            vm.push_input(*input);
+        }
+        vm.run_once()
+            .unwrap()
+            .iter()
+            .map(BigInt::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_day09_examples() {
+        let quine = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+        assert_eq!(run(quine, &[]).join(","), quine);
+        assert_eq!(
+            run("1102,34915192,34915192,7,4,7,99,0", &[]),
+            vec!["1219070632396864"]
+        );
+        assert_eq!(
+            run("104,1125899906842624,99", &[]),
+            vec!["1125899906842624"]
+        );
+        // The relative base can go negative.
+        assert_eq!(run("109,-10,204,12,99", &[]), vec!["204"]);
+    }
+
+    #[test]
+    fn test_agrees_with_intcode_vm_on_boost() {
+        let source = include_str!("../../inputs/day09.txt");
+        let mut vm = IntcodeVM::new();
+        vm.set_program(input::parse_intcode(source).unwrap());
+        vm.push_input(1);
+        let expected = vm.run_once().unwrap();
+
+        let expected = expected.iter().map(i64::to_string).collect::<Vec<_>>();
+        assert_eq!(run(source, &[1]), expected);
+    }
+
+    #[test]
+    fn test_values_past_i64_are_exact() {
+        // mul [11], #1, [12]; mul [12], [12], [12]; out [12]; hlt; 2^62 + 1
+        let program = "1002,11,1,12,2,12,12,12,4,12,99,4611686018427387905,0";
+        let mut vm = IntcodeVM::new();
+        vm.set_arithmetic(Arithmetic::Checked);
+        vm.set_program(input::parse_intcode(program).unwrap());
+        assert_eq!(
+            vm.run_once().unwrap_err().kind,
+            VmErrorKind::Overflow(4611686018427387905, 4611686018427387905)
+        );
+
+        assert_eq!(
+            run(program, &[]),
+            vec!["21267647932558653975684285001340289025"]
+        );
+    }
+
+    #[test]
+    fn test_faults_match_intcode_vm() {
+        let programs = [
+            "1101,0,0,-5,99",
+            "4,-1,99",
+            "42",
+            "3,0,99",
+            "109,-10,204,9,99",
+        ];
+        for program in programs.iter() {
+            let mut vm = IntcodeVM::new();
+            vm.set_program(input::parse_intcode(program).unwrap());
+            let expected = vm.run_once().unwrap_err();
+
+            let mut big = BigIntcodeVM::new();
+            big.set_program(input::parse_separated(program, ',').unwrap());
+            let error = big.run_once().unwrap_err();
+            assert_eq!(
+                (error.kind, error.instruction_pointer, error.word),
+                (expected.kind, expected.instruction_pointer, expected.word),
+                "{}",
+                program
+            );
+        }
+
+        let mut big = BigIntcodeVM::new();
+        big.set_program(input::parse_separated("4,99999999999999999999,99", ',').unwrap());
+        assert_eq!(
+            big.run_once().unwrap_err().kind,
+            VmErrorKind::AddressTooLarge
+        );
+    }
+
+    #[test]
+    fn test_far_writes_and_memory_limit() {
+        // add #7, #0, [FAR]; out [FAR]; hlt
+        let program = "1101,7,0,1000000000000,4,1000000000000,99";
+        assert_eq!(run(program, &[]), vec!["7"]);
+
+        let mut big = BigIntcodeVM::new();
+        big.set_program(input::parse_separated(program, ',').unwrap());
+        big.set_memory_limit(Some(50));
+        let error = big.run_once().unwrap_err();
+        assert_eq!(error.instruction_pointer, 0);
+        assert_eq!(
+            error.kind,
+            VmErrorKind::MemoryLimit(MemoryError {
+                address: 1_000_000_000_000,
+                limit: 50
+            })
+        );
+
+        // Growing dense memory picks up words written far past it.
+        // in [100000]; add #0, #0, [60000]; add #0, #0, [110000];
+        // out [100000]; hlt
+        let program = "3,100000,1101,0,0,60000,1101,0,0,110000,4,100000,99";
+        assert_eq!(run(program, &[42]), vec!["42"]);
+    }
+}
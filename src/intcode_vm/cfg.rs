@@ -0,0 +1,373 @@
+//! Recovering a program's control-flow graph without running it.
+//!
+//! The analysis follows every path from address 0, decoding instructions
+//! with `Instruction::decode`. Jumps to immediate targets are followed, and
+//! jumps with immediate conditions only go the way the condition sends
+//! them. Jumps whose target is read from memory can't be followed, so they
+//! are recorded as unresolved. Words no path reaches are data.
+//!
+//! Programs that modify their own code can take paths this doesn't see.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+use super::disasm::Instruction;
+use super::{Opcode, ParamMode};
+
+/// How control leaves a basic block.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Exit {
+    /// Carries on into the instruction at this address, which starts a
+    /// block of its own.
+    Next(usize),
+    /// A jump to a known address, which carries on to `next` when it isn't
+    /// taken, or always jumps if `next` is `None`.
+    Branch { target: usize, next: Option<usize> },
+    /// A jump whose target is read from memory. Carries on to `next` when
+    /// it isn't taken.
+    Indirect { next: Option<usize> },
+    /// Halts, or always jumps to a negative address, which faults.
+    Halt,
+}
+
+impl Exit {
+    /// The addresses control can go to, as far as the analysis knows.
+    pub fn successors(&self) -> Vec<usize> {
+        match *self {
+            Exit::Next(next) => vec![next],
+            Exit::Branch { target, next } => std::iter::once(target).chain(next).collect(),
+            Exit::Indirect { next } => next.into_iter().collect(),
+            Exit::Halt => vec![],
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BasicBlock {
+    pub start: usize,
+    /// One past the block's last word.
+    pub end: usize,
+    pub instructions: Vec<Instruction>,
+    pub exit: Exit,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Cfg {
+    blocks: BTreeMap<usize, BasicBlock>,
+    /// Whether each word of the program belongs to a reachable instruction.
+    code: Vec<bool>,
+    /// Addresses that control reaches but that don't hold a complete
+    /// instruction.
+    invalid: BTreeSet<usize>,
+}
+
+/// Where a single instruction can send control, ignoring block boundaries.
+fn exit_of(instruction: &Instruction) -> Option<Exit> {
+    let next = instruction.next_address();
+    let (taken_if_zero, condition, target) = match instruction.opcode {
+        Opcode::Halt => return Some(Exit::Halt),
+        Opcode::JmpIfZero(condition, target) => (true, condition, target),
+        Opcode::JmpIfNotZero(condition, target) => (false, condition, target),
+        _ => return None,
+    };
+    let (condition_word, target_word) = (instruction.words[1], instruction.words[2]);
+
+    // An immediate condition decides the jump once and for all.
+    let (taken, next) = match condition {
+        ParamMode::Immediate if (condition_word == 0) == taken_if_zero => (true, None),
+        ParamMode::Immediate => (false, Some(next)),
+        _ => (true, Some(next)),
+    };
+
+    Some(match (taken, target) {
+        (false, _) => Exit::Next(next.unwrap()),
+        // A negative target always faults, so it goes nowhere.
+        (true, ParamMode::Immediate) if target_word < 0 => match next {
+            Some(next) => Exit::Next(next),
+            None => Exit::Halt,
+        },
+        (true, ParamMode::Immediate) => Exit::Branch {
+            target: target_word as usize,
+            next,
+        },
+        (true, _) => Exit::Indirect { next },
+    })
+}
+
+impl Cfg {
+    /// Analyses `program`, starting from address 0.
+    pub fn build(program: &[i64]) -> Self {
+        Cfg::build_from(program, &[0])
+    }
+
+    /// Analyses `program`, starting from each of `entries`. This finds code
+    /// that is only reached through indirect jumps, such as the return
+    /// addresses of calls, once those are known.
+    pub fn build_from(program: &[i64], entries: &[usize]) -> Self {
+        let mut cfg = Cfg {
+            code: vec![false; program.len()],
+            ..Cfg::default()
+        };
+
+        // Find every reachable instruction, and the addresses that start
+        // a block: the entries, jump targets and whatever follows a jump.
+        let mut instructions = BTreeMap::new();
+        let mut leaders = entries.iter().copied().collect::<BTreeSet<_>>();
+        let mut pending = entries.to_vec();
+        while let Some(address) = pending.pop() {
+            if instructions.contains_key(&address) || cfg.invalid.contains(&address) {
+                continue;
+            }
+            let instruction = match Instruction::decode(program, address) {
+                Some(instruction) if !instruction.is_data() => instruction,
+                _ => {
+                    cfg.invalid.insert(address);
+                    continue;
+                }
+            };
+
+            for word in &mut cfg.code[address..instruction.next_address()] {
+                *word = true;
+            }
+            match exit_of(&instruction) {
+                Some(exit) => {
+                    let successors = exit.successors();
+                    leaders.extend(successors.iter().copied());
+                    pending.extend(successors);
+                }
+                None => pending.push(instruction.next_address()),
+            }
+            instructions.insert(address, instruction);
+        }
+
+        // Cut the instructions into blocks at the leaders.
+        for &start in &leaders {
+            let mut block = vec![];
+            let mut address = start;
+            let exit = loop {
+                let instruction = match instructions.get(&address) {
+                    Some(instruction) => instruction.clone(),
+                    None if block.is_empty() => break None,
+                    // Runs into something that isn't an instruction.
+                    None => break Some(Exit::Next(address)),
+                };
+                let next = instruction.next_address();
+                let exit = exit_of(&instruction);
+                block.push(instruction);
+
+                match exit {
+                    Some(exit) => break Some(exit),
+                    None if leaders.contains(&next) => break Some(Exit::Next(next)),
+                    None => address = next,
+                }
+            };
+
+            if let Some(exit) = exit {
+                cfg.blocks.insert(
+                    start,
+                    BasicBlock {
+                        start,
+                        end: block.last().unwrap().next_address(),
+                        instructions: block,
+                        exit,
+                    },
+                );
+            }
+        }
+
+        cfg
+    }
+
+    /// The blocks, in address order.
+    pub fn blocks(&self) -> impl Iterator<Item = &BasicBlock> {
+        self.blocks.values()
+    }
+
+    pub fn block(&self, start: usize) -> Option<&BasicBlock> {
+        self.blocks.get(&start)
+    }
+
+    pub fn is_code(&self, address: usize) -> bool {
+        self.code.get(address) == Some(&true)
+    }
+
+    /// Runs of words that no path reaches.
+    pub fn data(&self) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = vec![];
+        for (address, &code) in self.code.iter().enumerate() {
+            if code {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some(range) if range.end == address => range.end += 1,
+                _ => ranges.push(address..address + 1),
+            }
+        }
+        ranges
+    }
+
+    /// The addresses of jumps whose targets are read from memory.
+    pub fn unresolved(&self) -> Vec<usize> {
+        self.blocks()
+            .filter(|block| matches!(block.exit, Exit::Indirect { .. }))
+            .map(|block| block.instructions.last().unwrap().address)
+            .collect()
+    }
+
+    /// Addresses control reaches that don't hold a complete instruction.
+    pub fn invalid(&self) -> impl Iterator<Item = usize> + '_ {
+        self.invalid.iter().copied()
+    }
+
+    /// The graph in Graphviz DOT format. Indirect jumps lead to a `?` node
+    /// of their own, and invalid addresses to a red one.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph cfg {\n");
+        dot += "    node [shape=box, fontname=\"monospace\"];\n";
+
+        for block in self.blocks() {
+            let label = block
+                .instructions
+                .iter()
+                .map(|instruction| format!("{}: {}\\l", instruction.address, instruction.to_asm()))
+                .collect::<String>();
+            dot += &format!("    b{} [label=\"{}\"];\n", block.start, label);
+
+            match block.exit {
+                Exit::Next(next) => dot += &format!("    b{} -> b{};\n", block.start, next),
+                Exit::Branch { target, next } => {
+                    dot += &format!("    b{} -> b{} [label=\"taken\"];\n", block.start, target);
+                    if let Some(next) = next {
+                        dot +=
+                            &format!("    b{} -> b{} [label=\"not taken\"];\n", block.start, next);
+                    }
+                }
+                Exit::Indirect { next } => {
+                    dot += &format!(
+                        "    i{0} [label=\"?\", shape=diamond];\n    b{0} -> i{0} [style=dashed, label=\"taken\"];\n",
+                        block.start
+                    );
+                    if let Some(next) = next {
+                        dot +=
+                            &format!("    b{} -> b{} [label=\"not taken\"];\n", block.start, next);
+                    }
+                }
+                Exit::Halt => {}
+            }
+        }
+
+        for address in self.invalid() {
+            dot += &format!("    b{0} [label=\"invalid {0}\", color=red];\n", address);
+        }
+
+        dot += "}\n";
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cfg, Exit};
+    use crate::input;
+    use crate::intcode_vm::asm::assemble;
+
+    #[test]
+    fn test_blocks_and_edges() {
+        let program = assemble(
+            "       in   [n]
+             loop:  jz   [n], #done
+                    add  [n], #-1, [n]
+                    jz   #0, #loop
+             done:  out  [n]
+                    jnz  #1, [ret]
+                    hlt
+             n:     data 0
+             ret:   data 0",
+        )
+        .unwrap();
+        let cfg = Cfg::build(&program);
+
+        let exits = cfg
+            .blocks()
+            .map(|block| (block.start, block.end, block.exit))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            exits,
+            vec![
+                (0, 2, Exit::Next(2)),
+                (
+                    2,
+                    5,
+                    Exit::Branch {
+                        target: 12,
+                        next: Some(5),
+                    }
+                ),
+                (
+                    5,
+                    12,
+                    Exit::Branch {
+                        target: 2,
+                        next: None,
+                    }
+                ),
+                (12, 17, Exit::Indirect { next: None }),
+            ]
+        );
+
+        assert_eq!(cfg.unresolved(), vec![14]);
+        // The `hlt` after the unconditional indirect jump is never reached.
+        assert_eq!(cfg.data(), vec![17..20]);
+        assert!(cfg.is_code(16));
+        assert!(!cfg.is_code(17));
+    }
+
+    #[test]
+    fn test_invalid_targets() {
+        // jz [5], #4 and hlt, where 4 holds the data word 42.
+        let program = vec![1006, 5, 4, 99, 42, 0];
+        let cfg = Cfg::build(&program);
+        assert_eq!(cfg.invalid().collect::<Vec<_>>(), vec![4]);
+        assert!(cfg
+            .to_dot()
+            .contains("b4 [label=\"invalid 4\", color=red];"));
+
+        // add #1, #1, [5], then the 0 at 4.
+        let cfg = Cfg::build(&[1101, 1, 1, 5, 0, 0]);
+        assert_eq!(cfg.block(0).unwrap().exit, Exit::Next(4));
+        assert_eq!(cfg.invalid().collect::<Vec<_>>(), vec![4]);
+    }
+
+    #[test]
+    fn test_dot_export() {
+        let cfg = Cfg::build(&assemble("loop: in [0]\njnz [0], #loop\nhlt").unwrap());
+        assert_eq!(
+            cfg.to_dot(),
+            "digraph cfg {
+    node [shape=box, fontname=\"monospace\"];
+    b0 [label=\"0: in   [0]\\l2: jnz  [0], #0\\l\"];
+    b0 -> b0 [label=\"taken\"];
+    b0 -> b5 [label=\"not taken\"];
+    b5 [label=\"5: hlt\\l\"];
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_boost() {
+        let program = input::parse_intcode(include_str!("../../inputs/day09.txt")).unwrap();
+        let cfg = Cfg::build(&program);
+
+        assert_eq!(cfg.block(0).unwrap().instructions.len(), 3);
+        assert!(!cfg.unresolved().is_empty());
+        assert!(cfg.invalid().next().is_none());
+        // Every block ends where its successors start.
+        for block in cfg.blocks() {
+            for successor in block.exit.successors() {
+                assert!(cfg.block(successor).is_some(), "{}", successor);
+            }
+        }
+        assert!(!cfg.data().is_empty());
+    }
+}
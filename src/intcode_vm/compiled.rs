@@ -0,0 +1,533 @@
+//! A backend that compiles straight-line runs of instructions into chains of
+//! closures, each specialised for its opcode and parameter modes.
+//!
+//! A block starts wherever execution enters it and runs straight through
+//! memory, leaving early wherever a jump is taken, up to an unconditional
+//! jump or an instruction that needs the interpreter: input, output, halt,
+//! anything that faults and anything that has been overwritten since it was
+//! compiled. Writing to a word of a compiled block drops the block and marks
+//! the word, so that code is interpreted from then on.
+//!
+//! Compiling a block costs far more than interpreting it once, so a block is
+//! only compiled once execution has entered it `HOT_ENTRIES` times. Until
+//! then the code there is interpreted up to the first jump taken.
+
+use std::fmt;
+use std::sync::Arc;
+
+use super::trace::NoopSink;
+use super::{can_stop, IntcodeVM, Opcode, ParamMode, VMState, VmError, CACHED_WORDS};
+
+/// How `IntcodeVM::execute` runs a program. Traced execution always
+/// interprets, so sinks see every instruction.
+///
+/// `Compiled` only pays off for programs that spend a long time in loops,
+/// like day 9's BOOST. Short runs, like the day 5 diagnostics or each
+/// amplifier of a day 7 search, are mostly interpreted anyway and run at
+/// about the interpreter's speed.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Backend {
+    #[default]
+    Interpreter,
+    Compiled,
+}
+
+/// How many times execution enters an address before the block there is
+/// compiled. Code that only runs a few times is cheaper to interpret than to
+/// compile.
+const HOT_ENTRIES: u8 = 2;
+
+/// `HOT_ENTRIES` outside tests. Tests compile everything they run, so the
+/// compiled backend gets exercised by programs that only run once.
+const THRESHOLD: u8 = if cfg!(test) { 0 } else { HOT_ENTRIES };
+
+/// The longest block, in words, so a write only has to look this far back
+/// for blocks covering it.
+const MAX_BLOCK_WORDS: usize = 256;
+
+const POSITION: u8 = 0;
+const IMMEDIATE: u8 = 1;
+const RELATIVE: u8 = 2;
+
+const ADD: u8 = 0;
+const MUL: u8 = 1;
+const LESS_THAN: u8 = 2;
+const EQUALS: u8 = 3;
+
+/// What a compiled instruction did.
+enum Flow {
+    Next,
+    Jump(usize),
+    /// The instruction would fault. It has had no effect, so the
+    /// interpreter can run it again to report the fault.
+    Fault,
+}
+
+/// Executes one instruction.
+type Operation = Box<dyn Fn(&mut IntcodeVM) -> Flow + Send + Sync>;
+
+struct Step {
+    /// The address of the next instruction.
+    next: usize,
+    /// The instruction's fuel cost.
+    cost: u64,
+    run: Operation,
+}
+
+/// Where `execute_compiled` carries on from the instruction pointer.
+enum Entry {
+    Block(Arc<Block>),
+    /// Not entered often enough yet to be worth compiling.
+    Cold,
+    /// Past the words the cache covers, so left to the interpreter.
+    Uncached,
+}
+
+struct Block {
+    /// The end of the words the block covers. Running off the end leaves
+    /// the instruction there to the interpreter.
+    end: usize,
+    steps: Vec<Step>,
+}
+
+impl fmt::Debug for Block {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Block")
+            .field("end", &self.end)
+            .field("steps", &self.steps.len())
+            .finish()
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Word {
+    Uncompiled,
+    Compiled,
+    /// Written to after being compiled; never compiled again.
+    Overwritten,
+}
+
+/// The compiled blocks of a VM, shared between clones.
+#[derive(Clone, Debug)]
+pub(super) struct CodeCache {
+    /// Blocks by start address.
+    blocks: Vec<Option<Arc<Block>>>,
+    /// How often execution has entered each address, up to `threshold`.
+    entries: Vec<u8>,
+    /// Entries before a block is compiled.
+    threshold: u8,
+    words: Vec<Word>,
+    /// Set when a write hits a compiled word, so a running block stops
+    /// before executing anything stale.
+    invalidated: bool,
+}
+
+impl Default for CodeCache {
+    fn default() -> Self {
+        CodeCache {
+            blocks: Vec::new(),
+            entries: Vec::new(),
+            threshold: THRESHOLD,
+            words: Vec::new(),
+            invalidated: false,
+        }
+    }
+}
+
+impl CodeCache {
+    pub(super) fn clear(&mut self) {
+        self.blocks.clear();
+        self.entries.clear();
+        self.words.clear();
+        self.invalidated = false;
+    }
+
+    /// Drops every block covering `address` if it is compiled.
+    pub(super) fn invalidate(&mut self, address: usize) {
+        match self.words.get_mut(address) {
+            Some(word @ Word::Compiled) => *word = Word::Overwritten,
+            _ => return,
+        }
+
+        let first = address.saturating_sub(MAX_BLOCK_WORDS);
+        let last = address.min(self.blocks.len().saturating_sub(1));
+        for start in first..=last {
+            let covered = match &self.blocks[start] {
+                Some(block) => (start..block.end).contains(&address),
+                None => false,
+            };
+            if covered {
+                self.blocks[start] = None;
+            }
+        }
+
+        self.invalidated = true;
+    }
+
+    fn mark(&mut self, start: usize, end: usize) {
+        if self.words.len() < end {
+            self.words.resize(end, Word::Uncompiled);
+        }
+        for word in &mut self.words[start..end] {
+            if *word == Word::Uncompiled {
+                *word = Word::Compiled;
+            }
+        }
+    }
+
+    fn is_overwritten(&self, start: usize, end: usize) -> bool {
+        let end = end.min(self.words.len());
+        self.words[start.min(end)..end].contains(&Word::Overwritten)
+    }
+}
+
+/// Reads a parameter, or returns `None` if it points at a negative address
+/// or its address overflows.
+#[inline(always)]
+fn read<const MODE: u8>(vm: &IntcodeVM, raw: i64) -> Option<i64> {
+    match MODE {
+        IMMEDIATE => Some(raw),
+        _ => address::<MODE>(vm, raw).map(|address| vm.load_memory(address)),
+    }
+}
+
+#[inline(always)]
+fn address<const MODE: u8>(vm: &IntcodeVM, raw: i64) -> Option<usize> {
+    let address = match MODE {
+        RELATIVE => vm.relative_pointer.checked_add(raw)?,
+        _ => raw,
+    };
+    if address < 0 {
+        None
+    } else {
+        Some(address as usize)
+    }
+}
+
+fn binary<const OP: u8, const M1: u8, const M2: u8, const M3: u8>(params: [i64; 3]) -> Operation {
+    let [raw1, raw2, raw3] = params;
+    Box::new(move |vm: &mut IntcodeVM| {
+        let (param1, param2, target) = match (
+            read::<M1>(vm, raw1),
+            read::<M2>(vm, raw2),
+            address::<M3>(vm, raw3),
+        ) {
+            (Some(param1), Some(param2), Some(target)) => (param1, param2, target),
+            _ => return Flow::Fault,
+        };
+        let result = match OP {
+            ADD => vm.arithmetic.add(param1, param2),
+            MUL => vm.arithmetic.mul(param1, param2),
+            LESS_THAN => Some((param1 < param2) as i64),
+            _ => Some((param1 == param2) as i64),
+        };
+        let result = match result {
+            Some(result) => result,
+            None => return Flow::Fault,
+        };
+        match vm.save_memory(target, result, &mut NoopSink) {
+            Ok(()) => Flow::Next,
+            Err(_) => Flow::Fault,
+        }
+    })
+}
+
+fn set_relative_base<const M1: u8>(raw1: i64) -> Operation {
+    Box::new(move |vm: &mut IntcodeVM| match read::<M1>(vm, raw1) {
+        Some(offset) => match vm.add_relative_pointer(offset) {
+            Ok(()) => Flow::Next,
+            Err(_) => Flow::Fault,
+        },
+        None => Flow::Fault,
+    })
+}
+
+fn jump<const IF_ZERO: bool, const M1: u8, const M2: u8>(params: [i64; 3]) -> Operation {
+    let [raw1, raw2, _] = params;
+    Box::new(
+        move |vm: &mut IntcodeVM| match (read::<M1>(vm, raw1), read::<M2>(vm, raw2)) {
+            (Some(condition), Some(target)) if (condition == 0) == IF_ZERO => {
+                Flow::Jump(target as usize)
+            }
+            (Some(_), Some(_)) => Flow::Next,
+            _ => Flow::Fault,
+        },
+    )
+}
+
+/// Expands `$body` once per parameter mode with `$name` bound to the mode as
+/// a constant.
+macro_rules! with_mode {
+    ($mode:expr, $name:ident => $body:expr) => {
+        match $mode {
+            ParamMode::Position => {
+                const $name: u8 = POSITION;
+                $body
+            }
+            ParamMode::Immediate => {
+                const $name: u8 = IMMEDIATE;
+                $body
+            }
+            ParamMode::Relative => {
+                const $name: u8 = RELATIVE;
+                $body
+            }
+        }
+    };
+}
+
+macro_rules! binary_operation {
+    ($op:ident, $m1:expr, $m2:expr, $m3:expr, $params:expr) => {
+        with_mode!($m1, M1 => with_mode!($m2, M2 => match $m3 {
+            ParamMode::Relative => binary::<$op, M1, M2, RELATIVE>($params),
+            _ => binary::<$op, M1, M2, POSITION>($params),
+        }))
+    };
+}
+
+/// Compiles an instruction, or returns `None` if it has to be left to the
+/// interpreter.
+fn compile_step(opcode: Opcode, params: [i64; 3]) -> Option<Operation> {
+    let operation = match opcode {
+        Opcode::Add(_, _, ParamMode::Immediate)
+        | Opcode::Mul(_, _, ParamMode::Immediate)
+        | Opcode::LessThen(_, _, ParamMode::Immediate)
+        | Opcode::Equals(_, _, ParamMode::Immediate) => return None,
+        Opcode::Add(m1, m2, m3) => binary_operation!(ADD, m1, m2, m3, params),
+        Opcode::Mul(m1, m2, m3) => binary_operation!(MUL, m1, m2, m3, params),
+        Opcode::LessThen(m1, m2, m3) => binary_operation!(LESS_THAN, m1, m2, m3, params),
+        Opcode::Equals(m1, m2, m3) => binary_operation!(EQUALS, m1, m2, m3, params),
+        Opcode::SetRB(m1) => with_mode!(m1, M1 => set_relative_base::<M1>(params[0])),
+        Opcode::JmpIfZero(m1, m2) => {
+            with_mode!(m1, M1 => with_mode!(m2, M2 => jump::<true, M1, M2>(params)))
+        }
+        Opcode::JmpIfNotZero(m1, m2) => {
+            with_mode!(m1, M1 => with_mode!(m2, M2 => jump::<false, M1, M2>(params)))
+        }
+        _ => return None,
+    };
+
+    Some(operation)
+}
+
+/// Whether nothing after the instruction runs, because it always jumps.
+fn always_jumps(opcode: Opcode, params: [i64; 3]) -> bool {
+    match opcode {
+        Opcode::JmpIfZero(ParamMode::Immediate, _) => params[0] == 0,
+        Opcode::JmpIfNotZero(ParamMode::Immediate, _) => params[0] != 0,
+        _ => false,
+    }
+}
+
+impl IntcodeVM {
+    fn compile_block(&self, start: usize) -> Block {
+        let mut steps = vec![];
+        let mut address = start;
+
+        while address - start < MAX_BLOCK_WORDS - Opcode::MAX_SIZE {
+            if address >= self.memory.len() {
+                break;
+            }
+            let opcode = Opcode::from(self.load_memory(address));
+            let next = address + opcode.size();
+            if self.code.is_overwritten(address, next) {
+                break;
+            }
+
+            let mut params = [0; 3];
+            for (offset, param) in params.iter_mut().take(opcode.size() - 1).enumerate() {
+                *param = self.load_memory(address + offset + 1);
+            }
+
+            match compile_step(opcode, params) {
+                Some(run) => steps.push(Step {
+                    next,
+                    cost: self.fuel.costs.cost(opcode),
+                    run,
+                }),
+                None => break,
+            }
+            address = next;
+
+            if always_jumps(opcode, params) {
+                break;
+            }
+        }
+
+        Block {
+            end: address,
+            steps,
+        }
+    }
+
+    /// Takes the block starting at the instruction pointer out of the cache,
+    /// compiling it if it has been entered often enough.
+    fn take_block(&mut self) -> Entry {
+        let start = self.instruction_pointer;
+        if start >= self.memory.len() || start >= CACHED_WORDS {
+            return Entry::Uncached;
+        }
+
+        if self.code.blocks.len() <= start {
+            let len = self.memory.len().min(CACHED_WORDS).max(start + 1);
+            self.code.blocks.resize(len, None);
+            self.code.entries.resize(len, 0);
+        }
+        if let Some(block) = self.code.blocks[start].take() {
+            return Entry::Block(block);
+        }
+        if self.code.entries[start] < self.code.threshold {
+            self.code.entries[start] += 1;
+            return Entry::Cold;
+        }
+
+        let block = self.compile_block(start);
+        self.code.mark(start, block.end);
+        self.mark_code(start, block.end);
+        Entry::Block(Arc::new(block))
+    }
+
+    /// Interprets from the instruction pointer up to the first jump taken,
+    /// which is as far as a block compiled there would go.
+    fn interpret_run(&mut self) -> Result<Option<VMState>, VmError> {
+        loop {
+            let address = self.instruction_pointer;
+            let state = match self.decoded.get(address) {
+                Some(Some(instruction)) if !can_stop(instruction.opcode) => {
+                    self.run_decoded(address, *instruction, &mut NoopSink)?
+                }
+                _ => self.step()?,
+            };
+            if state.is_some() {
+                return Ok(state);
+            }
+            let size = self.current_opcode.map_or(0, |opcode| opcode.size());
+            if self.instruction_pointer != address + size {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// `execute` for `Backend::Compiled`.
+    pub(super) fn execute_compiled(&mut self) -> Result<VMState, VmError> {
+        loop {
+            let block = match self.take_block() {
+                Entry::Block(block) => block,
+                Entry::Cold => match self.interpret_run()? {
+                    Some(state) => return Ok(state),
+                    None => continue,
+                },
+                Entry::Uncached => return self.execute_traced(&mut NoopSink),
+            };
+            let start = self.instruction_pointer;
+            self.code.invalidated = false;
+
+            let mut next = block.end;
+            let mut interpret = true;
+            let mut address = start;
+            for step in &block.steps {
+                // Leave refilling the slice, and running out of fuel or
+                // time, to the interpreter.
+                if step.cost > self.fuel.slice || self.fuel.ticks == 0 {
+                    next = address;
+                    break;
+                }
+                self.fuel.slice -= step.cost;
+                self.fuel.ticks -= 1;
+
+                match (step.run)(self) {
+                    Flow::Next => {}
+                    Flow::Jump(target) => {
+                        next = target;
+                        interpret = false;
+                        break;
+                    }
+                    Flow::Fault => {
+                        // The interpreter pays for it again.
+                        self.fuel.slice += step.cost;
+                        self.fuel.ticks += 1;
+                        next = address;
+                        break;
+                    }
+                }
+                if self.code.invalidated {
+                    next = step.next;
+                    interpret = false;
+                    break;
+                }
+                address = step.next;
+            }
+            self.instruction_pointer = next;
+
+            if !self.code.invalidated {
+                self.code.blocks[start] = Some(block);
+            }
+
+            if interpret {
+                if let Some(state) = self.step()? {
+                    return Ok(state);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backend, Word, HOT_ENTRIES};
+    use crate::intcode_vm::asm::assemble;
+    use crate::{IntcodeVM, VMState};
+
+    #[test]
+    fn test_overwritten_code_falls_back_to_the_interpreter() {
+        // Counts down from 9, patching the immediate of its own `add` to
+        // subtract 2 instead of 1 after the first iteration.
+        let program = assemble(
+            "loop: add [count], #-1, [count]
+                   out [count]
+                   add #-2, #0, [loop+2]
+                   jnz [count], #loop
+                   hlt
+             count: data 9",
+        )
+        .unwrap();
+
+        let mut vm = IntcodeVM::new();
+        vm.set_backend(Backend::Compiled);
+        vm.set_program(program);
+
+        let mut outputs = vec![];
+        while let VMState::Output(value) = vm.execute().unwrap() {
+            outputs.push(value);
+        }
+        assert_eq!(outputs, vec![8, 6, 4, 2, 0]);
+        assert_eq!(vm.code.words[2], Word::Overwritten);
+        // The block at `loop` was recompiled without the patched `add`.
+        assert!(vm.code.blocks[0].as_ref().unwrap().steps.is_empty());
+    }
+
+    #[test]
+    fn test_only_hot_code_is_compiled() {
+        let program = assemble(
+            "      out #1
+             loop: add [count], #-1, [count]
+                   jnz [count], #loop
+                   out [count]
+                   hlt
+             count: data 5",
+        )
+        .unwrap();
+
+        let mut vm = IntcodeVM::new();
+        vm.set_backend(Backend::Compiled);
+        vm.code.threshold = HOT_ENTRIES;
+        vm.set_program(program);
+        assert_eq!(vm.run_once().unwrap(), vec![1, 0]);
+
+        // The loop was entered 5 times and the rest of the program once.
+        assert!(vm.code.blocks[0].is_none());
+        assert_eq!(vm.code.blocks[2].as_ref().unwrap().steps.len(), 2);
+        assert_eq!(vm.code.entries[2], HOT_ENTRIES);
+        assert_eq!(vm.code.words[0], Word::Uncompiled);
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::BTreeSet;
+
+use super::trace::TraceSink;
+use super::{IntcodeVM, MemoryWrite, Opcode, VMState, VmError};
+
+/// Why the debugger handed control back to the caller.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StopReason {
+    /// A single step finished without anything noteworthy happening.
+    Stepped,
+    /// The instruction pointer reached a breakpoint.
+    Breakpoint(usize),
+    /// A watched address was written to.
+    Watchpoint(MemoryWrite),
+    /// The VM halted, produced an output or is waiting for input.
+    State(VMState),
+}
+
+/// Remembers the first write to any of the watched addresses.
+struct WatchSink<'a> {
+    watchpoints: &'a BTreeSet<usize>,
+    hit: Option<MemoryWrite>,
+}
+
+impl TraceSink for WatchSink<'_> {
+    fn instruction(&mut self, _address: usize, _opcode: Opcode, _operands: &[i64]) {}
+
+    fn memory_write(&mut self, write: MemoryWrite) {
+        if self.hit.is_none() && self.watchpoints.contains(&write.address) {
+            self.hit = Some(write);
+        }
+    }
+}
+
+/// Wraps an `IntcodeVM` with breakpoints and write watchpoints.
+#[derive(Clone, Debug, Default)]
+pub struct Debugger {
+    vm: IntcodeVM,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeSet<usize>,
+}
+
+impl Debugger {
+    pub fn new(vm: IntcodeVM) -> Self {
+        Debugger {
+            vm,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+        }
+    }
+
+    pub fn vm(&self) -> &IntcodeVM {
+        &self.vm
+    }
+
+    pub fn vm_mut(&mut self) -> &mut IntcodeVM {
+        &mut self.vm
+    }
+
+    pub fn into_vm(self) -> IntcodeVM {
+        self.vm
+    }
+
+    /// Adds a breakpoint, returning `false` if it was already set.
+    pub fn add_breakpoint(&mut self, address: usize) -> bool {
+        self.breakpoints.insert(address)
+    }
+
+    pub fn remove_breakpoint(&mut self, address: usize) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Adds a watchpoint, returning `false` if it was already set.
+    pub fn add_watchpoint(&mut self, address: usize) -> bool {
+        self.watchpoints.insert(address)
+    }
+
+    pub fn remove_watchpoint(&mut self, address: usize) -> bool {
+        self.watchpoints.remove(&address)
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.watchpoints.iter().copied()
+    }
+
+    /// Executes exactly one instruction.
+    pub fn step(&mut self) -> Result<StopReason, VmError> {
+        let mut sink = WatchSink {
+            watchpoints: &self.watchpoints,
+            hit: None,
+        };
+
+        if let Some(state) = self.vm.step_traced(&mut sink)? {
+            return Ok(StopReason::State(state));
+        }
+
+        match sink.hit {
+            Some(write) => Ok(StopReason::Watchpoint(write)),
+            None => Ok(StopReason::Stepped),
+        }
+    }
+
+    /// Runs until a breakpoint or watchpoint is hit, or the VM stops on its
+    /// own. Always executes at least one instruction, so resuming from a
+    /// breakpoint doesn't stop on the same address again.
+    pub fn resume(&mut self) -> Result<StopReason, VmError> {
+        loop {
+            match self.step()? {
+                StopReason::Stepped => {}
+                reason => return Ok(reason),
+            }
+
+            let address = self.vm.instruction_pointer();
+            if self.breakpoints.contains(&address) {
+                return Ok(StopReason::Breakpoint(address));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Debugger, StopReason};
+    use crate::intcode_vm::asm::assemble;
+    use crate::intcode_vm::MemoryWrite;
+    use crate::{IntcodeVM, VMState};
+
+    fn debugger(source: &str) -> Debugger {
+        let mut vm = IntcodeVM::new();
+        vm.set_program(assemble(source).unwrap());
+        Debugger::new(vm)
+    }
+
+    #[test]
+    fn test_resume_stops_at_breakpoint_and_watchpoint() {
+        let mut debugger = debugger(
+            "
+                  add #1, #2, [value]
+            next: out [value]
+                  hlt
+            value: data 0
+            ",
+        );
+        debugger.add_breakpoint(4);
+        debugger.add_watchpoint(7);
+
+        assert_eq!(
+            debugger.resume().unwrap(),
+            StopReason::Watchpoint(MemoryWrite {
+                address: 7,
+                old_value: 0,
+                new_value: 3
+            })
+        );
+        assert_eq!(debugger.vm().instruction_pointer(), 4);
+        assert_eq!(
+            debugger.resume().unwrap(),
+            StopReason::State(VMState::Output(3))
+        );
+        assert_eq!(
+            debugger.resume().unwrap(),
+            StopReason::State(VMState::Halted)
+        );
+    }
+
+    #[test]
+    fn test_resume_steps_off_current_breakpoint() {
+        let mut debugger = debugger("add #0, #0, [9]\nadd #0, #0, [9]\nhlt");
+        debugger.add_breakpoint(0);
+        debugger.add_breakpoint(4);
+
+        assert_eq!(debugger.resume().unwrap(), StopReason::Breakpoint(4));
+        assert_eq!(debugger.step().unwrap(), StopReason::Stepped);
+        assert_eq!(debugger.step().unwrap(), StopReason::State(VMState::Halted));
+    }
+}
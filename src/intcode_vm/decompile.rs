@@ -0,0 +1,1172 @@
+//! Lifting Intcode back into structured pseudo-code.
+//!
+//! The decompiler works from the program's `Cfg`. A block that stores its
+//! own return address and then jumps unconditionally is a call, and the
+//! target of a call is a function. The return address is only reached
+//! through the callee's indirect jump back, so it's added as an extra entry
+//! to the `Cfg`. Functions return with an unconditional indirect jump, and
+//! their frames follow the relative base convention: a function starts
+//! with `arb #n` and ends with `arb #-n`, its caller leaves the return
+//! address at `rb+0` and the arguments from `rb+1`, and so inside the
+//! function the frame is `rb-n` to `rb-1`. Frame slots are named `ret_addr`,
+//! `p1`, `p2`, ... for arguments and `v3`, `v4`, ... for the rest. Frames
+//! over `MAX_FRAME` words aren't recognised, and their `arb` is shown as it
+//! is.
+//!
+//! Within a function, blocks are laid out as `if`/`else`, `while`,
+//! `do`/`while` and `loop` where the jumps have those shapes, and as
+//! `goto` where they don't. A comparison whose result is stored and then
+//! branched on straight away becomes the branch's condition.
+//!
+//! The output always follows the program's real control flow, but, like
+//! the `Cfg`, it can't account for code that modifies itself.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use super::cfg::{BasicBlock, Cfg, Exit};
+use super::disasm::Instruction;
+use super::{Opcode, ParamMode};
+
+/// The largest frame, in words, that is named slot by slot.
+const MAX_FRAME: i64 = 4096;
+
+/// A function found from its calls, or the program's entry point.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Function {
+    pub entry: usize,
+    /// The size of the function's frame, if it follows the relative base
+    /// convention.
+    pub frame: Option<i64>,
+    /// The most arguments any caller passes.
+    pub params: usize,
+    /// The function's body, indented.
+    pub lines: Vec<String>,
+}
+
+impl Function {
+    pub fn name(&self) -> String {
+        match self.entry {
+            0 => "main".to_string(),
+            entry => format!("f_{}", entry),
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let params = (1..=self.params)
+            .map(|param| format!("p{}", param))
+            .collect::<Vec<_>>();
+        writeln!(f, "fn {}({}) {{", self.name(), params.join(", "))?;
+
+        if let Some(size) = self.frame {
+            let names = Frame::new(self.frame, self.params);
+            let slots = (-size..0)
+                .map(|offset| names.slot(offset))
+                .collect::<Vec<_>>();
+            writeln!(f, "    // frame: {}", slots.join(", "))?;
+        }
+        for line in &self.lines {
+            writeln!(f, "{}", line)?;
+        }
+
+        write!(f, "}}")
+    }
+}
+
+/// A decompiled program, `main` first.
+#[derive(Clone, Debug, Default)]
+pub struct Decompiled {
+    pub functions: Vec<Function>,
+}
+
+impl Decompiled {
+    pub fn function(&self, entry: usize) -> Option<&Function> {
+        self.functions
+            .iter()
+            .find(|function| function.entry == entry)
+    }
+}
+
+impl fmt::Display for Decompiled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, function) in self.functions.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            writeln!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decompiles `program`, starting from address 0.
+pub fn decompile(program: &[i64]) -> Decompiled {
+    let decompiler = Decompiler::new(program);
+
+    let functions = decompiler
+        .functions
+        .values()
+        .map(|function| Function {
+            entry: function.entry,
+            frame: function.frame,
+            params: function.params,
+            lines: decompiler.body(function),
+        })
+        .collect();
+
+    Decompiled { functions }
+}
+
+/// An operand, or an expression built from operands.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Value {
+    Const(i64),
+    Expr(String),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Const(value) => write!(f, "{}", value),
+            Value::Expr(expr) => write!(f, "{}", expr),
+        }
+    }
+}
+
+fn add(a: Value, b: Value) -> Value {
+    match (a, b) {
+        (Value::Const(a), Value::Const(b)) if a.checked_add(b).is_some() => Value::Const(a + b),
+        (Value::Const(0), value) | (value, Value::Const(0)) => value,
+        (value, Value::Const(n)) | (Value::Const(n), value) if n < 0 => {
+            Value::Expr(format!("{} - {}", value, n.unsigned_abs()))
+        }
+        (a, b) => Value::Expr(format!("{} + {}", a, b)),
+    }
+}
+
+fn mul(a: Value, b: Value) -> Value {
+    match (a, b) {
+        (Value::Const(a), Value::Const(b)) if a.checked_mul(b).is_some() => Value::Const(a * b),
+        (Value::Const(1), value) | (value, Value::Const(1)) => value,
+        (Value::Const(0), _) | (_, Value::Const(0)) => Value::Const(0),
+        (a, b) => Value::Expr(format!("{} * {}", a, b)),
+    }
+}
+
+fn compare(a: Value, op: &'static str, b: Value) -> Value {
+    match (a, b) {
+        (Value::Const(a), Value::Const(b)) if op == "<" => Value::Const((a < b) as i64),
+        (Value::Const(a), Value::Const(b)) => Value::Const((a == b) as i64),
+        (a, b) => Value::Expr(format!("{} {} {}", a, op, b)),
+    }
+}
+
+/// When a branch is taken.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Cond {
+    NonZero(Value),
+    Zero(Value),
+    Compare(Value, &'static str, Value),
+}
+
+impl Cond {
+    fn negate(&self) -> Cond {
+        match self.clone() {
+            Cond::NonZero(value) => Cond::Zero(value),
+            Cond::Zero(value) => Cond::NonZero(value),
+            Cond::Compare(a, op, b) => {
+                let op = match op {
+                    "<" => ">=",
+                    ">=" => "<",
+                    "==" => "!=",
+                    _ => "==",
+                };
+                Cond::Compare(a, op, b)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Cond {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Cond::NonZero(value) => write!(f, "{} != 0", value),
+            Cond::Zero(value) => write!(f, "{} == 0", value),
+            Cond::Compare(a, op, b) => write!(f, "{} {} {}", a, op, b),
+        }
+    }
+}
+
+/// How a function's relative operands are named.
+#[derive(Copy, Clone, Debug)]
+struct Frame {
+    size: Option<i64>,
+    params: usize,
+}
+
+impl Frame {
+    fn new(size: Option<i64>, params: usize) -> Self {
+        Frame { size, params }
+    }
+
+    fn slot(&self, offset: i64) -> String {
+        match self.size {
+            Some(size) if (-size..0).contains(&offset) => match size + offset {
+                0 => "ret_addr".to_string(),
+                slot if slot as usize <= self.params => format!("p{}", slot),
+                slot => format!("v{}", slot),
+            },
+            _ => format!("rb[{}]", offset),
+        }
+    }
+
+    fn operand(&self, (mode, word): (ParamMode, i64)) -> Value {
+        match mode {
+            ParamMode::Immediate => Value::Const(word),
+            ParamMode::Position => Value::Expr(format!("mem[{}]", word)),
+            ParamMode::Relative => Value::Expr(self.slot(word)),
+        }
+    }
+
+    /// The value `instruction` computes, if it computes one.
+    fn value(&self, instruction: &Instruction) -> Option<Value> {
+        let operands = instruction.operands();
+        let operand = |index: usize| self.operand(operands[index]);
+        Some(match instruction.opcode {
+            Opcode::Add(..) => add(operand(0), operand(1)),
+            Opcode::Mul(..) => mul(operand(0), operand(1)),
+            Opcode::LessThen(..) => compare(operand(0), "<", operand(1)),
+            Opcode::Equals(..) => compare(operand(0), "==", operand(1)),
+            Opcode::Input(..) => Value::Expr("input()".to_string()),
+            _ => return None,
+        })
+    }
+
+    /// The statement `instruction` lifts to, or `None` if it copies a
+    /// value onto itself.
+    fn statement(&self, instruction: &Instruction) -> Option<String> {
+        let operands = instruction.operands();
+        if let (Some(value), Some(destination)) =
+            (self.value(instruction), destination(instruction))
+        {
+            let destination = self.operand(destination);
+            if value == destination {
+                return None;
+            }
+            return Some(format!("{} = {};", destination, value));
+        }
+
+        Some(match instruction.opcode {
+            Opcode::Output(..) => format!("output({});", self.operand(operands[0])),
+            Opcode::SetRB(..) => match self.operand(operands[0]) {
+                Value::Const(n) if n < 0 => format!("rb -= {};", n.unsigned_abs()),
+                value => format!("rb += {};", value),
+            },
+            Opcode::Halt => "halt;".to_string(),
+            _ => format!("// {}", instruction.to_asm()),
+        })
+    }
+}
+
+/// The operand an instruction writes to.
+fn destination(instruction: &Instruction) -> Option<(ParamMode, i64)> {
+    let operands = instruction.operands();
+    match instruction.opcode {
+        Opcode::Add(..) | Opcode::Mul(..) | Opcode::LessThen(..) | Opcode::Equals(..) => {
+            Some(operands[2])
+        }
+        Opcode::Input(..) => Some(operands[0]),
+        _ => None,
+    }
+}
+
+/// The operands an instruction reads.
+fn sources(instruction: &Instruction) -> Vec<(ParamMode, i64)> {
+    let mut operands = instruction.operands();
+    if destination(instruction).is_some() {
+        operands.pop();
+    }
+    operands
+}
+
+fn constant(instruction: &Instruction) -> Option<i64> {
+    match instruction.opcode {
+        Opcode::Add(..) | Opcode::Mul(..) => {}
+        _ => return None,
+    }
+    let frame = Frame::new(None, 0);
+    match frame.value(instruction)? {
+        Value::Const(value) => Some(value),
+        Value::Expr(_) => None,
+    }
+}
+
+/// A block that ends by calling a function.
+#[derive(Clone, Debug)]
+struct Call {
+    callee: usize,
+    ret: usize,
+    /// The index of the instruction that stores the return address.
+    store: usize,
+    /// The indices of the instructions that store the arguments, in order.
+    args: Vec<usize>,
+}
+
+impl Call {
+    fn find(block: &BasicBlock) -> Option<Call> {
+        let callee = match block.exit {
+            Exit::Branch { target, next: None } => target,
+            _ => return None,
+        };
+        let jump = block.instructions.len() - 1;
+        let ret = block.instructions[jump].next_address();
+        let store = block.instructions[..jump]
+            .iter()
+            .rposition(|instruction| constant(instruction) == Some(ret as i64))?;
+        let ret_slot = destination(&block.instructions[store]);
+
+        // The arguments are the stores to `rb+1` onwards just before the
+        // call, as long as they can be moved past each other.
+        let mut slots = BTreeMap::new();
+        for index in (0..jump).rev().filter(|&index| index != store) {
+            let instruction = &block.instructions[index];
+            let slot = match destination(instruction) {
+                Some((ParamMode::Relative, slot)) if slot >= 1 => slot,
+                _ => break,
+            };
+            let movable = !matches!(instruction.opcode, Opcode::Input(..))
+                && sources(instruction).iter().all(|&source| {
+                    Some(source) != ret_slot
+                        && !matches!(source, (ParamMode::Relative, offset) if offset >= 0)
+                });
+            if !movable || slots.insert(slot, index).is_some() {
+                break;
+            }
+        }
+        let args = (1..).map_while(|slot| slots.get(&slot).copied()).collect();
+
+        Some(Call {
+            callee,
+            ret,
+            store,
+            args,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+struct FunctionInfo {
+    entry: usize,
+    blocks: BTreeSet<usize>,
+    frame: Option<i64>,
+    params: usize,
+    /// Whether the function writes to its first argument, which is how
+    /// the convention returns a value.
+    returns: bool,
+}
+
+impl FunctionInfo {
+    fn names(&self) -> Frame {
+        Frame::new(self.frame, self.params)
+    }
+}
+
+struct Decompiler {
+    cfg: Cfg,
+    calls: BTreeMap<usize, Call>,
+    functions: BTreeMap<usize, FunctionInfo>,
+}
+
+impl Decompiler {
+    fn new(program: &[i64]) -> Self {
+        // Return addresses are only reached by jumping back from a call, so
+        // keep adding them as entries until there are no new ones.
+        let mut entries = vec![0];
+        let cfg = loop {
+            let cfg = Cfg::build_from(program, &entries);
+            let returns = cfg
+                .blocks()
+                .filter_map(Call::find)
+                .map(|call| call.ret)
+                .filter(|ret| !entries.contains(ret))
+                .collect::<BTreeSet<_>>();
+            if returns.is_empty() {
+                break cfg;
+            }
+            entries.extend(returns);
+        };
+
+        let calls = cfg
+            .blocks()
+            .filter_map(|block| Some((block.start, Call::find(block)?)))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut decompiler = Decompiler {
+            cfg,
+            calls,
+            functions: BTreeMap::new(),
+        };
+
+        let entries = std::iter::once(0)
+            .chain(decompiler.calls.values().map(|call| call.callee))
+            .filter(|&entry| decompiler.cfg.block(entry).is_some())
+            .collect::<BTreeSet<_>>();
+        for entry in entries {
+            let blocks = decompiler.reachable(entry);
+            let frame = decompiler.frame(entry, &blocks);
+            decompiler.functions.insert(
+                entry,
+                FunctionInfo {
+                    entry,
+                    blocks,
+                    frame,
+                    params: 0,
+                    returns: false,
+                },
+            );
+        }
+
+        for call in decompiler.calls.values() {
+            if let Some(callee) = decompiler.functions.get_mut(&call.callee) {
+                callee.params = callee.params.max(call.args.len());
+            }
+        }
+        let returns = decompiler
+            .functions
+            .values()
+            .map(|function| (function.entry, decompiler.writes_first_param(function)))
+            .collect::<Vec<_>>();
+        for (entry, returns) in returns {
+            decompiler.functions.get_mut(&entry).unwrap().returns = returns;
+        }
+
+        decompiler
+    }
+
+    fn block(&self, start: usize) -> &BasicBlock {
+        self.cfg.block(start).unwrap()
+    }
+
+    /// Where control goes after `block`, stepping over calls.
+    fn successors(&self, block: &BasicBlock) -> Vec<usize> {
+        match self.calls.get(&block.start) {
+            Some(call) => vec![call.ret],
+            None => block.exit.successors(),
+        }
+    }
+
+    fn reachable(&self, entry: usize) -> BTreeSet<usize> {
+        let mut blocks = BTreeSet::new();
+        let mut pending = vec![entry];
+        while let Some(start) = pending.pop() {
+            if let Some(block) = self.cfg.block(start) {
+                if blocks.insert(start) {
+                    pending.extend(self.successors(block));
+                }
+            }
+        }
+        blocks
+    }
+
+    /// Whether a function's block ends by returning.
+    fn is_return(&self, entry: usize, block: &BasicBlock) -> bool {
+        entry != 0 && block.exit == Exit::Indirect { next: None }
+    }
+
+    /// The size of the function's frame, if it sets one up on entry and
+    /// only ever changes the relative base to tear it down again.
+    fn frame(&self, entry: usize, blocks: &BTreeSet<usize>) -> Option<i64> {
+        let size = match self.block(entry).instructions[0].opcode {
+            Opcode::SetRB(ParamMode::Immediate) => self.block(entry).instructions[0].words[1],
+            _ => return None,
+        };
+        if size <= 0 || size > MAX_FRAME {
+            return None;
+        }
+
+        for &start in blocks {
+            let block = self.block(start);
+            let epilogue = match self.is_return(entry, block) {
+                true => block.instructions.len().checked_sub(2),
+                false => None,
+            };
+            for (index, instruction) in block.instructions.iter().enumerate() {
+                let prologue = start == entry && index == 0;
+                let allowed = match instruction.opcode {
+                    Opcode::SetRB(ParamMode::Immediate) if prologue => true,
+                    Opcode::SetRB(ParamMode::Immediate) if Some(index) == epilogue => {
+                        instruction.words[1] == -size
+                    }
+                    Opcode::SetRB(_) => false,
+                    _ => true,
+                };
+                if !allowed {
+                    return None;
+                }
+            }
+        }
+
+        Some(size)
+    }
+
+    fn writes_first_param(&self, function: &FunctionInfo) -> bool {
+        let size = match function.frame {
+            Some(size) => size,
+            None => return false,
+        };
+        function.blocks.iter().any(|&start| {
+            self.block(start).instructions.iter().any(|instruction| {
+                destination(instruction) == Some((ParamMode::Relative, 1 - size))
+            })
+        })
+    }
+
+    /// Lifts one of `function`'s blocks into statements.
+    fn lift(&self, function: &FunctionInfo, block: &BasicBlock) -> Lifted {
+        let names = function.names();
+        let instructions = &block.instructions;
+        let last = instructions.len() - 1;
+        let mut skip = BTreeSet::new();
+        if function.frame.is_some() && block.start == function.entry {
+            skip.insert(0);
+        }
+
+        let mut call_statement = None;
+        let end = match block.exit {
+            Exit::Next(next) => End::Jump(next),
+            Exit::Halt => {
+                skip.insert(last);
+                match instructions[last].opcode {
+                    Opcode::Halt => End::Stop("halt;"),
+                    _ => End::Stop("fault;  // jumps to a negative address"),
+                }
+            }
+            Exit::Branch { target, next: None } => {
+                skip.insert(last);
+                match self.calls.get(&block.start) {
+                    Some(call) => {
+                        skip.insert(call.store);
+                        skip.extend(call.args.iter().copied());
+                        call_statement = Some(self.call_statement(&names, block, call));
+                        End::Jump(call.ret)
+                    }
+                    None => End::Jump(target),
+                }
+            }
+            Exit::Branch {
+                target,
+                next: Some(next),
+            } => {
+                skip.insert(last);
+                End::Branch(self.condition(&names, block, &mut skip), target, next)
+            }
+            Exit::Indirect { next } => {
+                skip.insert(last);
+                if self.is_return(function.entry, block) {
+                    let epilogue = last
+                        .checked_sub(1)
+                        .filter(|&index| matches!(instructions[index].opcode, Opcode::SetRB(_)));
+                    if let (Some(_), Some(epilogue)) = (function.frame, epilogue) {
+                        skip.insert(epilogue);
+                    }
+                    End::Return
+                } else {
+                    let target = names.operand(instructions[last].operands()[1]);
+                    let condition = next.map(|_| self.condition(&names, block, &mut skip));
+                    End::Indirect(condition, target, next)
+                }
+            }
+        };
+
+        let mut statements = instructions
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !skip.contains(index))
+            .filter_map(|(_, instruction)| names.statement(instruction))
+            .collect::<Vec<_>>();
+        statements.extend(call_statement);
+
+        Lifted { statements, end }
+    }
+
+    fn call_statement(&self, names: &Frame, block: &BasicBlock, call: &Call) -> String {
+        let args = call
+            .args
+            .iter()
+            .map(|&index| {
+                let value = names.value(&block.instructions[index]).unwrap();
+                value.to_string()
+            })
+            .collect::<Vec<_>>();
+        let callee = &self.functions[&call.callee];
+        let result = match callee.returns {
+            true => format!("{} = ", names.slot(1)),
+            false => String::new(),
+        };
+        format!("{}f_{}({});", result, call.callee, args.join(", "))
+    }
+
+    /// When the jump that ends `block` is taken. Folds in a comparison
+    /// just before it that stores what the jump tests.
+    fn condition(&self, names: &Frame, block: &BasicBlock, skip: &mut BTreeSet<usize>) -> Cond {
+        let instructions = &block.instructions;
+        let last = instructions.len() - 1;
+        let jump = &instructions[last];
+        let tested = jump.operands()[0];
+
+        let mut condition = Cond::NonZero(names.operand(tested));
+        if last > 0 && !skip.contains(&(last - 1)) {
+            let previous = &instructions[last - 1];
+            let operands = previous.operands();
+            let op = match previous.opcode {
+                Opcode::LessThen(..) => Some("<"),
+                Opcode::Equals(..) => Some("=="),
+                _ => None,
+            };
+            if let Some(op) = op {
+                if destination(previous) == Some(tested) {
+                    skip.insert(last - 1);
+                    condition =
+                        Cond::Compare(names.operand(operands[0]), op, names.operand(operands[1]));
+                }
+            }
+        }
+
+        match jump.opcode {
+            Opcode::JmpIfZero(..) => condition.negate(),
+            _ => condition,
+        }
+    }
+
+    fn body(&self, function: &FunctionInfo) -> Vec<String> {
+        let blocks = function
+            .blocks
+            .iter()
+            .map(|&start| (start, self.lift(function, self.block(start))))
+            .collect();
+        let mut structurer = Structurer::new(blocks);
+        structurer.run(function.entry);
+        structurer.finish()
+    }
+}
+
+/// How a lifted block ends.
+#[derive(Clone, Debug)]
+enum End {
+    Jump(usize),
+    /// A condition, the address it jumps to and the address it doesn't.
+    Branch(Cond, usize, usize),
+    /// An indirect jump that isn't a return, with its condition if it has
+    /// one and the address it doesn't jump to.
+    Indirect(Option<Cond>, Value, Option<usize>),
+    Return,
+    Stop(&'static str),
+}
+
+#[derive(Clone, Debug)]
+struct Lifted {
+    statements: Vec<String>,
+    end: End,
+}
+
+impl Lifted {
+    fn successors(&self) -> Vec<usize> {
+        match &self.end {
+            End::Jump(next) => vec![*next],
+            End::Branch(_, taken, next) => vec![*taken, *next],
+            End::Indirect(_, _, next) => next.iter().copied().collect(),
+            End::Return | End::Stop(_) => vec![],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Loop {
+    /// The block with the last jump back to the head.
+    latch: usize,
+    /// The first address after the loop that it can leave to.
+    exit: Option<usize>,
+}
+
+/// How control leaves the code being laid out for an address.
+#[derive(Copy, Clone, Debug)]
+enum Escape {
+    Continue,
+    Break,
+    Goto(usize),
+    Fault(usize),
+}
+
+enum Line {
+    Label(usize, usize),
+    Code(usize, String),
+}
+
+/// Lays out a function's lifted blocks as structured code.
+struct Structurer {
+    blocks: BTreeMap<usize, Lifted>,
+    loops: BTreeMap<usize, Loop>,
+    /// The loops being laid out, innermost last, by head.
+    active: Vec<usize>,
+    emitted: BTreeSet<usize>,
+    gotos: BTreeSet<usize>,
+    lines: Vec<Line>,
+}
+
+impl Structurer {
+    fn new(blocks: BTreeMap<usize, Lifted>) -> Self {
+        // A jump back to an earlier block closes a loop. Compilers lay
+        // loops out in order, so its body is everything in between.
+        let mut loops = BTreeMap::<usize, Loop>::new();
+        for (&start, block) in &blocks {
+            for head in block.successors() {
+                if head <= start && blocks.contains_key(&head) {
+                    let latch = loops
+                        .get(&head)
+                        .map_or(start, |found| found.latch.max(start));
+                    loops.insert(head, Loop { latch, exit: None });
+                }
+            }
+        }
+        for (&head, found) in loops.iter_mut() {
+            found.exit = blocks
+                .range(head..=found.latch)
+                .flat_map(|(_, block)| block.successors())
+                .filter(|&successor| successor > found.latch)
+                .min();
+        }
+
+        Structurer {
+            blocks,
+            loops,
+            active: vec![],
+            emitted: BTreeSet::new(),
+            gotos: BTreeSet::new(),
+            lines: vec![],
+        }
+    }
+
+    fn code(&mut self, depth: usize, code: String) {
+        self.lines.push(Line::Code(depth, code));
+    }
+
+    fn escape(&self, at: usize) -> Option<Escape> {
+        if let Some(&head) = self.active.last() {
+            if at == head {
+                return Some(Escape::Continue);
+            }
+            if Some(at) == self.loops[&head].exit {
+                return Some(Escape::Break);
+            }
+        }
+        if !self.blocks.contains_key(&at) {
+            Some(Escape::Fault(at))
+        } else if self.emitted.contains(&at) {
+            Some(Escape::Goto(at))
+        } else {
+            None
+        }
+    }
+
+    fn render(&mut self, escape: Escape) -> String {
+        match escape {
+            Escape::Continue => "continue;".to_string(),
+            Escape::Break => "break;".to_string(),
+            Escape::Goto(at) => {
+                self.gotos.insert(at);
+                format!("goto L_{};", at)
+            }
+            Escape::Fault(at) => format!("fault;  // no instruction at {}", at),
+        }
+    }
+
+    fn run(&mut self, entry: usize) {
+        self.region(entry, None, 1);
+        // Blocks only reached by a jump back to somewhere not yet laid out.
+        while let Some(&at) = self.gotos.iter().find(|at| !self.emitted.contains(at)) {
+            self.region(at, None, 1);
+        }
+    }
+
+    /// Lays out the code from `at` until control reaches `stop`.
+    fn region(&mut self, mut at: usize, stop: Option<usize>, depth: usize) {
+        loop {
+            if Some(at) == stop {
+                return;
+            }
+            if let Some(escape) = self.escape(at) {
+                let code = self.render(escape);
+                self.code(depth, code);
+                return;
+            }
+
+            let next = if self.loops.contains_key(&at) {
+                self.emit_loop(at, depth)
+            } else {
+                self.emit_block(at, stop, depth)
+            };
+            match next {
+                Some(next) => at = next,
+                None => return,
+            }
+        }
+    }
+
+    /// Lays out a block and returns where control goes next, if it
+    /// carries on.
+    fn emit_block(&mut self, at: usize, stop: Option<usize>, depth: usize) -> Option<usize> {
+        let block = self.emit_statements(at, depth);
+
+        match block.end {
+            End::Jump(next) => Some(next),
+            End::Branch(condition, taken, next) => {
+                self.emit_branch(condition, taken, next, stop, depth)
+            }
+            End::Indirect(None, target, _) => {
+                self.code(depth, format!("goto *{};", target));
+                None
+            }
+            End::Indirect(Some(condition), target, next) => {
+                self.code(depth, format!("if ({}) {{", condition));
+                self.code(depth + 1, format!("goto *{};", target));
+                self.code(depth, "}".to_string());
+                next
+            }
+            End::Return => {
+                self.code(depth, "return;".to_string());
+                None
+            }
+            End::Stop(code) => {
+                self.code(depth, code.to_string());
+                None
+            }
+        }
+    }
+
+    fn emit_statements(&mut self, at: usize, depth: usize) -> Lifted {
+        let block = self.blocks[&at].clone();
+        self.emitted.insert(at);
+        self.lines.push(Line::Label(depth, at));
+        for statement in &block.statements {
+            self.code(depth, statement.clone());
+        }
+        block
+    }
+
+    fn emit_branch(
+        &mut self,
+        condition: Cond,
+        taken: usize,
+        next: usize,
+        stop: Option<usize>,
+        depth: usize,
+    ) -> Option<usize> {
+        let escape = |structurer: &Self, at: usize| match Some(at) == stop {
+            true => Some(Escape::Goto(at)),
+            false => structurer.escape(at),
+        };
+
+        let (condition, escape, next) = match (escape(self, taken), escape(self, next)) {
+            (Some(escape), _) => (condition, escape, next),
+            (None, Some(escape)) => (condition.negate(), escape, taken),
+            // A jump back to a block that hasn't been laid out.
+            (None, None) if taken < next => (condition, Escape::Goto(taken), next),
+            (None, None) => return Some(self.emit_if(condition, taken, next, stop, depth)),
+        };
+
+        self.code(depth, format!("if ({}) {{", condition));
+        let code = self.render(escape);
+        self.code(depth + 1, code);
+        self.code(depth, "}".to_string());
+        Some(next)
+    }
+
+    /// Lays out a branch that skips forward over `next..taken`, and returns
+    /// where the two paths meet.
+    fn emit_if(
+        &mut self,
+        condition: Cond,
+        taken: usize,
+        next: usize,
+        stop: Option<usize>,
+        depth: usize,
+    ) -> usize {
+        // If the skipped code ends by jumping over more code, that's the
+        // `else`.
+        let join = match self.blocks.range(next..taken).next_back() {
+            Some((
+                _,
+                Lifted {
+                    end: End::Jump(join),
+                    ..
+                },
+            )) if *join > taken && (Some(*join) == stop || self.escape(*join).is_none()) => {
+                Some(*join)
+            }
+            _ => None,
+        };
+
+        self.code(depth, format!("if ({}) {{", condition.negate()));
+        let opening = self.lines.len() - 1;
+        match join {
+            Some(join) => {
+                self.region(next, Some(join), depth + 1);
+                let empty = self.lines[opening + 1..]
+                    .iter()
+                    .all(|line| matches!(line, Line::Label(..)));
+                // Rather than an empty `if`, only keep the `else`.
+                if empty {
+                    self.lines[opening] = Line::Code(depth, format!("if ({}) {{", condition));
+                } else {
+                    self.code(depth, "} else {".to_string());
+                }
+                let otherwise = self.lines.len();
+                self.region(taken, Some(join), depth + 1);
+                // Nor an empty `else`.
+                let empty_else = self.lines[otherwise..]
+                    .iter()
+                    .all(|line| matches!(line, Line::Label(..)));
+                if !empty && empty_else {
+                    self.lines.remove(otherwise - 1);
+                }
+                self.code(depth, "}".to_string());
+                join
+            }
+            None => {
+                self.region(next, Some(taken), depth + 1);
+                self.code(depth, "}".to_string());
+                taken
+            }
+        }
+    }
+
+    /// Lays out the loop starting at `head`, and returns where control
+    /// goes after it, if anywhere.
+    fn emit_loop(&mut self, head: usize, depth: usize) -> Option<usize> {
+        let Loop { latch, exit } = self.loops[&head];
+        self.active.push(head);
+
+        let block = self.blocks[&head].clone();
+        let latch_end = self.blocks[&latch].end.clone();
+        match (block.end, latch_end) {
+            // A head that only tests whether to leave.
+            (End::Branch(condition, taken, next), _)
+                if block.statements.is_empty()
+                    && exit.is_some()
+                    && (Some(taken) == exit || Some(next) == exit)
+                    && taken != head
+                    && next != head =>
+            {
+                let (condition, body) = match Some(taken) == exit {
+                    true => (condition.negate(), next),
+                    false => (condition, taken),
+                };
+                self.emitted.insert(head);
+                self.lines.push(Line::Label(depth, head));
+                self.code(depth, format!("while ({}) {{", condition));
+                self.region(body, Some(head), depth + 1);
+                self.code(depth, "}".to_string());
+            }
+            // A latch that tests whether to go round again.
+            (_, End::Branch(condition, taken, next))
+                if (taken == head && Some(next) == exit)
+                    || (next == head && Some(taken) == exit) =>
+            {
+                let condition = match taken == head {
+                    true => condition,
+                    false => condition.negate(),
+                };
+                self.code(depth, "do {".to_string());
+                if latch != head {
+                    if let Some(next) = self.emit_block(head, Some(latch), depth + 1) {
+                        self.region(next, Some(latch), depth + 1);
+                    }
+                }
+                self.emit_statements(latch, depth + 1);
+                self.code(depth, format!("}} while ({});", condition));
+            }
+            _ => {
+                self.code(depth, "loop {".to_string());
+                if let Some(next) = self.emit_block(head, None, depth + 1) {
+                    self.region(next, None, depth + 1);
+                }
+                if let Some(Line::Code(_, code)) = self.lines.last() {
+                    if code == "continue;" {
+                        self.lines.pop();
+                    }
+                }
+                self.code(depth, "}".to_string());
+            }
+        }
+
+        self.active.pop();
+        exit
+    }
+
+    fn finish(self) -> Vec<String> {
+        let gotos = self.gotos;
+        self.lines
+            .into_iter()
+            .filter_map(|line| match line {
+                Line::Label(depth, at) if gotos.contains(&at) => {
+                    Some(format!("{}L_{}:", "    ".repeat(depth - 1), at))
+                }
+                Line::Label(..) => None,
+                Line::Code(depth, code) => Some(format!("{}{}", "    ".repeat(depth), code)),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decompile;
+    use crate::input;
+    use crate::intcode_vm::asm::assemble;
+
+    fn main_of(source: &str) -> String {
+        let program = assemble(source).unwrap();
+        decompile(&program).function(0).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_if_else() {
+        let source = "       in  [n]
+                             lt  [n], #10, [t]
+                             jnz [t], #small
+                             out #1
+                             jz  #0, #done
+                      small: out #0
+                      done:  hlt
+                      n:     data 0
+                      t:     data 0";
+        assert_eq!(
+            main_of(source),
+            "fn main() {
+    mem[17] = input();
+    if (mem[17] >= 10) {
+        output(1);
+    } else {
+        output(0);
+    }
+    halt;
+}"
+        );
+    }
+
+    #[test]
+    fn test_loops() {
+        let source = "loop: jz  [n], #done
+                            out [n]
+                            add [n], #-1, [n]
+                            jz  #0, #loop
+                      done: hlt
+                      n:    data 3";
+        assert_eq!(
+            main_of(source),
+            "fn main() {
+    while (mem[13] != 0) {
+        output(mem[13]);
+        mem[13] = mem[13] - 1;
+    }
+    halt;
+}"
+        );
+
+        let source = "outer: add #2, #0, [j]
+                      inner: add [j], #-1, [j]
+                             jnz [j], #inner
+                             add [sum], [i], [sum]
+                             add [i], #-1, [i]
+                             jnz [i], #outer
+                             out [sum]
+                             hlt
+                      i:     data 3
+                      j:     data 0
+                      sum:   data 0";
+        assert_eq!(
+            main_of(source),
+            "fn main() {
+    do {
+        mem[26] = 2;
+        do {
+            mem[26] = mem[26] - 1;
+        } while (mem[26] != 0);
+        mem[27] = mem[27] + mem[25];
+        mem[25] = mem[25] - 1;
+    } while (mem[25] != 0);
+    output(mem[27]);
+    halt;
+}"
+        );
+    }
+
+    #[test]
+    fn test_position_mode_return() {
+        let source = "        add #5, #0, [x]
+                                add #0, #back, [r]
+                                jz  #0, #double
+                        back:   out [x]
+                                hlt
+                        double: mul [x], #2, [x]
+                                jz  #0, [r]
+                        x:      data 0
+                        r:      data 0";
+        let decompiled = decompile(&assemble(source).unwrap());
+        assert_eq!(
+            decompiled.to_string(),
+            "fn main() {
+    mem[21] = 5;
+    f_14();
+    output(mem[21]);
+    halt;
+}
+
+fn f_14() {
+    mem[21] = mem[21] * 2;
+    return;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_boost_calls() {
+        let program = input::parse_intcode(include_str!("../../inputs/day09.txt")).unwrap();
+        let decompiled = decompile(&program);
+
+        assert_eq!(decompiled.functions.len(), 2);
+        assert_eq!(
+            decompiled.function(922).unwrap().to_string(),
+            "fn f_922(p1) {
+    // frame: ret_addr, p1, v2
+    if (p1 >= 3) {
+        rb[1] = f_922(p1 - 1);
+        v2 = rb[1];
+        rb[1] = f_922(p1 - 3);
+        p1 = rb[1] + v2;
+    }
+    return;
+}"
+        );
+
+        let main = &decompiled.function(0).unwrap().lines;
+        let call = main
+            .iter()
+            .position(|line| line.trim() == "rb[1] = f_922(27);")
+            .unwrap();
+        assert_eq!(main[call + 1].trim(), "rb[1] = rb[1] + 31783;");
+    }
+
+    #[test]
+    fn test_huge_frames_are_not_named() {
+        let decompiled = decompile(&[109, 100_000_000_000, 99]);
+        assert_eq!(decompiled.function(0).unwrap().frame, None);
+        assert_eq!(
+            decompiled.to_string(),
+            "fn main() {\n    rb += 100000000000;\n    halt;\n}\n"
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use std::fmt;
+
+use super::memory::Memory;
+use super::{Opcode, ParamMode};
+
+/// A single decoded instruction, or a data word that doesn't decode to one.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Instruction {
+    pub address: usize,
+    /// The opcode word followed by the raw parameter words.
+    pub words: Vec<i64>,
+    /// `Opcode::Invalid` for data words.
+    pub opcode: Opcode,
+}
+
+impl Instruction {
+    /// Decodes the instruction at `address`, falling back to a one word data
+    /// entry when the word isn't a valid opcode or the instruction would run
+    /// past the end of `program`.
+    pub fn decode(program: &[i64], address: usize) -> Option<Self> {
+        let word = *program.get(address)?;
+        let opcode = Opcode::from(word);
+
+        let end = address + opcode.size();
+        if end > program.len() {
+            return Some(Instruction {
+                address,
+                words: vec![word],
+                opcode: Opcode::Invalid,
+            });
+        }
+
+        Some(Instruction {
+            address,
+            words: program[address..end].to_vec(),
+            opcode,
+        })
+    }
+
+    pub fn is_data(&self) -> bool {
+        self.opcode == Opcode::Invalid
+    }
+
+    /// The address of the instruction that follows this one.
+    pub fn next_address(&self) -> usize {
+        self.address + self.words.len()
+    }
+
+    /// The parameter modes paired with their raw parameter words.
+    pub fn operands(&self) -> Vec<(ParamMode, i64)> {
+        self.opcode
+            .param_modes()
+            .into_iter()
+            .zip(self.words[1..].iter().copied())
+            .collect()
+    }
+
+    /// The mnemonic and operands in the syntax accepted by `asm::assemble`.
+    pub fn to_asm(&self) -> String {
+        let operands = if self.is_data() {
+            self.words
+                .iter()
+                .map(|word| word.to_string())
+                .collect::<Vec<_>>()
+        } else {
+            self.operands()
+                .into_iter()
+                .map(|(mode, value)| format_operand(mode, value))
+                .collect::<Vec<_>>()
+        };
+
+        if operands.is_empty() {
+            self.opcode.mnemonic().to_string()
+        } else {
+            format!("{:<4} {}", self.opcode.mnemonic(), operands.join(", "))
+        }
+    }
+}
+
+fn format_operand(mode: ParamMode, value: i64) -> String {
+    match mode {
+        ParamMode::Immediate => format!("#{}", value),
+        ParamMode::Position => format!("[{}]", value),
+        ParamMode::Relative if value < 0 => format!("rb{}", value),
+        ParamMode::Relative => format!("rb+{}", value),
+    }
+}
+
+impl fmt::Display for Instruction {
+    /// Formats the instruction as a listing line: the address, the raw words
+    /// and then the same text as `to_asm`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let words = self
+            .words
+            .iter()
+            .map(|word| word.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        write!(f, "{:>6}: {:<32} {}", self.address, words, self.to_asm())
+    }
+}
+
+/// Disassembles the whole of `program`, starting at address 0.
+pub fn disassemble(program: &[i64]) -> Vec<Instruction> {
+    disassemble_from(program, 0)
+}
+
+/// Disassembles `program` with a linear sweep starting at `entry`.
+pub fn disassemble_from(program: &[i64], entry: usize) -> Vec<Instruction> {
+    let mut instructions = vec![];
+    let mut address = entry;
+
+    while let Some(instruction) = Instruction::decode(program, address) {
+        address = instruction.next_address();
+        instructions.push(instruction);
+    }
+
+    instructions
+}
+
+/// Like `disassemble_from`, but reads `memory` one instruction at a time and
+/// stops after `count` instructions, so sparse memory is never copied whole.
+pub fn disassemble_memory(memory: &dyn Memory, entry: usize, count: usize) -> Vec<Instruction> {
+    let mut instructions = vec![];
+    let mut address = entry;
+
+    while instructions.len() < count && address < memory.len() {
+        let end = (address + Opcode::MAX_SIZE).min(memory.len());
+        let mut instruction = Instruction::decode(&memory.read_range(address..end), 0).unwrap();
+        instruction.address = address;
+        address = instruction.next_address();
+        instructions.push(instruction);
+    }
+
+    instructions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{disassemble, disassemble_from, disassemble_memory};
+    use crate::intcode_vm::asm::assemble;
+    use crate::intcode_vm::memory::{Memory, PagedMemory};
+
+    #[test]
+    fn test_disassemble_round_trips_through_assembler() {
+        let program = vec![1102, 34, -2, 7, 204, -1, 99, 5];
+        let listing = disassemble(&program);
+
+        let source = listing
+            .iter()
+            .map(|instruction| instruction.to_asm())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        assert_eq!(assemble(&source).unwrap(), program);
+    }
+
+    #[test]
+    fn test_disassemble_marks_undecodable_words_as_data() {
+        let listing = disassemble(&[42, 1, 0, 0]);
+
+        assert!(listing[0].is_data());
+        assert_eq!(listing[0].to_asm(), "data 42");
+        assert!(listing[1].is_data());
+        assert_eq!(listing.len(), 4);
+    }
+
+    #[test]
+    fn test_disassemble_memory_reads_only_what_it_decodes() {
+        let program = vec![1102, 34, -2, 7, 204, -1, 99, 5];
+        let mut memory = PagedMemory::new();
+        memory.load(program.clone());
+        memory.write(1_000_000_000_000, 99).unwrap();
+
+        assert_eq!(
+            disassemble_memory(&memory, 4, 2),
+            disassemble_from(&program, 4)[..2]
+        );
+        let far = disassemble_memory(&memory, 1_000_000_000_000, 10);
+        assert_eq!(far.len(), 1);
+        assert_eq!(far[0].to_asm(), "hlt");
+    }
+}
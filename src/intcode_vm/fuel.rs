@@ -0,0 +1,330 @@
+//! Bounding how long a program runs.
+//!
+//! Every executed instruction costs fuel according to the VM's `CostTable`,
+//! and the total is metered whether or not the VM has a budget. A VM with a
+//! budget stops with `VMState::OutOfFuel` before running an instruction it
+//! can't afford, and a VM with a deadline stops with `VMState::TimedOut`
+//! once it has passed. Either way it can carry on from where it stopped
+//! once it has been given more fuel or time.
+
+use std::time::{Duration, Instant};
+
+use super::{IntcodeVM, Opcode, VMState};
+
+/// How many instructions the VM can run between checks of the clock,
+/// whatever they cost.
+const CLOCK_INTERVAL: u64 = 1 << 16;
+
+/// The fuel each opcode costs to execute, whatever its parameter modes.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct CostTable {
+    /// By opcode number, with halt at 0.
+    costs: [u64; 10],
+}
+
+impl Default for CostTable {
+    fn default() -> Self {
+        CostTable::uniform(1)
+    }
+}
+
+impl CostTable {
+    /// Every opcode costs `cost`, so fuel counts instructions.
+    pub fn uniform(cost: u64) -> Self {
+        CostTable { costs: [cost; 10] }
+    }
+
+    /// The costs by opcode number, with halt first, as snapshots store them.
+    pub(super) fn to_array(self) -> [u64; 10] {
+        self.costs
+    }
+
+    pub(super) fn from_array(costs: [u64; 10]) -> Self {
+        CostTable { costs }
+    }
+
+    fn index(opcode: i64) -> Option<usize> {
+        match opcode {
+            1..=9 => Some(opcode as usize),
+            99 => Some(0),
+            _ => None,
+        }
+    }
+
+    /// Sets the cost of the opcode numbered `opcode`.
+    ///
+    /// # Panics
+    ///
+    /// If `opcode` isn't a known opcode number.
+    pub fn set(&mut self, opcode: i64, cost: u64) {
+        match CostTable::index(opcode) {
+            Some(index) => self.costs[index] = cost,
+            None => panic!("unknown opcode {}", opcode),
+        }
+    }
+
+    /// `set`, for building tables.
+    pub fn with(mut self, opcode: i64, cost: u64) -> Self {
+        self.set(opcode, cost);
+        self
+    }
+
+    #[inline]
+    pub fn cost(&self, opcode: Opcode) -> u64 {
+        let index = match opcode {
+            Opcode::Add(..) => 1,
+            Opcode::Mul(..) => 2,
+            Opcode::Input(..) => 3,
+            Opcode::Output(..) => 4,
+            Opcode::JmpIfNotZero(..) => 5,
+            Opcode::JmpIfZero(..) => 6,
+            Opcode::LessThen(..) => 7,
+            Opcode::Equals(..) => 8,
+            Opcode::SetRB(..) => 9,
+            Opcode::Halt | Opcode::Invalid => 0,
+        };
+        self.costs[index]
+    }
+}
+
+/// A VM's budget and meter. The interpreter draws on `slice`, which holds
+/// fuel taken out of `budget`, and counts down `ticks`, and only comes back
+/// here when the slice can't cover an instruction or the ticks run out, so
+/// the clock is only checked once a slice and the meter is only updated
+/// once a slice.
+#[derive(Clone, Debug, Default)]
+pub(super) struct Fuel {
+    pub(super) costs: CostTable,
+    /// Fuel left, not counting the slice; `None` for no limit.
+    budget: Option<u64>,
+    pub(super) slice: u64,
+    /// Instructions left before the clock is checked again. Counted
+    /// separately from fuel so instructions that cost nothing still do.
+    pub(super) ticks: u64,
+    /// The size of the slice when it was taken.
+    taken: u64,
+    /// Fuel spent, not counting what's been spent of the slice.
+    used: u64,
+    deadline: Option<Instant>,
+}
+
+impl Fuel {
+    /// Rebuilds a meter from what `IntcodeVM::save_snapshot` records. The
+    /// deadline is saved as the time left until it, since an `Instant`
+    /// means nothing to another process.
+    pub(super) fn restore(
+        costs: CostTable,
+        budget: Option<u64>,
+        used: u64,
+        time_left: Option<Duration>,
+    ) -> Self {
+        Fuel {
+            costs,
+            budget,
+            used,
+            deadline: time_left.map(|time_left| Instant::now() + time_left),
+            ..Fuel::default()
+        }
+    }
+
+    pub(super) fn time_left(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    pub(super) fn remaining(&self) -> Option<u64> {
+        self.budget.map(|budget| budget + self.slice)
+    }
+
+    pub(super) fn used(&self) -> u64 {
+        self.used + (self.taken - self.slice)
+    }
+
+    /// Returns the slice to the budget.
+    fn settle(&mut self) {
+        self.budget = self.remaining();
+        self.used = self.used();
+        self.slice = 0;
+        self.ticks = 0;
+        self.taken = 0;
+    }
+
+    /// Takes a new slice that covers `cost`, or returns why it can't.
+    #[cold]
+    #[inline(never)]
+    pub(super) fn refill(&mut self, cost: u64) -> Option<VMState> {
+        self.settle();
+        if let Some(deadline) = self.deadline {
+            if Instant::now() >= deadline {
+                return Some(VMState::TimedOut);
+            }
+        }
+
+        let available = self.budget.unwrap_or(u64::MAX);
+        if cost > available {
+            return Some(VMState::OutOfFuel);
+        }
+        self.slice = available;
+        self.ticks = match self.deadline {
+            Some(_) => CLOCK_INTERVAL,
+            None => u64::MAX,
+        };
+        self.taken = self.slice;
+        self.budget = self.budget.map(|budget| budget - self.slice);
+        None
+    }
+
+    /// Pays for an instruction, or returns why it can't be paid for.
+    #[inline]
+    pub(super) fn charge(&mut self, cost: u64) -> Option<VMState> {
+        if cost > self.slice || self.ticks == 0 {
+            if let Some(state) = self.refill(cost) {
+                return Some(state);
+            }
+        }
+        self.slice -= cost;
+        self.ticks -= 1;
+        None
+    }
+}
+
+impl IntcodeVM {
+    pub fn costs(&self) -> &CostTable {
+        &self.fuel.costs
+    }
+
+    /// Sets what each opcode costs from now on.
+    pub fn set_costs(&mut self, costs: CostTable) {
+        self.fuel.costs = costs;
+        // Compiled blocks have their costs baked in.
+        self.code.clear();
+    }
+
+    /// The fuel left, or `None` if there's no limit.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel.remaining()
+    }
+
+    /// Limits execution to `fuel`, or lifts the limit for `None`.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel.settle();
+        self.fuel.budget = fuel;
+    }
+
+    /// Tops up the fuel left. Does nothing if there's no limit.
+    pub fn add_fuel(&mut self, fuel: u64) {
+        self.fuel.settle();
+        self.fuel.budget = self.fuel.budget.map(|budget| budget.saturating_add(fuel));
+    }
+
+    /// The fuel spent so far, limited or not.
+    pub fn fuel_used(&self) -> u64 {
+        self.fuel.used()
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.fuel.deadline
+    }
+
+    /// Stops execution once `deadline` has passed, or lifts the deadline
+    /// for `None`.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.fuel.settle();
+        self.fuel.deadline = deadline;
+    }
+
+    /// Sets a deadline `timeout` from now.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.set_deadline(Some(Instant::now() + timeout));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::CostTable;
+    use crate::intcode_vm::asm::assemble;
+    use crate::intcode_vm::Backend;
+    use crate::{IntcodeVM, VMState};
+
+    const BACKENDS: [Backend; 2] = [Backend::Interpreter, Backend::Compiled];
+
+    /// Counts down from 3, outputting each value.
+    const COUNTDOWN: &str = "loop: add [n], #-1, [n]
+                                   out [n]
+                                   jnz [n], #loop
+                                   hlt
+                             n:    data 3";
+
+    fn vm(source: &str, backend: Backend) -> IntcodeVM {
+        let mut vm = IntcodeVM::new();
+        vm.set_backend(backend);
+        vm.set_program(assemble(source).unwrap());
+        vm
+    }
+
+    #[test]
+    fn test_fuel_runs_out_and_resumes() {
+        for backend in BACKENDS.iter() {
+            let mut vm = vm(COUNTDOWN, *backend);
+            vm.set_fuel(Some(4));
+
+            // add, out; jnz, add, then the out can't be paid for.
+            assert_eq!(vm.execute().unwrap(), VMState::Output(2));
+            assert_eq!(vm.execute().unwrap(), VMState::OutOfFuel);
+            assert_eq!(vm.execute().unwrap(), VMState::OutOfFuel);
+            assert_eq!(vm.instruction_pointer(), 4);
+            assert_eq!(vm.fuel(), Some(0));
+
+            vm.add_fuel(100);
+            let mut outputs = vec![];
+            while let VMState::Output(value) = vm.execute().unwrap() {
+                outputs.push(value);
+            }
+            assert_eq!(outputs, vec![1, 0]);
+            assert_eq!(vm.fuel_used(), 10);
+            assert_eq!(vm.fuel(), Some(94));
+        }
+    }
+
+    #[test]
+    fn test_cost_table_meters_without_a_limit() {
+        for backend in BACKENDS.iter() {
+            let mut vm = vm(COUNTDOWN, *backend);
+            vm.set_costs(CostTable::uniform(1).with(1, 10).with(99, 0));
+
+            assert_eq!(vm.run_once().unwrap(), vec![2, 1, 0]);
+            assert_eq!(vm.fuel_used(), 3 * 10 + 3 + 3);
+            assert_eq!(vm.fuel(), None);
+        }
+    }
+
+    #[test]
+    fn test_infinite_loop_times_out() {
+        for backend in BACKENDS.iter() {
+            let mut vm = vm("loop: jz #0, #loop", *backend);
+            vm.set_timeout(Duration::from_millis(20));
+            assert_eq!(vm.execute().unwrap(), VMState::TimedOut);
+            assert!(vm.fuel_used() > 0);
+
+            vm.set_deadline(Some(Instant::now()));
+            assert_eq!(vm.execute().unwrap(), VMState::TimedOut);
+        }
+    }
+
+    #[test]
+    fn test_free_instructions_still_time_out() {
+        for backend in BACKENDS.iter() {
+            let mut vm = vm("loop: jz #0, #loop", *backend);
+            vm.set_costs(CostTable::uniform(1).with(6, 0));
+            vm.set_deadline(Some(Instant::now()));
+            assert_eq!(vm.execute().unwrap(), VMState::TimedOut);
+            assert_eq!(vm.fuel_used(), 0);
+
+            vm.set_timeout(Duration::from_millis(20));
+            assert_eq!(vm.execute().unwrap(), VMState::TimedOut);
+            assert_eq!(vm.fuel_used(), 0);
+        }
+    }
+}
@@ -0,0 +1,261 @@
+//! Adapters that drive an `IntcodeVM` from iterators, channels or custom
+//! devices instead of a hand-written `execute` loop.
+
+use std::collections::VecDeque;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use super::{IntcodeVM, VMState, VmError};
+
+/// Supplies values to a VM's input instructions.
+pub trait InputSource {
+    /// The next input, or `None` if there's nothing to give the VM right
+    /// now, which pauses it with `VMState::NeedsInput`.
+    fn next_input(&mut self) -> Option<i64>;
+}
+
+/// Receives a VM's outputs.
+pub trait OutputSink {
+    fn output(&mut self, value: i64);
+}
+
+impl<F: FnMut() -> Option<i64>> InputSource for F {
+    fn next_input(&mut self) -> Option<i64> {
+        self()
+    }
+}
+
+impl InputSource for VecDeque<i64> {
+    fn next_input(&mut self) -> Option<i64> {
+        self.pop_front()
+    }
+}
+
+/// Blocks until a value arrives; the VM pauses once every sender is gone.
+impl InputSource for Receiver<i64> {
+    fn next_input(&mut self) -> Option<i64> {
+        self.recv().ok()
+    }
+}
+
+impl<F: FnMut(i64)> OutputSink for F {
+    fn output(&mut self, value: i64) {
+        self(value)
+    }
+}
+
+impl OutputSink for Vec<i64> {
+    fn output(&mut self, value: i64) {
+        self.push(value)
+    }
+}
+
+impl OutputSink for VecDeque<i64> {
+    fn output(&mut self, value: i64) {
+        self.push_back(value)
+    }
+}
+
+/// Outputs sent after the receiver hangs up are dropped.
+impl OutputSink for Sender<i64> {
+    fn output(&mut self, value: i64) {
+        let _ = self.send(value);
+    }
+}
+
+/// An iterator over a VM's outputs, created by `IntcodeVM::outputs`.
+///
+/// It ends when the VM halts, needs input the inputs can't give, or
+/// faults; `state` tells which.
+#[derive(Debug)]
+pub struct Outputs<'a, I> {
+    vm: &'a mut IntcodeVM,
+    inputs: I,
+    state: Option<Result<VMState, VmError>>,
+}
+
+impl<'a, I> Outputs<'a, I> {
+    /// Why the iterator ended, or `None` while it hasn't.
+    pub fn state(&self) -> Option<Result<VMState, VmError>> {
+        self.state
+    }
+}
+
+impl<'a, I: Iterator<Item = i64>> Iterator for Outputs<'a, I> {
+    type Item = i64;
+
+    fn next(&mut self) -> Option<i64> {
+        if self.state.is_some() {
+            return None;
+        }
+
+        loop {
+            match self.vm.execute() {
+                Ok(VMState::Output(value)) => return Some(value),
+                Ok(VMState::NeedsInput) => match self.inputs.next() {
+                    Some(value) => self.vm.push_input(value),
+                    None => break,
+                },
+                state => {
+                    self.state = Some(state);
+                    return None;
+                }
+            }
+        }
+
+        self.state = Some(Ok(VMState::NeedsInput));
+        None
+    }
+}
+
+impl IntcodeVM {
+    /// Iterates over the outputs of the program, taking inputs from `inputs`
+    /// only as the program asks for them.
+    pub fn outputs<I: IntoIterator<Item = i64>>(&mut self, inputs: I) -> Outputs<'_, I::IntoIter> {
+        Outputs {
+            vm: self,
+            inputs: inputs.into_iter(),
+            state: None,
+        }
+    }
+
+    /// Runs the program, connected to `input` and `output`, until it halts,
+    /// `input` runs dry or it runs out of fuel or time.
+    pub fn run_with<I, O>(&mut self, input: &mut I, output: &mut O) -> Result<VMState, VmError>
+    where
+        I: InputSource + ?Sized,
+        O: OutputSink + ?Sized,
+    {
+        loop {
+            match self.execute()? {
+                VMState::Output(value) => output.output(value),
+                VMState::NeedsInput => match input.next_input() {
+                    Some(value) => self.push_input(value),
+                    None => return Ok(VMState::NeedsInput),
+                },
+                state => return Ok(state),
+            }
+        }
+    }
+
+    /// Runs the program on its own thread, reading inputs from `input` and
+    /// sending outputs to `output`. The thread ends when the program halts,
+    /// every input sender is dropped or the program runs out of fuel or
+    /// time, returning the VM and why it stopped.
+    pub fn spawn(
+        mut self,
+        mut input: Receiver<i64>,
+        mut output: Sender<i64>,
+    ) -> JoinHandle<(IntcodeVM, Result<VMState, VmError>)> {
+        thread::spawn(move || {
+            let state = self.run_with(&mut input, &mut output);
+            (self, state)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::sync::mpsc::channel;
+
+    use crate::input;
+    use crate::intcode_vm::asm::assemble;
+    use crate::{IntcodeVM, VMState, VmErrorKind};
+
+    /// Doubles every input until it reads a zero.
+    const DOUBLER: &str = "loop: in [value]
+                                 jz [value], #end
+                                 mul [value], #2, [value]
+                                 out [value]
+                                 jz #0, #loop
+                           end:  hlt
+                           value: data 0";
+
+    fn vm(program: Vec<i64>) -> IntcodeVM {
+        let mut vm = IntcodeVM::new();
+        vm.set_program(program);
+        vm
+    }
+
+    #[test]
+    fn test_outputs_pull_inputs_lazily() {
+        let mut vm = vm(assemble(DOUBLER).unwrap());
+
+        let mut outputs = vm.outputs(vec![1, 2, 3]);
+        assert_eq!(outputs.by_ref().take(2).collect::<Vec<_>>(), vec![2, 4]);
+        assert_eq!(outputs.state(), None);
+        assert_eq!(outputs.next(), Some(6));
+        assert_eq!(outputs.next(), None);
+        assert_eq!(outputs.state(), Some(Ok(VMState::NeedsInput)));
+
+        // The VM picks up where it left off.
+        assert_eq!(vm.outputs(vec![5, 0]).collect::<Vec<_>>(), vec![10]);
+    }
+
+    #[test]
+    fn test_outputs_stop_on_fault() {
+        let mut vm = vm(vec![104, 7, 42]);
+
+        let mut outputs = vm.outputs(vec![]);
+        assert_eq!(outputs.by_ref().collect::<Vec<_>>(), vec![7]);
+        assert_eq!(
+            outputs.state().unwrap().unwrap_err().kind,
+            VmErrorKind::UnknownOpcode(42)
+        );
+    }
+
+    #[test]
+    fn test_run_with_closures_and_queues() {
+        let mut vm = vm(assemble(DOUBLER).unwrap());
+        let mut inputs = VecDeque::from(vec![4, 5]);
+        let mut outputs = vec![];
+
+        assert_eq!(
+            vm.run_with(&mut inputs, &mut outputs).unwrap(),
+            VMState::NeedsInput
+        );
+        assert_eq!(outputs, vec![8, 10]);
+
+        let mut sum = 0;
+        let state = vm.run_with(&mut || Some(0), &mut |value| sum += value);
+        assert_eq!(state.unwrap(), VMState::Halted);
+        assert_eq!(sum, 0);
+    }
+
+    #[test]
+    fn test_feedback_loop_on_threads() {
+        let program = input::parse_intcode(include_str!("../../inputs/day07_test.txt")).unwrap();
+        let phases = [9, 8, 7, 6, 5];
+
+        // Each amp reads the channel the one before it writes to, starting
+        // with its phase setting.
+        let (first, mut receiver) = channel();
+        first.send(phases[0]).unwrap();
+        first.send(0).unwrap();
+
+        let mut handles = vec![];
+        for index in 0..phases.len() {
+            let (sender, next) = channel();
+            if let Some(phase) = phases.get(index + 1) {
+                sender.send(*phase).unwrap();
+            }
+            handles.push(vm(program.clone()).spawn(receiver, sender));
+            receiver = next;
+        }
+
+        // Feed the last amp back into the first until it halts.
+        let mut signal = 0;
+        for value in receiver {
+            signal = value;
+            let _ = first.send(value);
+        }
+        assert_eq!(signal, 139629729);
+
+        let states = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap().1.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(states, vec![VMState::Halted; 5]);
+    }
+}
@@ -0,0 +1,1082 @@
+use std::collections::HashMap;
+
+use super::lexer::tokenize;
+use super::parser::{parse, Expr, ExprKind, Function, Global, Init, Program, Stmt, StmtKind, Type};
+use super::{CompileError, CompileErrorKind};
+
+/// Functions the compiler provides for the rest of the language.
+/// Division truncates towards zero, like Rust's, and is long division by
+/// doubling, since Intcode can only multiply and compare.
+const PRELUDE: &str = "
+fn __divmod(a: int, b: int, remainder: bool) -> int {
+    if b == 0 {
+        __trap();
+    }
+    let negative_a = a < 0;
+    let negative_b = b < 0;
+    if negative_a {
+        a = -a;
+    }
+    if negative_b {
+        b = -b;
+    }
+
+    let quotient = 0;
+    while b <= a {
+        let step = b;
+        let multiple = 1;
+        while step <= a - step {
+            step = step * 2;
+            multiple = multiple * 2;
+        }
+        a = a - step;
+        quotient = quotient + multiple;
+    }
+
+    if remainder {
+        if negative_a {
+            return -a;
+        }
+        return a;
+    }
+    if negative_a != negative_b {
+        return -quotient;
+    }
+    return quotient;
+}
+
+fn __div(a: int, b: int) -> int {
+    return __divmod(a, b, false);
+}
+
+fn __mod(a: int, b: int) -> int {
+    return __divmod(a, b, true);
+}
+";
+
+/// Calls the compiler turns into instructions. `__trap` faults the VM.
+const BUILTINS: [&str; 4] = ["input", "output", "len", "__trap"];
+
+/// Calls `main` with the stack starting past the end of the program.
+const ENTRY: &str = "    arb  #stack
+    add  #exit, #0, rb+0
+    jz   #0, #f_main
+exit:
+    hlt
+";
+
+/// The longest run of slots `fill` stores without a loop.
+const UNROLLED_FILL: usize = 8;
+
+/// Scratch words for indexing and filling local arrays, which moves the relative base
+/// while the frame is addressed through it.
+const SCRATCH: &str = "
+r_idx:
+    data 0
+r_nidx:
+    data 0
+r_val:
+    data 0
+";
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Operand {
+    Imm(i64),
+    /// A label's address, as an immediate.
+    Addr(String),
+    /// The word at a label plus an offset.
+    Mem(String, i64),
+    /// The word at an address the code patches before using it.
+    Abs(i64),
+    /// A slot of the current frame.
+    Slot(usize),
+    /// A word past the current frame, which becomes slot `k` of the next
+    /// call's frame.
+    Out(usize),
+}
+
+impl Operand {
+    fn render(&self, frame_size: usize) -> String {
+        match self {
+            Operand::Imm(value) => format!("#{}", value),
+            Operand::Addr(label) => format!("#{}", label),
+            Operand::Mem(label, 0) => format!("[{}]", label),
+            Operand::Mem(label, offset) => format!("[{}+{}]", label, offset),
+            Operand::Abs(address) => format!("[{}]", address),
+            Operand::Slot(slot) => format!("rb-{}", frame_size - slot),
+            Operand::Out(offset) => format!("rb+{}", offset),
+        }
+    }
+}
+
+/// A line of a function's code. Slots can only be rendered once the frame
+/// size is known.
+enum Line {
+    Label(String),
+    Op(&'static str, Vec<Operand>),
+}
+
+#[derive(Clone, Debug)]
+enum Place {
+    /// The first slot of a local.
+    Local(usize),
+    /// The label of a global.
+    Global(String),
+}
+
+#[derive(Clone, Debug)]
+struct Variable {
+    ty: Type,
+    place: Place,
+}
+
+impl Variable {
+    /// The word holding a scalar, for `0`, or an array's element.
+    fn element(&self, index: usize) -> Operand {
+        match &self.place {
+            Place::Local(base) => Operand::Slot(base + index),
+            Place::Global(label) => Operand::Mem(label.clone(), index as i64),
+        }
+    }
+
+    fn array(&self, name: &str, line: usize) -> Result<(Type, usize), CompileError> {
+        match &self.ty {
+            Type::Array(element, length) => Ok(((**element).clone(), *length)),
+            _ => Err(CompileError {
+                line,
+                kind: CompileErrorKind::NotAnArray(name.to_string()),
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Signature {
+    params: Vec<Type>,
+    ret: Type,
+}
+
+fn size(ty: &Type) -> usize {
+    match ty {
+        Type::Array(_, length) => *length,
+        _ => 1,
+    }
+}
+
+fn check_index(index: i64, length: usize, line: usize) -> Result<usize, CompileError> {
+    match index {
+        index if index >= 0 && (index as usize) < length => Ok(index as usize),
+        index => Err(CompileError {
+            line,
+            kind: CompileErrorKind::IndexOutOfBounds { index, length },
+        }),
+    }
+}
+
+/// Evaluates a binary operator on constants. `i64::MIN` isn't folded, as
+/// the assembler can't read it back.
+fn fold(op: &str, a: i64, b: i64) -> Option<i64> {
+    let value = match op {
+        "+" => a.checked_add(b)?,
+        "-" => a.checked_sub(b)?,
+        "*" => a.checked_mul(b)?,
+        "<" => (a < b) as i64,
+        "<=" => (a <= b) as i64,
+        ">" => (a > b) as i64,
+        ">=" => (a >= b) as i64,
+        "==" => (a == b) as i64,
+        "!=" => (a != b) as i64,
+        _ => return None,
+    };
+    Some(value).filter(|&value| value != i64::MIN)
+}
+
+/// The value of a global's initialiser, which must be a literal.
+fn constant(expr: &Expr) -> Option<(i64, Type)> {
+    match &expr.kind {
+        ExprKind::Int(value) => Some((*value, Type::Int)),
+        ExprKind::Bool(value) => Some((*value as i64, Type::Bool)),
+        ExprKind::Unary("-", operand) => match operand.kind {
+            ExprKind::Int(value) => Some((-value, Type::Int)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The type of a `let` from its declared type and the types of its
+/// initialiser's values.
+fn binding_type(
+    name: &str,
+    declared: Option<&Type>,
+    init: Option<&Init>,
+    found: &[Type],
+) -> Result<Type, CompileErrorKind> {
+    let ty = match init {
+        None => declared
+            .cloned()
+            .ok_or_else(|| CompileErrorKind::CannotInfer(name.to_string()))?,
+        Some(Init::Expr(_)) => found[0].clone(),
+        Some(Init::List(_)) => {
+            let element = match (found.first(), declared) {
+                (Some(element), _) => element.clone(),
+                (None, Some(Type::Array(element, _))) => (**element).clone(),
+                (None, _) => return Err(CompileErrorKind::CannotInfer(name.to_string())),
+            };
+            if let Some(other) = found.iter().find(|ty| **ty != element) {
+                return Err(CompileErrorKind::TypeMismatch {
+                    expected: element,
+                    found: other.clone(),
+                });
+            }
+            Type::Array(Box::new(element), found.len())
+        }
+        Some(Init::Repeat(_, length)) => Type::Array(Box::new(found[0].clone()), *length),
+    };
+
+    match declared {
+        Some(declared) if *declared != ty => Err(CompileErrorKind::TypeMismatch {
+            expected: declared.clone(),
+            found: ty,
+        }),
+        _ => Ok(ty),
+    }
+}
+
+/// Whether running `statements` always ends in a `return`.
+fn returns(statements: &[Stmt]) -> bool {
+    statements.iter().any(|statement| match &statement.kind {
+        StmtKind::Return(_) => true,
+        StmtKind::If {
+            then, otherwise, ..
+        } => returns(then) && returns(otherwise),
+        StmtKind::While { condition, body } => {
+            matches!(condition.kind, ExprKind::Bool(true)) && !breaks(body)
+        }
+        _ => false,
+    })
+}
+
+/// Whether `statements` can break out of the loop they're in.
+fn breaks(statements: &[Stmt]) -> bool {
+    statements.iter().any(|statement| match &statement.kind {
+        StmtKind::Break => true,
+        StmtKind::If {
+            then, otherwise, ..
+        } => breaks(then) || breaks(otherwise),
+        _ => false,
+    })
+}
+
+/// Compiles `program`, along with the parts of the prelude it uses, to
+/// assembly.
+pub(super) fn generate(program: &Program) -> Result<String, CompileError> {
+    let prelude = parse(tokenize(PRELUDE)?, true)?;
+    let mut codegen = Codegen {
+        functions: HashMap::new(),
+        globals: HashMap::new(),
+        labels: 0,
+        divides: false,
+        lines: vec![],
+        scopes: vec![],
+        next_slot: 0,
+        frame_size: 0,
+        loops: vec![],
+        ret: Type::Unit,
+        epilogue: String::new(),
+    };
+
+    let mut data = String::new();
+    for global in &program.globals {
+        data += &codegen.global(global)?;
+    }
+    for function in prelude.functions.iter().chain(&program.functions) {
+        codegen.declare(function)?;
+    }
+    match codegen.functions.get("main") {
+        Some(main) if main.params.is_empty() && main.ret == Type::Unit => {}
+        _ => {
+            let main = program.functions.iter().find(|f| f.name == "main");
+            return Err(CompileError {
+                line: main.map_or(1, |main| main.line),
+                kind: CompileErrorKind::NoMain,
+            });
+        }
+    }
+
+    let mut asm = String::from(ENTRY);
+    for function in &program.functions {
+        asm += &codegen.function(function)?;
+    }
+    if codegen.divides {
+        for function in &prelude.functions {
+            asm += &codegen.function(function)?;
+        }
+    }
+    asm += SCRATCH;
+    asm += &data;
+    // The stack grows up from here.
+    asm += "stack:\n    data 0\n";
+    Ok(asm)
+}
+
+struct Codegen {
+    functions: HashMap<String, Signature>,
+    globals: HashMap<String, Variable>,
+    labels: usize,
+    /// Whether the program divides, and so needs the prelude.
+    divides: bool,
+
+    // The function being compiled.
+    lines: Vec<Line>,
+    scopes: Vec<HashMap<String, Variable>>,
+    /// The first slot no local or temporary uses.
+    next_slot: usize,
+    /// The most slots used so far.
+    frame_size: usize,
+    /// The `continue` and `break` labels of the loops around the code.
+    loops: Vec<(String, String)>,
+    ret: Type,
+    epilogue: String,
+}
+
+impl Codegen {
+    fn label(&mut self) -> String {
+        self.labels += 1;
+        format!("l{}", self.labels)
+    }
+
+    fn op(&mut self, mnemonic: &'static str, operands: Vec<Operand>) {
+        self.lines.push(Line::Op(mnemonic, operands));
+    }
+
+    fn jump(&mut self, label: &str) {
+        self.op(
+            "jz",
+            vec![Operand::Imm(0), Operand::Addr(label.to_string())],
+        );
+    }
+
+    fn alloc(&mut self, slots: usize) -> usize {
+        let base = self.next_slot;
+        self.next_slot += slots;
+        self.frame_size = self.frame_size.max(self.next_slot);
+        base
+    }
+
+    fn temp(&mut self) -> Operand {
+        Operand::Slot(self.alloc(1))
+    }
+
+    /// Runs `mnemonic` on `a` and `b`, storing into `dest` or a temporary.
+    fn compute(
+        &mut self,
+        mnemonic: &'static str,
+        a: Operand,
+        b: Operand,
+        dest: Option<Operand>,
+    ) -> Operand {
+        let result = dest.unwrap_or_else(|| self.temp());
+        self.op(mnemonic, vec![a, b, result.clone()]);
+        result
+    }
+
+    /// Moves `value` to `dest`, if there is one.
+    fn finish(&mut self, value: Operand, dest: Option<Operand>) -> Operand {
+        match dest {
+            Some(dest) if dest != value => self.compute("add", value, Operand::Imm(0), Some(dest)),
+            _ => value,
+        }
+    }
+
+    fn global(&mut self, global: &Global) -> Result<String, CompileError> {
+        let error = |kind| CompileError {
+            line: global.line,
+            kind,
+        };
+        let name = &global.name;
+        let not_constant = || error(CompileErrorKind::NotConstant(name.clone()));
+
+        let mut values = vec![];
+        let mut found = vec![];
+        match &global.init {
+            None => {}
+            Some(Init::Expr(expr)) | Some(Init::Repeat(expr, _)) => {
+                let (value, ty) = constant(expr).ok_or_else(not_constant)?;
+                values.push(value);
+                found.push(ty);
+            }
+            Some(Init::List(elements)) => {
+                for element in elements {
+                    let (value, ty) = constant(element).ok_or_else(not_constant)?;
+                    values.push(value);
+                    found.push(ty);
+                }
+            }
+        }
+        let ty =
+            binding_type(name, global.ty.as_ref(), global.init.as_ref(), &found).map_err(error)?;
+        match &global.init {
+            None => values = vec![0; size(&ty)],
+            Some(Init::Repeat(_, length)) => values = vec![values[0]; *length],
+            _ => {}
+        }
+
+        if self.globals.contains_key(name) {
+            return Err(error(CompileErrorKind::AlreadyDefined(name.clone())));
+        }
+        let label = format!("g_{}", name);
+        let mut data = format!("{}:\n", label);
+        if !values.is_empty() {
+            let values = values.iter().map(i64::to_string).collect::<Vec<_>>();
+            data += &format!("    data {}\n", values.join(", "));
+        }
+        self.globals.insert(
+            name.clone(),
+            Variable {
+                ty,
+                place: Place::Global(label),
+            },
+        );
+        Ok(data)
+    }
+
+    fn declare(&mut self, function: &Function) -> Result<(), CompileError> {
+        let error = |kind| CompileError {
+            line: function.line,
+            kind,
+        };
+        let name = &function.name;
+        if BUILTINS.contains(&name.as_str()) || self.functions.contains_key(name) {
+            return Err(error(CompileErrorKind::AlreadyDefined(name.clone())));
+        }
+        if let Some((param, _)) = function
+            .params
+            .iter()
+            .find(|(_, ty)| matches!(ty, Type::Array(..)))
+        {
+            return Err(error(CompileErrorKind::ArrayParameter(param.clone())));
+        }
+
+        self.functions.insert(
+            name.clone(),
+            Signature {
+                params: function.params.iter().map(|(_, ty)| ty.clone()).collect(),
+                ret: function.ret.clone(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Compiles a function: `arb` to allocate its frame, the body, then the
+    /// epilogue that frees the frame and returns through slot 0.
+    fn function(&mut self, function: &Function) -> Result<String, CompileError> {
+        let mut params = HashMap::new();
+        for (slot, (name, ty)) in function.params.iter().enumerate() {
+            let variable = Variable {
+                ty: ty.clone(),
+                place: Place::Local(slot + 1),
+            };
+            if params.insert(name.clone(), variable).is_some() {
+                return Err(CompileError {
+                    line: function.line,
+                    kind: CompileErrorKind::AlreadyDefined(name.clone()),
+                });
+            }
+        }
+
+        // Slot 1 takes the result even if there are no parameters.
+        self.scopes = vec![params];
+        self.next_slot = (function.params.len() + 1).max(2);
+        self.frame_size = self.next_slot;
+        self.lines.clear();
+        self.loops.clear();
+        self.ret = function.ret.clone();
+        self.epilogue = self.label();
+
+        self.block(&function.body)?;
+        if function.ret != Type::Unit && !returns(&function.body) {
+            return Err(CompileError {
+                line: function.line,
+                kind: CompileErrorKind::MissingReturn(function.name.clone()),
+            });
+        }
+        // A final `return` doesn't need to jump over nothing.
+        let epilogue = self.epilogue.clone();
+        if let Some(Line::Op("jz", operands)) = self.lines.last() {
+            if *operands == [Operand::Imm(0), Operand::Addr(epilogue.clone())] {
+                self.lines.pop();
+            }
+        }
+
+        let frame_size = self.frame_size;
+        let mut asm = format!("\nf_{}:\n    arb  #{}\n", function.name, frame_size);
+        for line in self.lines.drain(..) {
+            match line {
+                Line::Label(label) => asm += &format!("{}:\n", label),
+                Line::Op(mnemonic, operands) => {
+                    let operands = operands
+                        .iter()
+                        .map(|operand| operand.render(frame_size))
+                        .collect::<Vec<_>>();
+                    asm += &format!("    {:<4} {}\n", mnemonic, operands.join(", "));
+                }
+            }
+        }
+        asm += &format!(
+            "{}:\n    arb  #-{}\n    jz   #0, rb+0\n",
+            epilogue, frame_size
+        );
+        Ok(asm)
+    }
+
+    fn variable(&self, name: &str, line: usize) -> Result<Variable, CompileError> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .or_else(|| self.globals.get(name))
+            .cloned()
+            .ok_or_else(|| CompileError {
+                line,
+                kind: CompileErrorKind::Undefined(name.to_string()),
+            })
+    }
+
+    /// Compiles `statements` in a scope of their own, whose locals are freed
+    /// at the end.
+    fn block(&mut self, statements: &[Stmt]) -> Result<(), CompileError> {
+        let next_slot = self.next_slot;
+        self.scopes.push(HashMap::new());
+        for statement in statements {
+            self.statement(statement)?;
+        }
+        self.scopes.pop();
+        self.next_slot = next_slot;
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Stmt) -> Result<(), CompileError> {
+        let line = statement.line;
+        let error = |kind| CompileError { line, kind };
+        let next_slot = self.next_slot;
+
+        match &statement.kind {
+            StmtKind::Let { name, ty, init } => {
+                return self.binding(name, ty.as_ref(), init.as_ref(), line)
+            }
+            StmtKind::Assign {
+                name,
+                index: None,
+                value,
+            } => {
+                let variable = self.variable(name, line)?;
+                if let Type::Array(..) = variable.ty {
+                    return Err(error(CompileErrorKind::ArrayValue(name.clone())));
+                }
+                self.typed(value, &variable.ty, Some(variable.element(0)))?;
+            }
+            StmtKind::Assign {
+                name,
+                index: Some(index),
+                value,
+            } => self.store(name, index, value, line)?,
+            StmtKind::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                let condition = self.typed(condition, &Type::Bool, None)?;
+                let otherwise_label = self.label();
+                self.op(
+                    "jz",
+                    vec![condition, Operand::Addr(otherwise_label.clone())],
+                );
+                self.block(then)?;
+                if otherwise.is_empty() {
+                    self.lines.push(Line::Label(otherwise_label));
+                } else {
+                    let end = self.label();
+                    self.jump(&end);
+                    self.lines.push(Line::Label(otherwise_label));
+                    self.block(otherwise)?;
+                    self.lines.push(Line::Label(end));
+                }
+            }
+            StmtKind::While { condition, body } => {
+                let (start, end) = (self.label(), self.label());
+                self.lines.push(Line::Label(start.clone()));
+                let condition = self.typed(condition, &Type::Bool, None)?;
+                self.op("jz", vec![condition, Operand::Addr(end.clone())]);
+                self.loops.push((start.clone(), end.clone()));
+                self.block(body)?;
+                self.loops.pop();
+                self.jump(&start);
+                self.lines.push(Line::Label(end));
+            }
+            StmtKind::Return(value) => {
+                match (value, self.ret.clone()) {
+                    (None, Type::Unit) => {}
+                    (None, ret) => {
+                        return Err(error(CompileErrorKind::TypeMismatch {
+                            expected: ret,
+                            found: Type::Unit,
+                        }))
+                    }
+                    (Some(value), Type::Unit) => {
+                        let (_, found) = self.value(value, None)?;
+                        return Err(error(CompileErrorKind::TypeMismatch {
+                            expected: Type::Unit,
+                            found,
+                        }));
+                    }
+                    (Some(value), ret) => {
+                        self.typed(value, &ret, Some(Operand::Slot(1)))?;
+                    }
+                }
+                let epilogue = self.epilogue.clone();
+                self.jump(&epilogue);
+            }
+            StmtKind::Break | StmtKind::Continue => {
+                let keyword = match statement.kind {
+                    StmtKind::Break => "break",
+                    _ => "continue",
+                };
+                let (start, end) = self
+                    .loops
+                    .last()
+                    .cloned()
+                    .ok_or_else(|| error(CompileErrorKind::OutsideLoop(keyword)))?;
+                self.jump(if keyword == "break" { &end } else { &start });
+            }
+            StmtKind::Expr(expr) => {
+                self.expr(expr, None)?;
+            }
+        }
+
+        // Free the statement's temporaries.
+        self.next_slot = next_slot;
+        Ok(())
+    }
+
+    /// Compiles a local `let`. The variable's slots are allocated first, so
+    /// the initialiser can be evaluated straight into them, but the name is
+    /// only bound afterwards.
+    fn binding(
+        &mut self,
+        name: &str,
+        declared: Option<&Type>,
+        init: Option<&Init>,
+        line: usize,
+    ) -> Result<(), CompileError> {
+        let slots = match (declared, init) {
+            (_, Some(Init::List(elements))) => elements.len(),
+            (_, Some(Init::Repeat(_, length))) => *length,
+            (Some(ty), _) => size(ty),
+            (None, _) => 1,
+        };
+        let base = self.alloc(slots);
+
+        let mut found = vec![];
+        match init {
+            None => self.fill(base, slots, Operand::Imm(0)),
+            Some(Init::Expr(expr)) => found.push(self.value(expr, Some(Operand::Slot(base)))?.1),
+            Some(Init::List(elements)) => {
+                for (slot, element) in (base..).zip(elements) {
+                    found.push(self.value(element, Some(Operand::Slot(slot)))?.1);
+                }
+            }
+            Some(Init::Repeat(expr, length)) => {
+                let (value, ty) = self.value(expr, None)?;
+                self.fill(base, *length, value);
+                found.push(ty);
+            }
+        }
+        let ty = binding_type(name, declared, init, &found)
+            .map_err(|kind| CompileError { line, kind })?;
+
+        self.next_slot = base + slots;
+        self.scopes.last_mut().unwrap().insert(
+            name.to_string(),
+            Variable {
+                ty,
+                place: Place::Local(base),
+            },
+        );
+        Ok(())
+    }
+
+    /// Stores `value` in `length` slots from `base`. Short runs are stored
+    /// one by one; longer ones in a loop that moves the relative base along
+    /// them, so the code doesn't grow with the array.
+    fn fill(&mut self, base: usize, length: usize, value: Operand) {
+        if length <= UNROLLED_FILL {
+            for slot in base..base + length {
+                self.op(
+                    "add",
+                    vec![value.clone(), Operand::Imm(0), Operand::Slot(slot)],
+                );
+            }
+            return;
+        }
+
+        let (idx, val) = (
+            Operand::Mem("r_idx".into(), 0),
+            Operand::Mem("r_val".into(), 0),
+        );
+        self.op("add", vec![value, Operand::Imm(0), val.clone()]);
+        self.op(
+            "add",
+            vec![Operand::Imm(length as i64), Operand::Imm(0), idx.clone()],
+        );
+        let start = self.label();
+        self.lines.push(Line::Label(start.clone()));
+        self.op("add", vec![val, Operand::Imm(0), Operand::Slot(base)]);
+        self.op("arb", vec![Operand::Imm(1)]);
+        self.op("add", vec![idx.clone(), Operand::Imm(-1), idx.clone()]);
+        self.op("jnz", vec![idx, Operand::Addr(start)]);
+        self.op("arb", vec![Operand::Imm(-(length as i64))]);
+    }
+
+    /// Points the relative base `index` words further on, so the frame's
+    /// slots address the elements `index` past them. `[r_nidx]` moves it
+    /// back.
+    fn shift(&mut self, index: Operand) {
+        let (idx, nidx) = (
+            Operand::Mem("r_idx".into(), 0),
+            Operand::Mem("r_nidx".into(), 0),
+        );
+        self.op("add", vec![index.clone(), Operand::Imm(0), idx.clone()]);
+        self.op("mul", vec![index, Operand::Imm(-1), nidx]);
+        self.op("arb", vec![idx]);
+    }
+
+    fn load(
+        &mut self,
+        name: &str,
+        index: &Expr,
+        dest: Option<Operand>,
+        line: usize,
+    ) -> Result<(Operand, Type), CompileError> {
+        let variable = self.variable(name, line)?;
+        let (element, length) = variable.array(name, line)?;
+        let index = self.typed(index, &Type::Int, None)?;
+
+        let value = match (index, &variable.place) {
+            (Operand::Imm(index), _) => {
+                let index = check_index(index, length, line)?;
+                self.finish(variable.element(index), dest)
+            }
+            (index, Place::Local(base)) => {
+                let val = Operand::Mem("r_val".into(), 0);
+                self.shift(index);
+                self.op(
+                    "add",
+                    vec![Operand::Slot(*base), Operand::Imm(0), val.clone()],
+                );
+                self.op("arb", vec![Operand::Mem("r_nidx".into(), 0)]);
+                self.compute("add", val, Operand::Imm(0), dest)
+            }
+            // Globals have fixed addresses, so patch the address into the
+            // instruction that reads it.
+            (index, Place::Global(label)) => {
+                let patch = self.label();
+                self.op(
+                    "add",
+                    vec![
+                        Operand::Addr(label.clone()),
+                        index,
+                        Operand::Mem(patch.clone(), 1),
+                    ],
+                );
+                self.lines.push(Line::Label(patch));
+                self.compute("add", Operand::Abs(0), Operand::Imm(0), dest)
+            }
+        };
+        Ok((value, element))
+    }
+
+    fn store(
+        &mut self,
+        name: &str,
+        index: &Expr,
+        value: &Expr,
+        line: usize,
+    ) -> Result<(), CompileError> {
+        let variable = self.variable(name, line)?;
+        let (element, length) = variable.array(name, line)?;
+        let mut index = self.typed(index, &Type::Int, None)?;
+        if matches!(index, Operand::Mem(..)) && value.has_call() {
+            index = self.compute("add", index, Operand::Imm(0), None);
+        }
+        let value = self.typed(value, &element, None)?;
+
+        match (index, &variable.place) {
+            (Operand::Imm(index), _) => {
+                let index = check_index(index, length, line)?;
+                self.op("add", vec![value, Operand::Imm(0), variable.element(index)]);
+            }
+            (index, Place::Local(base)) => {
+                let val = Operand::Mem("r_val".into(), 0);
+                self.op("add", vec![value, Operand::Imm(0), val.clone()]);
+                self.shift(index);
+                self.op("add", vec![val, Operand::Imm(0), Operand::Slot(*base)]);
+                self.op("arb", vec![Operand::Mem("r_nidx".into(), 0)]);
+            }
+            (index, Place::Global(label)) => {
+                let patch = self.label();
+                self.op(
+                    "add",
+                    vec![
+                        Operand::Addr(label.clone()),
+                        index,
+                        Operand::Mem(patch.clone(), 3),
+                    ],
+                );
+                self.lines.push(Line::Label(patch));
+                self.op("add", vec![value, Operand::Imm(0), Operand::Abs(0)]);
+            }
+        }
+        Ok(())
+    }
+
+    fn call(
+        &mut self,
+        name: &str,
+        args: &[Expr],
+        dest: Option<Operand>,
+        line: usize,
+    ) -> Result<(Operand, Type), CompileError> {
+        let error = |kind| CompileError { line, kind };
+        let params = match name {
+            "input" | "__trap" => vec![],
+            "output" => vec![Type::Int],
+            // Checked below, as `len` takes any array.
+            "len" => vec![Type::Unit],
+            _ => self
+                .functions
+                .get(name)
+                .ok_or_else(|| error(CompileErrorKind::Undefined(name.to_string())))?
+                .params
+                .clone(),
+        };
+        if args.len() != params.len() {
+            return Err(error(CompileErrorKind::ArgumentCount {
+                function: name.to_string(),
+                expected: params.len(),
+                found: args.len(),
+            }));
+        }
+
+        match name {
+            "input" => {
+                let result = dest.unwrap_or_else(|| self.temp());
+                self.op("in", vec![result.clone()]);
+                return Ok((result, Type::Int));
+            }
+            "output" => {
+                let value = self.typed(&args[0], &Type::Int, None)?;
+                self.op("out", vec![value]);
+                return Ok((Operand::Imm(0), Type::Unit));
+            }
+            "len" => {
+                let length = match &args[0].kind {
+                    ExprKind::Var(array) => self.variable(array, line)?.array(array, line)?.1,
+                    _ => {
+                        let (_, found) = self.value(&args[0], None)?;
+                        return Err(error(CompileErrorKind::NotAnArray(found.to_string())));
+                    }
+                };
+                return Ok((self.finish(Operand::Imm(length as i64), dest), Type::Int));
+            }
+            "__trap" => {
+                self.op("jz", vec![Operand::Imm(0), Operand::Imm(-1)]);
+                return Ok((Operand::Imm(0), Type::Unit));
+            }
+            _ => {}
+        }
+
+        let mut operands = vec![];
+        for (position, (arg, ty)) in args.iter().zip(&params).enumerate() {
+            let mut operand = self.typed(arg, ty, None)?;
+            // A later call could change the global.
+            if matches!(operand, Operand::Mem(..))
+                && args[position + 1..].iter().any(Expr::has_call)
+            {
+                operand = self.compute("add", operand, Operand::Imm(0), None);
+            }
+            operands.push(operand);
+        }
+        for (slot, operand) in (1..).zip(operands) {
+            self.op("add", vec![operand, Operand::Imm(0), Operand::Out(slot)]);
+        }
+        let ret = self.label();
+        self.op(
+            "add",
+            vec![Operand::Addr(ret.clone()), Operand::Imm(0), Operand::Out(0)],
+        );
+        self.jump(&format!("f_{}", name));
+        self.lines.push(Line::Label(ret));
+
+        match self.functions[name].ret.clone() {
+            Type::Unit => Ok((Operand::Imm(0), Type::Unit)),
+            ret => Ok((
+                self.compute("add", Operand::Out(1), Operand::Imm(0), dest),
+                ret,
+            )),
+        }
+    }
+
+    /// Compiles `expr`, returning the operand holding its value and its
+    /// type. With a `dest`, the value ends up there.
+    fn expr(
+        &mut self,
+        expr: &Expr,
+        dest: Option<Operand>,
+    ) -> Result<(Operand, Type), CompileError> {
+        let line = expr.line;
+        let error = |kind| CompileError { line, kind };
+
+        match &expr.kind {
+            ExprKind::Int(value) => Ok((self.finish(Operand::Imm(*value), dest), Type::Int)),
+            ExprKind::Bool(value) => {
+                Ok((self.finish(Operand::Imm(*value as i64), dest), Type::Bool))
+            }
+            ExprKind::Var(name) => {
+                let variable = self.variable(name, line)?;
+                if let Type::Array(..) = variable.ty {
+                    return Err(error(CompileErrorKind::ArrayValue(name.clone())));
+                }
+                Ok((self.finish(variable.element(0), dest), variable.ty))
+            }
+            ExprKind::Index(name, index) => self.load(name, index, dest, line),
+            ExprKind::Call(name, args) => self.call(name, args, dest, line),
+            ExprKind::Unary(op, operand) => {
+                let ty = if *op == "-" { Type::Int } else { Type::Bool };
+                let value = match (*op, self.typed(operand, &ty, None)?) {
+                    ("-", Operand::Imm(value)) if value != i64::MIN => {
+                        self.finish(Operand::Imm(-value), dest)
+                    }
+                    ("!", Operand::Imm(value)) => {
+                        self.finish(Operand::Imm((value == 0) as i64), dest)
+                    }
+                    ("-", value) => self.compute("mul", value, Operand::Imm(-1), dest),
+                    (_, value) => self.compute("eq", value, Operand::Imm(0), dest),
+                };
+                Ok((value, ty))
+            }
+            ExprKind::Binary(op @ ("&&" | "||"), left, right) => {
+                // Only evaluate the right side if the left doesn't decide.
+                let result = self.temp();
+                self.typed(left, &Type::Bool, Some(result.clone()))?;
+                let end = self.label();
+                let skip = if *op == "&&" { "jz" } else { "jnz" };
+                self.op(skip, vec![result.clone(), Operand::Addr(end.clone())]);
+                self.typed(right, &Type::Bool, Some(result.clone()))?;
+                self.lines.push(Line::Label(end));
+                Ok((self.finish(result, dest), Type::Bool))
+            }
+            ExprKind::Binary(op @ ("/" | "%"), left, right) => {
+                self.divides = true;
+                let function = if *op == "/" { "__div" } else { "__mod" };
+                self.call(function, &[(**left).clone(), (**right).clone()], dest, line)
+            }
+            ExprKind::Binary(op, left, right) => {
+                let (mut a, left_type) = self.value(left, None)?;
+                // `==` and `!=` compare any two values of the same type.
+                let operand_type = match *op {
+                    "==" | "!=" => left_type.clone(),
+                    _ => Type::Int,
+                };
+                if left_type != operand_type {
+                    return Err(CompileError {
+                        line: left.line,
+                        kind: CompileErrorKind::TypeMismatch {
+                            expected: operand_type,
+                            found: left_type,
+                        },
+                    });
+                }
+                if matches!(a, Operand::Mem(..)) && right.has_call() {
+                    a = self.compute("add", a, Operand::Imm(0), None);
+                }
+                let b = self.typed(right, &operand_type, None)?;
+                let ty = match *op {
+                    "+" | "-" | "*" => Type::Int,
+                    _ => Type::Bool,
+                };
+
+                if let (Operand::Imm(a), Operand::Imm(b)) = (&a, &b) {
+                    if let Some(value) = fold(op, *a, *b) {
+                        return Ok((self.finish(Operand::Imm(value), dest), ty));
+                    }
+                }
+                let value = match *op {
+                    "+" => self.compute("add", a, b, dest),
+                    "*" => self.compute("mul", a, b, dest),
+                    "-" => match b {
+                        Operand::Imm(b) if b != i64::MIN => {
+                            self.compute("add", a, Operand::Imm(-b), dest)
+                        }
+                        b => {
+                            let negated = self.compute("mul", b, Operand::Imm(-1), None);
+                            self.compute("add", a, negated, dest)
+                        }
+                    },
+                    "<" => self.compute("lt", a, b, dest),
+                    ">" => self.compute("lt", b, a, dest),
+                    "==" => self.compute("eq", a, b, dest),
+                    // The opposites of `>`, `<` and `==`.
+                    _ => {
+                        let (mnemonic, a, b) = match *op {
+                            "<=" => ("lt", b, a),
+                            ">=" => ("lt", a, b),
+                            _ => ("eq", a, b),
+                        };
+                        let opposite = self.compute(mnemonic, a, b, dest);
+                        self.compute("eq", opposite.clone(), Operand::Imm(0), Some(opposite))
+                    }
+                };
+                Ok((value, ty))
+            }
+        }
+    }
+
+    /// Like `expr`, for expressions that must have a value.
+    fn value(
+        &mut self,
+        expr: &Expr,
+        dest: Option<Operand>,
+    ) -> Result<(Operand, Type), CompileError> {
+        match self.expr(expr, dest)? {
+            (_, Type::Unit) => Err(CompileError {
+                line: expr.line,
+                kind: CompileErrorKind::TypeMismatch {
+                    expected: Type::Int,
+                    found: Type::Unit,
+                },
+            }),
+            value => Ok(value),
+        }
+    }
+
+    /// Like `expr`, for expressions that must have type `ty`.
+    fn typed(
+        &mut self,
+        expr: &Expr,
+        ty: &Type,
+        dest: Option<Operand>,
+    ) -> Result<Operand, CompileError> {
+        let (value, found) = self.expr(expr, dest)?;
+        if found != *ty {
+            return Err(CompileError {
+                line: expr.line,
+                kind: CompileErrorKind::TypeMismatch {
+                    expected: ty.clone(),
+                    found,
+                },
+            });
+        }
+        Ok(value)
+    }
+}
@@ -0,0 +1,85 @@
+use std::fmt;
+
+use super::{CompileError, CompileErrorKind};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) enum Token {
+    Ident(String),
+    Int(i64),
+    /// A keyword or punctuation.
+    Symbol(&'static str),
+    Eof,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Ident(name) => write!(f, "`{}`", name),
+            Token::Int(value) => write!(f, "`{}`", value),
+            Token::Symbol(symbol) => write!(f, "`{}`", symbol),
+            Token::Eof => write!(f, "end of input"),
+        }
+    }
+}
+
+const KEYWORDS: [&str; 12] = [
+    "fn", "let", "if", "else", "while", "return", "break", "continue", "true", "false", "int",
+    "bool",
+];
+
+/// Longest first, so `<=` isn't read as `<` then `=`.
+const PUNCTUATION: [&str; 25] = [
+    "->", "==", "!=", "<=", ">=", "&&", "||", "(", ")", "{", "}", "[", "]", ",", ";", ":", "=",
+    "<", ">", "+", "-", "*", "/", "%", "!",
+];
+
+/// Splits `source` into tokens, each with its 1-based line, ending with
+/// `Token::Eof`.
+pub(super) fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, CompileError> {
+    let mut tokens = vec![];
+    let mut line_number = 1;
+
+    for (index, line) in source.lines().enumerate() {
+        line_number = index + 1;
+        let error = |kind| CompileError {
+            line: line_number,
+            kind,
+        };
+
+        let mut rest = line.split("//").next().unwrap_or("").trim_start();
+        while let Some(c) = rest.chars().next() {
+            let (token, length) = if c.is_ascii_alphabetic() || c == '_' {
+                let length = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                let word = &rest[..length];
+                let token = match KEYWORDS.iter().find(|keyword| **keyword == word) {
+                    Some(keyword) => Token::Symbol(keyword),
+                    None => Token::Ident(word.to_string()),
+                };
+                (token, length)
+            } else if c.is_ascii_digit() {
+                let length = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric())
+                    .unwrap_or(rest.len());
+                let number = &rest[..length];
+                let value = number
+                    .parse()
+                    .map_err(|_| error(CompileErrorKind::InvalidNumber(number.to_string())))?;
+                (Token::Int(value), length)
+            } else {
+                let symbol = PUNCTUATION
+                    .iter()
+                    .find(|symbol| rest.starts_with(**symbol))
+                    .ok_or_else(|| error(CompileErrorKind::UnexpectedChar(c)))?;
+                (Token::Symbol(symbol), symbol.len())
+            };
+
+            tokens.push((token, line_number));
+            rest = rest[length..].trim_start();
+        }
+    }
+
+    tokens.push((Token::Eof, line_number));
+    Ok(tokens)
+}
@@ -0,0 +1,181 @@
+//! A small typed language that compiles to Intcode.
+//!
+//! ```text
+//! let squares: [int; 10];
+//!
+//! fn fib(n: int) -> int {
+//!     if n < 2 {
+//!         return n;
+//!     }
+//!     return fib(n - 1) + fib(n - 2);
+//! }
+//!
+//! fn main() {
+//!     let n = input();
+//!     let i = 0;
+//!     while i < len(squares) {
+//!         squares[i] = i * i;
+//!         i = i + 1;
+//!     }
+//!     output(fib(n) % 1000);
+//! }
+//! ```
+//!
+//! Values are `int` or `bool`, and arrays like `[int; 10]` hold a fixed
+//! number of either, up to `MAX_ARRAY_LENGTH`. Variables are declared with
+//! `let`, with a type, an initial value or both; arrays start out as
+//! `[a, b, c]`, `[value; length]` or all zeros. Globals are declared at the
+//! top level and can only be initialised with constants. Functions take
+//! `int` and `bool` arguments, and return one of those or nothing. The
+//! program starts at `main`, which takes no arguments and returns nothing.
+//!
+//! Statements are `let`, assignment, `if`/`else`, `while`, `break`,
+//! `continue`, `return` and function calls. Expressions have `+ - * / %`
+//! on `int`, `< <= > >=` and `== !=` comparisons, and `&& || !` on `bool`.
+//! Intcode has no division, so `/` and `%` call functions that build it
+//! from `Mul` and `LessThen` loops; they truncate like Rust, and divide by
+//! zero by faulting. `input()` reads an input, `output(value)` writes an
+//! output and `len(array)` is an array's length.
+//!
+//! The relative base is the stack pointer, and frames follow the
+//! convention the decompiler recognises: a function's frame is `rb-n` to
+//! `rb-1`, holding the return address, the arguments, the locals and any
+//! temporaries in turn, and results come back in the first argument's slot.
+//! Array indices aren't checked at run time.
+
+mod codegen;
+mod lexer;
+mod parser;
+#[cfg(test)]
+mod tests;
+
+use std::error::Error;
+use std::fmt;
+
+use super::asm::assemble;
+pub use parser::{Type, MAX_ARRAY_LENGTH};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CompileErrorKind {
+    UnexpectedChar(char),
+    InvalidNumber(String),
+    UnexpectedToken {
+        expected: String,
+        found: String,
+    },
+    ReservedName(String),
+    Undefined(String),
+    AlreadyDefined(String),
+    TypeMismatch {
+        expected: Type,
+        found: Type,
+    },
+    ArgumentCount {
+        function: String,
+        expected: usize,
+        found: usize,
+    },
+    NotAnArray(String),
+    ArrayValue(String),
+    IndexOutOfBounds {
+        index: i64,
+        length: usize,
+    },
+    ArrayParameter(String),
+    ArrayTooLong(i64),
+    CannotInfer(String),
+    NotConstant(String),
+    OutsideLoop(&'static str),
+    MissingReturn(String),
+    NoMain,
+}
+
+impl fmt::Display for CompileErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompileErrorKind::UnexpectedChar(c) => write!(f, "unexpected character `{}`", c),
+            CompileErrorKind::InvalidNumber(number) => write!(f, "invalid number `{}`", number),
+            CompileErrorKind::UnexpectedToken { expected, found } => {
+                write!(f, "expected {}, found {}", expected, found)
+            }
+            CompileErrorKind::ReservedName(name) => write!(f, "the name `{}` is reserved", name),
+            CompileErrorKind::Undefined(name) => write!(f, "`{}` is not defined", name),
+            CompileErrorKind::AlreadyDefined(name) => {
+                write!(f, "`{}` is already defined", name)
+            }
+            CompileErrorKind::TypeMismatch { expected, found } => {
+                write!(f, "expected `{}`, found `{}`", expected, found)
+            }
+            CompileErrorKind::ArgumentCount {
+                function,
+                expected,
+                found,
+            } => write!(
+                f,
+                "`{}` takes {} argument(s) but {} were given",
+                function, expected, found
+            ),
+            CompileErrorKind::NotAnArray(name) => write!(f, "`{}` is not an array", name),
+            CompileErrorKind::ArrayValue(name) => {
+                write!(f, "the array `{}` can only be indexed", name)
+            }
+            CompileErrorKind::IndexOutOfBounds { index, length } => write!(
+                f,
+                "index {} is out of bounds for an array of length {}",
+                index, length
+            ),
+            CompileErrorKind::ArrayParameter(name) => {
+                write!(f, "the parameter `{}` can't be an array", name)
+            }
+            CompileErrorKind::ArrayTooLong(length) => write!(
+                f,
+                "array length {} is over the limit of {}",
+                length, MAX_ARRAY_LENGTH
+            ),
+            CompileErrorKind::CannotInfer(name) => {
+                write!(f, "can't infer the type of `{}`", name)
+            }
+            CompileErrorKind::NotConstant(name) => {
+                write!(
+                    f,
+                    "the global `{}` must be initialised with a constant",
+                    name
+                )
+            }
+            CompileErrorKind::OutsideLoop(keyword) => write!(f, "`{}` outside of a loop", keyword),
+            CompileErrorKind::MissingReturn(name) => {
+                write!(f, "`{}` might not return a value", name)
+            }
+            CompileErrorKind::NoMain => {
+                write!(f, "there must be a `fn main()` with no arguments or result")
+            }
+        }
+    }
+}
+
+/// A compile error and the 1-based source line it was found on.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CompileError {
+    pub line: usize,
+    pub kind: CompileErrorKind,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl Error for CompileError {}
+
+/// Compiles `source` to assembly in the syntax `asm::assemble` accepts.
+pub fn compile_to_asm(source: &str) -> Result<String, CompileError> {
+    let program = parser::parse(lexer::tokenize(source)?, false)?;
+    codegen::generate(&program)
+}
+
+/// Compiles `source` into a program ready for `IntcodeVM::set_program`.
+pub fn compile(source: &str) -> Result<Vec<i64>, CompileError> {
+    let asm = compile_to_asm(source)?;
+    Ok(assemble(&asm).expect("the compiler emits valid assembly"))
+}
@@ -0,0 +1,516 @@
+use std::fmt;
+
+use super::lexer::Token;
+use super::{CompileError, CompileErrorKind};
+
+/// The longest an array can be.
+pub const MAX_ARRAY_LENGTH: usize = 1 << 16;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Type {
+    Int,
+    Bool,
+    /// What functions without a return type return.
+    Unit,
+    /// A fixed-size array of `Int` or `Bool`.
+    Array(Box<Type>, usize),
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Bool => write!(f, "bool"),
+            Type::Unit => write!(f, "()"),
+            Type::Array(element, length) => write!(f, "[{}; {}]", element, length),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct Expr {
+    pub(super) kind: ExprKind,
+    pub(super) line: usize,
+}
+
+#[derive(Clone, Debug)]
+pub(super) enum ExprKind {
+    Int(i64),
+    Bool(bool),
+    Var(String),
+    Index(String, Box<Expr>),
+    Call(String, Vec<Expr>),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Whether evaluating the expression calls a function, which might
+    /// change globals.
+    pub(super) fn has_call(&self) -> bool {
+        match &self.kind {
+            ExprKind::Int(_) | ExprKind::Bool(_) | ExprKind::Var(_) => false,
+            ExprKind::Call(..) => true,
+            ExprKind::Index(_, index) => index.has_call(),
+            ExprKind::Unary(_, operand) => operand.has_call(),
+            ExprKind::Binary(op, left, right) => {
+                matches!(*op, "/" | "%") || left.has_call() || right.has_call()
+            }
+        }
+    }
+}
+
+/// How a variable starts out.
+#[derive(Clone, Debug)]
+pub(super) enum Init {
+    Expr(Expr),
+    /// `[a, b, c]`.
+    List(Vec<Expr>),
+    /// `[value; length]`.
+    Repeat(Expr, usize),
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct Stmt {
+    pub(super) kind: StmtKind,
+    pub(super) line: usize,
+}
+
+#[derive(Clone, Debug)]
+pub(super) enum StmtKind {
+    Let {
+        name: String,
+        ty: Option<Type>,
+        init: Option<Init>,
+    },
+    Assign {
+        name: String,
+        index: Option<Expr>,
+        value: Expr,
+    },
+    If {
+        condition: Expr,
+        then: Vec<Stmt>,
+        otherwise: Vec<Stmt>,
+    },
+    While {
+        condition: Expr,
+        body: Vec<Stmt>,
+    },
+    Return(Option<Expr>),
+    Break,
+    Continue,
+    Expr(Expr),
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct Function {
+    pub(super) name: String,
+    pub(super) params: Vec<(String, Type)>,
+    pub(super) ret: Type,
+    pub(super) body: Vec<Stmt>,
+    pub(super) line: usize,
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct Global {
+    pub(super) name: String,
+    pub(super) ty: Option<Type>,
+    pub(super) init: Option<Init>,
+    pub(super) line: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(super) struct Program {
+    pub(super) functions: Vec<Function>,
+    pub(super) globals: Vec<Global>,
+}
+
+/// Parses `tokens`. Names starting with `__` are reserved for the compiler's
+/// own functions unless `reserved` allows them.
+pub(super) fn parse(tokens: Vec<(Token, usize)>, reserved: bool) -> Result<Program, CompileError> {
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        reserved,
+    };
+
+    let mut program = Program::default();
+    loop {
+        match parser.peek() {
+            Token::Symbol("fn") => program.functions.push(parser.function()?),
+            Token::Symbol("let") => {
+                let line = parser.line();
+                let (name, ty, init) = parser.binding()?;
+                program.globals.push(Global {
+                    name,
+                    ty,
+                    init,
+                    line,
+                });
+            }
+            Token::Eof => return Ok(program),
+            _ => return Err(parser.expected("`fn` or `let`")),
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    reserved: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position].0
+    }
+
+    fn peek_second(&self) -> &Token {
+        let index = (self.position + 1).min(self.tokens.len() - 1);
+        &self.tokens[index].0
+    }
+
+    fn line(&self) -> usize {
+        self.tokens[self.position].1
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.position].0.clone();
+        if token != Token::Eof {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn expected(&self, expected: &str) -> CompileError {
+        CompileError {
+            line: self.line(),
+            kind: CompileErrorKind::UnexpectedToken {
+                expected: expected.to_string(),
+                found: self.peek().to_string(),
+            },
+        }
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Token::Symbol(found) if *found == symbol) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), CompileError> {
+        match self.eat(symbol) {
+            true => Ok(()),
+            false => Err(self.expected(&format!("`{}`", symbol))),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, CompileError> {
+        match self.peek().clone() {
+            Token::Ident(name) if name.starts_with("__") && !self.reserved => Err(CompileError {
+                line: self.line(),
+                kind: CompileErrorKind::ReservedName(name),
+            }),
+            Token::Ident(name) => {
+                self.next();
+                Ok(name)
+            }
+            _ => Err(self.expected("a name")),
+        }
+    }
+
+    fn ty(&mut self) -> Result<Type, CompileError> {
+        if self.eat("int") {
+            Ok(Type::Int)
+        } else if self.eat("bool") {
+            Ok(Type::Bool)
+        } else if self.eat("[") {
+            let element = match self.peek() {
+                Token::Symbol("int") | Token::Symbol("bool") => self.ty()?,
+                _ => return Err(self.expected("`int` or `bool`")),
+            };
+            self.expect(";")?;
+            let length = self.length()?;
+            self.expect("]")?;
+            Ok(Type::Array(Box::new(element), length))
+        } else {
+            Err(self.expected("a type"))
+        }
+    }
+
+    fn length(&mut self) -> Result<usize, CompileError> {
+        match self.peek() {
+            Token::Int(length) if *length as usize <= MAX_ARRAY_LENGTH => {
+                let length = *length as usize;
+                self.next();
+                Ok(length)
+            }
+            Token::Int(length) => Err(CompileError {
+                line: self.line(),
+                kind: CompileErrorKind::ArrayTooLong(*length),
+            }),
+            _ => Err(self.expected("an array length")),
+        }
+    }
+
+    fn function(&mut self) -> Result<Function, CompileError> {
+        let line = self.line();
+        self.expect("fn")?;
+        let name = self.ident()?;
+
+        self.expect("(")?;
+        let mut params = vec![];
+        while !self.eat(")") {
+            if !params.is_empty() {
+                self.expect(",")?;
+            }
+            let name = self.ident()?;
+            self.expect(":")?;
+            params.push((name, self.ty()?));
+        }
+
+        let ret = match self.eat("->") {
+            true => self.ty()?,
+            false => Type::Unit,
+        };
+        let body = self.block()?;
+
+        Ok(Function {
+            name,
+            params,
+            ret,
+            body,
+            line,
+        })
+    }
+
+    /// `let name: ty = init;`, where the type and the initial value are
+    /// optional.
+    fn binding(&mut self) -> Result<(String, Option<Type>, Option<Init>), CompileError> {
+        self.expect("let")?;
+        let name = self.ident()?;
+        let ty = match self.eat(":") {
+            true => Some(self.ty()?),
+            false => None,
+        };
+        let init = match self.eat("=") {
+            true => Some(self.init()?),
+            false => None,
+        };
+        self.expect(";")?;
+        Ok((name, ty, init))
+    }
+
+    fn init(&mut self) -> Result<Init, CompileError> {
+        if !self.eat("[") {
+            return Ok(Init::Expr(self.expr()?));
+        }
+
+        let mut elements = vec![];
+        while !self.eat("]") {
+            if !elements.is_empty() {
+                self.expect(",")?;
+            }
+            elements.push(self.expr()?);
+
+            if elements.len() == 1 && self.eat(";") {
+                let length = self.length()?;
+                self.expect("]")?;
+                return Ok(Init::Repeat(elements.pop().unwrap(), length));
+            }
+        }
+        Ok(Init::List(elements))
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, CompileError> {
+        self.expect("{")?;
+        let mut statements = vec![];
+        while !self.eat("}") {
+            statements.push(self.statement()?);
+        }
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, CompileError> {
+        let line = self.line();
+        let kind = match self.peek() {
+            Token::Symbol("let") => {
+                let (name, ty, init) = self.binding()?;
+                StmtKind::Let { name, ty, init }
+            }
+            Token::Symbol("if") => return self.if_statement(),
+            Token::Symbol("while") => {
+                self.next();
+                let condition = self.expr()?;
+                let body = self.block()?;
+                StmtKind::While { condition, body }
+            }
+            Token::Symbol("return") => {
+                self.next();
+                let value = match self.peek() {
+                    Token::Symbol(";") => None,
+                    _ => Some(self.expr()?),
+                };
+                self.expect(";")?;
+                StmtKind::Return(value)
+            }
+            Token::Symbol("break") => {
+                self.next();
+                self.expect(";")?;
+                StmtKind::Break
+            }
+            Token::Symbol("continue") => {
+                self.next();
+                self.expect(";")?;
+                StmtKind::Continue
+            }
+            Token::Ident(_) if *self.peek_second() == Token::Symbol("=") => {
+                let name = self.ident()?;
+                self.expect("=")?;
+                let value = self.expr()?;
+                self.expect(";")?;
+                StmtKind::Assign {
+                    name,
+                    index: None,
+                    value,
+                }
+            }
+            _ => {
+                let expr = self.expr()?;
+                let kind = match expr.kind {
+                    ExprKind::Index(name, index) if self.eat("=") => StmtKind::Assign {
+                        name,
+                        index: Some(*index),
+                        value: self.expr()?,
+                    },
+                    kind => StmtKind::Expr(Expr { kind, line }),
+                };
+                self.expect(";")?;
+                kind
+            }
+        };
+        Ok(Stmt { kind, line })
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt, CompileError> {
+        let line = self.line();
+        self.expect("if")?;
+        let condition = self.expr()?;
+        let then = self.block()?;
+        let otherwise = match self.eat("else") {
+            true if *self.peek() == Token::Symbol("if") => vec![self.if_statement()?],
+            true => self.block()?,
+            false => vec![],
+        };
+        Ok(Stmt {
+            kind: StmtKind::If {
+                condition,
+                then,
+                otherwise,
+            },
+            line,
+        })
+    }
+
+    fn expr(&mut self) -> Result<Expr, CompileError> {
+        self.binary(0)
+    }
+
+    /// Parses operators of precedence `level` and tighter.
+    fn binary(&mut self, level: usize) -> Result<Expr, CompileError> {
+        const LEVELS: [&[&str]; 5] = [
+            &["||"],
+            &["&&"],
+            &["==", "!=", "<", "<=", ">", ">="],
+            &["+", "-"],
+            &["*", "/", "%"],
+        ];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+
+        let mut left = self.binary(level + 1)?;
+        loop {
+            let op = match self.peek() {
+                Token::Symbol(op) if LEVELS[level].contains(op) => *op,
+                _ => return Ok(left),
+            };
+            let line = self.line();
+            self.next();
+            let right = self.binary(level + 1)?;
+            left = Expr {
+                kind: ExprKind::Binary(op, Box::new(left), Box::new(right)),
+                line,
+            };
+
+            // Comparisons don't chain.
+            if level == 2 {
+                return Ok(left);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, CompileError> {
+        let line = self.line();
+        let op = match self.peek() {
+            Token::Symbol("-") => "-",
+            Token::Symbol("!") => "!",
+            _ => return self.primary(),
+        };
+        self.next();
+        let operand = self.unary()?;
+        Ok(Expr {
+            kind: ExprKind::Unary(op, Box::new(operand)),
+            line,
+        })
+    }
+
+    fn primary(&mut self) -> Result<Expr, CompileError> {
+        let line = self.line();
+        let kind = match self.peek().clone() {
+            Token::Int(value) => {
+                self.next();
+                ExprKind::Int(value)
+            }
+            Token::Symbol("true") => {
+                self.next();
+                ExprKind::Bool(true)
+            }
+            Token::Symbol("false") => {
+                self.next();
+                ExprKind::Bool(false)
+            }
+            Token::Symbol("(") => {
+                self.next();
+                let expr = self.expr()?;
+                self.expect(")")?;
+                return Ok(expr);
+            }
+            Token::Ident(_) => {
+                let name = self.ident()?;
+                if self.eat("(") {
+                    let mut args = vec![];
+                    while !self.eat(")") {
+                        if !args.is_empty() {
+                            self.expect(",")?;
+                        }
+                        args.push(self.expr()?);
+                    }
+                    ExprKind::Call(name, args)
+                } else if self.eat("[") {
+                    let index = self.expr()?;
+                    self.expect("]")?;
+                    ExprKind::Index(name, Box::new(index))
+                } else {
+                    ExprKind::Var(name)
+                }
+            }
+            _ => return Err(self.expected("an expression")),
+        };
+        Ok(Expr { kind, line })
+    }
+}
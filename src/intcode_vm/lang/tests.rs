@@ -0,0 +1,484 @@
+//! End-to-end tests: every program is compiled, assembled and run on every
+//! backend.
+
+use super::{compile, compile_to_asm, CompileError, CompileErrorKind, Type};
+use crate::intcode_vm::decompile::decompile;
+use crate::intcode_vm::{Backend, IntcodeVM, VmError};
+
+const BACKENDS: [Backend; 2] = [Backend::Interpreter, Backend::Compiled];
+
+/// Runs `source` with `inputs` on every backend, checking they agree.
+fn try_run(source: &str, inputs: &[i64]) -> Result<Vec<i64>, VmError> {
+    let program = compile(source).unwrap_or_else(|error| panic!("{}", error));
+    let results = BACKENDS
+        .iter()
+        .map(|&backend| {
+            let mut vm = IntcodeVM::new();
+            vm.set_backend(backend);
+            vm.set_program(program.clone());
+            for &input in inputs {
+                vm.push_input(input);
+            }
+            vm.run_once()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(results[0], results[1]);
+    results[0].clone()
+}
+
+fn run(source: &str, inputs: &[i64]) -> Vec<i64> {
+    try_run(source, inputs).unwrap()
+}
+
+fn error(source: &str) -> CompileError {
+    compile(source).unwrap_err()
+}
+
+#[test]
+fn test_arithmetic() {
+    let source = "
+        fn main() {
+            let a = input();
+            let b = input();
+            output(a + b * 2);
+            output((a + b) * 2);
+            output(a - b - 1);
+            output(-a + 1);
+            output(a * -b);
+            output(1 + 2 * 3 - 4);
+            output(a - -5);
+        }";
+    assert_eq!(run(source, &[7, 3]), vec![13, 20, 3, -6, -21, 3, 12]);
+}
+
+#[test]
+fn test_comparisons() {
+    let source = "
+        fn show(condition: bool) {
+            if condition {
+                output(1);
+            } else {
+                output(0);
+            }
+        }
+
+        fn main() {
+            let a = input();
+            let b = input();
+            show(a < b);
+            show(a <= b);
+            show(a > b);
+            show(a >= b);
+            show(a == b);
+            show(a != b);
+            show(!(a < b) == (b <= a));
+            show(a < b && b < 10 || a == 0);
+        }";
+    assert_eq!(run(source, &[2, 5]), vec![1, 1, 0, 0, 0, 1, 1, 1]);
+    assert_eq!(run(source, &[5, 5]), vec![0, 1, 0, 1, 1, 0, 1, 0]);
+    assert_eq!(run(source, &[0, -5]), vec![0, 0, 1, 1, 0, 1, 1, 1]);
+}
+
+#[test]
+fn test_division() {
+    let source = "
+        fn main() {
+            let a = input();
+            let b = input();
+            output(a / b);
+            output(a % b);
+        }";
+    for &a in &[0, 1, 7, 100, -7, -100, 123_456_789, -1] {
+        for &b in &[1, 2, 3, 7, -1, -3, 1000, 123_456_789] {
+            assert_eq!(run(source, &[a, b]), vec![a / b, a % b], "{} {}", a, b);
+        }
+    }
+    assert_eq!(run("fn main() { output(17 / 5 % 2); }", &[]), vec![1]);
+}
+
+#[test]
+fn test_division_by_zero() {
+    let source = "fn main() { output(1); output(input() / input()); }";
+    assert!(try_run(source, &[1, 0]).is_err());
+    assert!(try_run("fn main() { output(input() % 0); }", &[5]).is_err());
+}
+
+#[test]
+fn test_recursion() {
+    let source = "
+        fn fib(n: int) -> int {
+            if n < 2 {
+                return n;
+            }
+            return fib(n - 1) + fib(n - 2);
+        }
+
+        fn factorial(n: int) -> int {
+            if n == 0 {
+                return 1;
+            }
+            return n * factorial(n - 1);
+        }
+
+        fn gcd(a: int, b: int) -> int {
+            if b == 0 {
+                return a;
+            }
+            return gcd(b, a % b);
+        }
+
+        fn main() {
+            output(fib(input()));
+            output(factorial(input()));
+            output(gcd(input(), input()));
+        }";
+    assert_eq!(run(source, &[15, 10, 1071, 462]), vec![610, 3_628_800, 21]);
+}
+
+#[test]
+fn test_local_arrays() {
+    let source = "
+        fn main() {
+            let n = input();
+            let composite: [bool; 50];
+            let i = 2;
+            while i < n {
+                if !composite[i] {
+                    output(i);
+                    let j = i * i;
+                    while j < n {
+                        composite[j] = true;
+                        j = j + i;
+                    }
+                }
+                i = i + 1;
+            }
+
+            let xs = [3, 1, 2];
+            let ys = [xs[2] * 10; 2];
+            xs[0] = xs[1] + xs[2];
+            output(xs[0] + ys[1] + len(ys));
+        }";
+    assert_eq!(
+        run(source, &[50]),
+        vec![2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 25]
+    );
+}
+
+#[test]
+fn test_long_local_arrays() {
+    let source = "
+        fn sum(n: int) -> int {
+            let xs = [n; 5000];
+            let zeros: [int; 3000];
+            let total = 0;
+            let i = 0;
+            while i < len(xs) {
+                total = total + xs[i] + zeros[i % len(zeros)];
+                i = i + 1;
+            }
+            return total;
+        }
+
+        fn main() {
+            let guard = 11;
+            output(sum(input()));
+            output(guard);
+        }";
+    assert_eq!(run(source, &[3]), vec![15000, 11]);
+    assert!(compile(source).unwrap().len() < 1000);
+}
+
+#[test]
+fn test_global_arrays() {
+    let source = "
+        let values: [int; 8];
+        let count = 0;
+
+        fn push(value: int) {
+            values[count] = value;
+            count = count + 1;
+        }
+
+        fn sort() {
+            let i = 1;
+            while i < count {
+                let j = i;
+                while j > 0 && values[j - 1] > values[j] {
+                    let swap = values[j];
+                    values[j] = values[j - 1];
+                    values[j - 1] = swap;
+                    j = j - 1;
+                }
+                i = i + 1;
+            }
+        }
+
+        fn main() {
+            let n = input();
+            while count < n {
+                push(input());
+            }
+            sort();
+            let i = 0;
+            while i < count {
+                output(values[i]);
+                i = i + 1;
+            }
+        }";
+    assert_eq!(
+        run(source, &[6, 5, -2, 9, 0, 5, 1]),
+        vec![-2, 0, 1, 5, 5, 9]
+    );
+}
+
+#[test]
+fn test_control_flow() {
+    let source = "
+        fn classify(n: int) -> int {
+            if n < 0 {
+                return -1;
+            } else if n == 0 {
+                return 0;
+            } else {
+                return 1;
+            }
+        }
+
+        fn first_multiple(of: int) -> int {
+            let i = 1;
+            while true {
+                if i % of == 0 {
+                    return i;
+                }
+                i = i + 1;
+            }
+        }
+
+        fn main() {
+            output(classify(-5));
+            output(classify(0));
+            output(classify(5));
+            output(first_multiple(7));
+
+            let i = 0;
+            while true {
+                i = i + 1;
+                if i > 8 {
+                    break;
+                }
+                if i % 2 == 0 {
+                    continue;
+                }
+                output(i);
+            }
+        }";
+    assert_eq!(run(source, &[]), vec![-1, 0, 1, 7, 1, 3, 5, 7]);
+}
+
+#[test]
+fn test_short_circuit() {
+    let source = "
+        let calls = 0;
+
+        fn check(result: bool) -> bool {
+            calls = calls + 1;
+            return result;
+        }
+
+        fn main() {
+            if check(false) && check(true) {
+                output(-1);
+            }
+            if check(true) || check(false) {
+                output(calls);
+            }
+            let both = check(true) && check(true);
+            output(calls + calls * 10);
+        }";
+    assert_eq!(run(source, &[]), vec![2, 44]);
+}
+
+#[test]
+fn test_globals_and_calls() {
+    // Globals read before a call see the value from before it.
+    let source = "
+        let counter = 10;
+        let flags = [true, false];
+
+        fn bump() -> int {
+            counter = counter + 1;
+            return counter;
+        }
+
+        fn add(a: int, b: int) -> int {
+            return a + b;
+        }
+
+        fn main() {
+            output(counter + bump());
+            output(add(counter, bump()));
+            if flags[0] && !flags[1] {
+                output(counter);
+            }
+        }";
+    assert_eq!(run(source, &[]), vec![21, 23, 12]);
+}
+
+#[test]
+fn test_echo() {
+    let source = "
+        fn main() {
+            let n = input();
+            while n > 0 {
+                output(input());
+                n = n - 1;
+            }
+        }";
+    assert_eq!(run(source, &[3, 4, -5, 6]), vec![4, -5, 6]);
+    assert_eq!(run(source, &[0]), vec![]);
+}
+
+#[test]
+fn test_compile_errors() {
+    let cases: Vec<(&str, usize, CompileErrorKind)> = vec![
+        (
+            "fn main() {\n  let x = 1 $ 2;\n}",
+            2,
+            CompileErrorKind::UnexpectedChar('$'),
+        ),
+        (
+            "fn main() {\n  output(1)\n}",
+            3,
+            CompileErrorKind::UnexpectedToken {
+                expected: "`;`".to_string(),
+                found: "`}`".to_string(),
+            },
+        ),
+        (
+            "fn main() {\n  output(x);\n}",
+            2,
+            CompileErrorKind::Undefined("x".to_string()),
+        ),
+        (
+            "fn main() {\n  let x: int = true;\n}",
+            2,
+            CompileErrorKind::TypeMismatch {
+                expected: Type::Int,
+                found: Type::Bool,
+            },
+        ),
+        (
+            "fn main() {\n  if 1 { }\n}",
+            2,
+            CompileErrorKind::TypeMismatch {
+                expected: Type::Bool,
+                found: Type::Int,
+            },
+        ),
+        (
+            "fn f(a: int) { }\nfn main() {\n  f(1, 2);\n}",
+            3,
+            CompileErrorKind::ArgumentCount {
+                function: "f".to_string(),
+                expected: 1,
+                found: 2,
+            },
+        ),
+        (
+            "fn main() {\n  let xs = [1, 2];\n  xs[2] = 0;\n}",
+            3,
+            CompileErrorKind::IndexOutOfBounds {
+                index: 2,
+                length: 2,
+            },
+        ),
+        (
+            "fn main() {\n  let xs = [1, 2];\n  output(xs);\n}",
+            3,
+            CompileErrorKind::ArrayValue("xs".to_string()),
+        ),
+        (
+            "fn f(n: int) -> int {\n  if n < 0 { return 0; }\n}\nfn main() { }",
+            1,
+            CompileErrorKind::MissingReturn("f".to_string()),
+        ),
+        (
+            "fn main() {\n  break;\n}",
+            2,
+            CompileErrorKind::OutsideLoop("break"),
+        ),
+        ("fn start() { }", 1, CompileErrorKind::NoMain),
+        (
+            "let x = input();\nfn main() { }",
+            1,
+            CompileErrorKind::NotConstant("x".to_string()),
+        ),
+        (
+            "fn f(xs: [int; 2]) { }\nfn main() { }",
+            1,
+            CompileErrorKind::ArrayParameter("xs".to_string()),
+        ),
+        (
+            "let g = [0; 100000000000];\nfn main() { }",
+            1,
+            CompileErrorKind::ArrayTooLong(100_000_000_000),
+        ),
+        (
+            "fn main() {\n  let xs: [int; 65537];\n}",
+            2,
+            CompileErrorKind::ArrayTooLong(65537),
+        ),
+        (
+            "fn main() {\n  __div(1, 2);\n}",
+            2,
+            CompileErrorKind::ReservedName("__div".to_string()),
+        ),
+        (
+            "fn output(x: int) { }\nfn main() { }",
+            1,
+            CompileErrorKind::AlreadyDefined("output".to_string()),
+        ),
+    ];
+
+    for (source, line, kind) in cases {
+        assert_eq!(error(source), CompileError { line, kind }, "{}", source);
+    }
+    assert_eq!(
+        error("fn main() {\n  let x: int = true;\n}").to_string(),
+        "line 2: expected `int`, found `bool`"
+    );
+}
+
+#[test]
+fn test_prelude_only_when_dividing() {
+    let asm = compile_to_asm("fn main() { output(6 * 7); }").unwrap();
+    assert!(!asm.contains("f___div"));
+    let asm = compile_to_asm("fn main() { output(input() / 7); }").unwrap();
+    assert!(asm.contains("f___divmod:"));
+}
+
+#[test]
+fn test_decompiles() {
+    // `fib` comes right after the 10 words that call `main`.
+    let program = compile(
+        "fn fib(n: int) -> int {
+            if n < 2 {
+                return n;
+            }
+            return fib(n - 1) + fib(n - 2);
+        }
+
+        fn main() {
+            output(fib(input()));
+        }",
+    )
+    .unwrap();
+    let decompiled = decompile(&program);
+    let fib = decompiled.function(10).unwrap();
+    assert_eq!(fib.params, 1);
+    assert!(fib.frame.is_some());
+    let listing = fib.to_string();
+    assert!(listing.starts_with("fn f_10(p1) {"), "{}", listing);
+    assert!(listing.contains("rb[1] = f_10(v2);"), "{}", listing);
+}
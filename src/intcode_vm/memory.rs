@@ -0,0 +1,439 @@
+//! Where a VM keeps its memory.
+//!
+//! Intcode memory is unbounded and reads as 0 wherever nothing has been
+//! written. `DenseMemory` stores every word up to the highest one written,
+//! which is fastest for ordinary programs. `PagedMemory` only stores the
+//! pages that have been written, so programs that scatter data far apart
+//! don't allocate everything in between. `BoundedMemory` refuses writes past
+//! a limit, for running programs that can't be trusted.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+/// A write past the end of a `BoundedMemory`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct MemoryError {
+    pub address: usize,
+    pub limit: usize,
+}
+
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "write to address {} is past the memory limit of {} words",
+            self.address, self.limit
+        )
+    }
+}
+
+impl Error for MemoryError {}
+
+/// Which memory backend a snapshot restores.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MemoryKind {
+    Dense,
+    Paged,
+    /// Bounded at this limit.
+    Bounded(usize),
+}
+
+impl MemoryKind {
+    /// Rebuilds memory of this kind from `chunks`, as returned by
+    /// `Memory::chunks`. Returns `None` if this kind can't hold them.
+    pub(super) fn restore(self, chunks: Vec<(usize, Vec<i64>)>) -> Option<Storage> {
+        if self == MemoryKind::Paged {
+            let mut memory = PagedMemory::new();
+            for (start, words) in chunks {
+                start.checked_add(words.len())?;
+                for (address, value) in (start..).zip(words) {
+                    memory.write(address, value).ok()?;
+                }
+            }
+            return Some(Storage::Other(Box::new(memory)));
+        }
+
+        let words = match chunks.len() {
+            0 => vec![],
+            1 if chunks[0].0 == 0 => chunks.into_iter().next().unwrap().1,
+            _ => return None,
+        };
+        Some(match self {
+            MemoryKind::Bounded(limit) => {
+                let mut memory = BoundedMemory::new(limit);
+                memory.load(words);
+                Storage::Other(Box::new(memory))
+            }
+            _ => Storage::Dense(DenseMemory::from(words)),
+        })
+    }
+}
+
+pub trait Memory: fmt::Debug + Send + Sync {
+    /// Reads `address`; words that were never written read as 0.
+    fn read(&self, address: usize) -> i64;
+
+    /// Writes `value` to `address` and returns the value it replaced.
+    fn write(&mut self, address: usize, value: i64) -> Result<i64, MemoryError>;
+
+    /// One past the highest address that has been loaded or written.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Replaces the contents of memory with `program`, starting at address
+    /// 0. Loading is never refused, even past a limit on writes.
+    fn load(&mut self, program: Vec<i64>);
+
+    fn clear(&mut self) {
+        self.load(vec![]);
+    }
+
+    fn box_clone(&self) -> Box<dyn Memory>;
+
+    /// Words `0..len()`. For sparse memory this can be huge, so prefer
+    /// `read_range` or `chunks`.
+    fn to_vec(&self) -> Vec<i64> {
+        self.read_range(0..self.len())
+    }
+
+    fn read_range(&self, range: Range<usize>) -> Vec<i64> {
+        range.map(|address| self.read(address)).collect()
+    }
+
+    /// The runs of words that can be non-zero, as their start address and
+    /// their words, in address order. The last run ends at `len()`.
+    fn chunks(&self) -> Vec<(usize, Vec<i64>)> {
+        match self.len() {
+            0 => vec![],
+            _ => vec![(0, self.to_vec())],
+        }
+    }
+
+    /// The backend a snapshot restores this memory as. Memories of other
+    /// types are restored as `DenseMemory`.
+    fn kind(&self) -> MemoryKind {
+        MemoryKind::Dense
+    }
+}
+
+impl Clone for Box<dyn Memory> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// A VM's memory. Dense memory, which nearly every VM uses, is stored
+/// directly so reads and writes to it don't go through a virtual call.
+#[derive(Clone, Debug)]
+pub(super) enum Storage {
+    Dense(DenseMemory),
+    Other(Box<dyn Memory>),
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        Storage::Dense(DenseMemory::new())
+    }
+}
+
+impl Storage {
+    #[inline(always)]
+    pub(super) fn read(&self, address: usize) -> i64 {
+        match self {
+            Storage::Dense(memory) => memory.read(address),
+            Storage::Other(memory) => memory.read(address),
+        }
+    }
+
+    #[inline(always)]
+    pub(super) fn write(&mut self, address: usize, value: i64) -> Result<i64, MemoryError> {
+        match self {
+            Storage::Dense(memory) => memory.write(address, value),
+            Storage::Other(memory) => memory.write(address, value),
+        }
+    }
+
+    #[inline(always)]
+    pub(super) fn len(&self) -> usize {
+        match self {
+            Storage::Dense(memory) => memory.len(),
+            Storage::Other(memory) => memory.len(),
+        }
+    }
+
+    pub(super) fn get(&self) -> &dyn Memory {
+        match self {
+            Storage::Dense(memory) => memory,
+            Storage::Other(memory) => memory.as_ref(),
+        }
+    }
+
+    pub(super) fn get_mut(&mut self) -> &mut dyn Memory {
+        match self {
+            Storage::Dense(memory) => memory,
+            Storage::Other(memory) => memory.as_mut(),
+        }
+    }
+}
+
+/// Every word up to the highest one written, in a `Vec`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DenseMemory {
+    words: Vec<i64>,
+}
+
+impl DenseMemory {
+    pub fn new() -> Self {
+        DenseMemory::default()
+    }
+}
+
+impl From<Vec<i64>> for DenseMemory {
+    fn from(words: Vec<i64>) -> Self {
+        DenseMemory { words }
+    }
+}
+
+impl Memory for DenseMemory {
+    #[inline]
+    fn read(&self, address: usize) -> i64 {
+        self.words.get(address).copied().unwrap_or(0)
+    }
+
+    #[inline]
+    fn write(&mut self, address: usize, value: i64) -> Result<i64, MemoryError> {
+        match self.words.get_mut(address) {
+            Some(word) => Ok(std::mem::replace(word, value)),
+            None => {
+                self.words.resize(address + 1, 0);
+                self.words[address] = value;
+                Ok(0)
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    fn load(&mut self, program: Vec<i64>) {
+        self.words = program;
+    }
+
+    fn box_clone(&self) -> Box<dyn Memory> {
+        Box::new(self.clone())
+    }
+
+    fn to_vec(&self) -> Vec<i64> {
+        self.words.clone()
+    }
+
+    fn read_range(&self, range: Range<usize>) -> Vec<i64> {
+        let end = range.end.min(self.words.len()).max(range.start);
+        let mut words = self.words.get(range.start..end).unwrap_or(&[]).to_vec();
+        words.resize(range.len(), 0);
+        words
+    }
+}
+
+const PAGE_SIZE: usize = 1024;
+
+/// Fixed size pages, allocated the first time a word in them is written.
+#[derive(Clone, Debug, Default)]
+pub struct PagedMemory {
+    pages: HashMap<usize, Box<[i64]>>,
+    len: usize,
+}
+
+impl PagedMemory {
+    pub fn new() -> Self {
+        PagedMemory::default()
+    }
+
+    /// The number of pages allocated.
+    pub fn pages(&self) -> usize {
+        self.pages.len()
+    }
+}
+
+impl Memory for PagedMemory {
+    fn read(&self, address: usize) -> i64 {
+        match self.pages.get(&(address / PAGE_SIZE)) {
+            Some(page) => page[address % PAGE_SIZE],
+            None => 0,
+        }
+    }
+
+    fn write(&mut self, address: usize, value: i64) -> Result<i64, MemoryError> {
+        let page = self
+            .pages
+            .entry(address / PAGE_SIZE)
+            .or_insert_with(|| vec![0; PAGE_SIZE].into_boxed_slice());
+        self.len = self.len.max(address + 1);
+        Ok(std::mem::replace(&mut page[address % PAGE_SIZE], value))
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn load(&mut self, program: Vec<i64>) {
+        self.pages.clear();
+        self.len = 0;
+        for (address, value) in program.into_iter().enumerate() {
+            // Paged writes can't fail.
+            let _ = self.write(address, value);
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn Memory> {
+        Box::new(self.clone())
+    }
+
+    fn chunks(&self) -> Vec<(usize, Vec<i64>)> {
+        let mut pages = self.pages.keys().copied().collect::<Vec<_>>();
+        pages.sort_unstable();
+        pages
+            .into_iter()
+            .map(|page| {
+                let start = page * PAGE_SIZE;
+                let end = (start + PAGE_SIZE).min(self.len);
+                (start, self.pages[&page][..end - start].to_vec())
+            })
+            .collect()
+    }
+
+    fn kind(&self) -> MemoryKind {
+        MemoryKind::Paged
+    }
+}
+
+/// Dense memory that refuses writes at or past `limit`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BoundedMemory {
+    memory: DenseMemory,
+    limit: usize,
+}
+
+impl BoundedMemory {
+    pub fn new(limit: usize) -> Self {
+        BoundedMemory {
+            memory: DenseMemory::new(),
+            limit,
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+impl Memory for BoundedMemory {
+    fn read(&self, address: usize) -> i64 {
+        self.memory.read(address)
+    }
+
+    fn write(&mut self, address: usize, value: i64) -> Result<i64, MemoryError> {
+        if address >= self.limit {
+            return Err(MemoryError {
+                address,
+                limit: self.limit,
+            });
+        }
+        self.memory.write(address, value)
+    }
+
+    fn len(&self) -> usize {
+        self.memory.len()
+    }
+
+    fn load(&mut self, program: Vec<i64>) {
+        self.memory.load(program);
+    }
+
+    fn box_clone(&self) -> Box<dyn Memory> {
+        Box::new(self.clone())
+    }
+
+    fn to_vec(&self) -> Vec<i64> {
+        self.memory.to_vec()
+    }
+
+    fn read_range(&self, range: Range<usize>) -> Vec<i64> {
+        self.memory.read_range(range)
+    }
+
+    fn kind(&self) -> MemoryKind {
+        MemoryKind::Bounded(self.limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BoundedMemory, DenseMemory, Memory, MemoryError, PagedMemory};
+
+    #[test]
+    fn test_backends_agree() {
+        let memories: Vec<Box<dyn Memory>> = vec![
+            Box::new(DenseMemory::new()),
+            Box::new(PagedMemory::new()),
+            Box::new(BoundedMemory::new(10_000)),
+        ];
+
+        for mut memory in memories {
+            memory.load(vec![1, 2, 3]);
+            assert_eq!(memory.write(1, 20), Ok(2));
+            assert_eq!(memory.write(5000, 7), Ok(0));
+            assert_eq!(memory.read(5000), 7);
+            assert_eq!(memory.read(4999), 0);
+            assert_eq!(memory.read(1 << 40), 0);
+            assert_eq!(memory.len(), 5001);
+            assert_eq!(&memory.to_vec()[..4], &[1, 20, 3, 0]);
+            assert_eq!(memory.read_range(4998..5003), vec![0, 0, 7, 0, 0]);
+            assert_eq!(memory.chunks().last().unwrap().1.last(), Some(&7));
+
+            memory.clear();
+            assert!(memory.is_empty());
+            assert_eq!(memory.read(1), 0);
+        }
+    }
+
+    #[test]
+    fn test_paged_memory_only_allocates_written_pages() {
+        let mut memory = PagedMemory::new();
+        memory.write(1_000_000_000_000, 42).unwrap();
+        memory.write(3, 1).unwrap();
+
+        assert_eq!(memory.pages(), 2);
+        assert_eq!(memory.read(1_000_000_000_000), 42);
+        assert_eq!(memory.len(), 1_000_000_000_001);
+
+        let chunks = memory.chunks();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(
+            chunks[0],
+            (0, [0, 0, 0, 1].iter().chain(&[0; 1020]).copied().collect())
+        );
+        assert_eq!(chunks[1].0 + chunks[1].1.len(), 1_000_000_000_001);
+    }
+
+    #[test]
+    fn test_bounded_memory_refuses_writes_past_limit() {
+        let mut memory = BoundedMemory::new(100);
+        memory.load(vec![0; 200]);
+
+        assert_eq!(memory.write(99, 1), Ok(0));
+        assert_eq!(
+            memory.write(100, 1),
+            Err(MemoryError {
+                address: 100,
+                limit: 100,
+            })
+        );
+    }
+}
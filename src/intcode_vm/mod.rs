@@ -1,14 +1,122 @@
+mod arithmetic;
+pub mod ascii;
+pub mod asm;
+pub mod big;
+pub mod cfg;
+mod compiled;
+pub mod debugger;
+pub mod decompile;
+mod disasm;
+pub mod fuel;
+pub mod io;
+pub mod lang;
+pub mod memory;
+pub mod network;
+pub mod profile;
+mod snapshot;
+#[cfg(test)]
+mod tests;
+pub mod trace;
+
 use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+pub use arithmetic::Arithmetic;
+pub use compiled::Backend;
+pub use disasm::{disassemble, disassemble_from, disassemble_memory, Instruction};
+pub use fuel::CostTable;
+use memory::{DenseMemory, Memory, MemoryError, Storage};
+pub use snapshot::SnapshotError;
+use trace::{NoopSink, TraceSink};
 
-#[derive(Copy, Clone, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum VMState {
     Halted,
     NeedsInput,
     Output(i64),
+    /// The fuel left can't pay for the next instruction.
+    OutOfFuel,
+    /// The deadline passed.
+    TimedOut,
+}
+
+/// Why the VM refused to carry on executing.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum VmErrorKind {
+    /// The low two digits of the word are not a known opcode.
+    UnknownOpcode(i64),
+    /// One of the parameter mode digits is not 0, 1 or 2.
+    InvalidParamMode(i64),
+    /// An instruction tried to write through an immediate mode parameter.
+    ImmediateWrite,
+    /// The instruction pointer ran past the end of memory.
+    InstructionPointerOutOfBounds,
+    /// The program asked for input while running in a context that can't provide any.
+    NotEnoughInput,
+    /// A parameter pointed at a negative address.
+    NegativeAddress(i64),
+    /// The VM's memory refused a write.
+    MemoryLimit(MemoryError),
+    /// An `add` or `mul` of these operands overflowed under
+    /// `Arithmetic::Checked`.
+    Overflow(i64, i64),
+    /// The program ran out of fuel while running in a context that can't
+    /// top it up.
+    OutOfFuel,
+    /// The program's deadline passed while running in a context that can't
+    /// extend it.
+    TimedOut,
+    /// A parameter's address, or the relative base, doesn't fit in an
+    /// `i64`.
+    AddressTooLarge,
+}
+
+impl fmt::Display for VmErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmErrorKind::UnknownOpcode(opcode) => write!(f, "unknown opcode {}", opcode),
+            VmErrorKind::InvalidParamMode(mode) => write!(f, "invalid parameter mode {}", mode),
+            VmErrorKind::ImmediateWrite => write!(f, "write through an immediate mode parameter"),
+            VmErrorKind::InstructionPointerOutOfBounds => {
+                write!(f, "instruction pointer is out of bounds")
+            }
+            VmErrorKind::NotEnoughInput => write!(f, "not enough input"),
+            VmErrorKind::NegativeAddress(address) => write!(f, "negative address {}", address),
+            VmErrorKind::MemoryLimit(error) => write!(f, "{}", error),
+            VmErrorKind::Overflow(a, b) => write!(f, "overflow with operands {} and {}", a, b),
+            VmErrorKind::OutOfFuel => write!(f, "out of fuel"),
+            VmErrorKind::TimedOut => write!(f, "timed out"),
+            VmErrorKind::AddressTooLarge => write!(f, "address is too large"),
+        }
+    }
 }
 
+/// A fault raised while executing an instruction.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
-enum Opcode {
+pub struct VmError {
+    pub instruction_pointer: usize,
+    /// The raw word at `instruction_pointer`, or 0 if the pointer is out of bounds.
+    pub word: i64,
+    pub opcode: Opcode,
+    pub kind: VmErrorKind,
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at address {} (word {}, {:?})",
+            self.kind, self.instruction_pointer, self.word, self.opcode
+        )
+    }
+}
+
+impl Error for VmError {}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Opcode {
     Add(ParamMode, ParamMode, ParamMode),
     Mul(ParamMode, ParamMode, ParamMode),
     Input(ParamMode),
@@ -22,10 +130,10 @@ enum Opcode {
     Invalid,
 }
 
-impl From<i64> for Opcode {
-    fn from(value: i64) -> Self {
-        let (opcode, param_mode_1, param_mode_2, param_mode_3) = parse_instruction(value);
-        match opcode {
+impl Opcode {
+    pub fn decode(value: i64) -> Result<Self, VmErrorKind> {
+        let (opcode, param_mode_1, param_mode_2, param_mode_3) = parse_instruction(value)?;
+        let opcode = match opcode {
             1 => Opcode::Add(param_mode_1, param_mode_2, param_mode_3),
             2 => Opcode::Mul(param_mode_1, param_mode_2, param_mode_3),
             3 => Opcode::Input(param_mode_1),
@@ -36,48 +144,172 @@ impl From<i64> for Opcode {
             8 => Opcode::Equals(param_mode_1, param_mode_2, param_mode_3),
             9 => Opcode::SetRB(param_mode_1),
             99 => Opcode::Halt,
-            _ => Opcode::Invalid,
+            _ => return Err(VmErrorKind::UnknownOpcode(opcode)),
+        };
+
+        Ok(opcode)
+    }
+
+    /// The most words any instruction occupies.
+    pub const MAX_SIZE: usize = 4;
+
+    /// Number of words the instruction occupies, including the opcode itself.
+    pub fn size(&self) -> usize {
+        match self {
+            Opcode::Add(..) | Opcode::Mul(..) | Opcode::LessThen(..) | Opcode::Equals(..) => 4,
+            Opcode::JmpIfZero(..) | Opcode::JmpIfNotZero(..) => 3,
+            Opcode::Input(..) | Opcode::Output(..) | Opcode::SetRB(..) => 2,
+            Opcode::Halt | Opcode::Invalid => 1,
+        }
+    }
+
+    pub fn param_modes(&self) -> Vec<ParamMode> {
+        match *self {
+            Opcode::Add(a, b, c)
+            | Opcode::Mul(a, b, c)
+            | Opcode::LessThen(a, b, c)
+            | Opcode::Equals(a, b, c) => vec![a, b, c],
+            Opcode::JmpIfZero(a, b) | Opcode::JmpIfNotZero(a, b) => vec![a, b],
+            Opcode::Input(a) | Opcode::Output(a) | Opcode::SetRB(a) => vec![a],
+            Opcode::Halt | Opcode::Invalid => vec![],
+        }
+    }
+
+    /// The instruction word this opcode decodes from. `Invalid` encodes as 0.
+    pub fn encode(&self) -> i64 {
+        let number = match self {
+            Opcode::Add(..) => 1,
+            Opcode::Mul(..) => 2,
+            Opcode::Input(..) => 3,
+            Opcode::Output(..) => 4,
+            Opcode::JmpIfNotZero(..) => 5,
+            Opcode::JmpIfZero(..) => 6,
+            Opcode::LessThen(..) => 7,
+            Opcode::Equals(..) => 8,
+            Opcode::SetRB(..) => 9,
+            Opcode::Halt => 99,
+            Opcode::Invalid => return 0,
+        };
+
+        let modes = self.param_modes().iter().rev().fold(0, |acc, mode| {
+            acc * 10
+                + match mode {
+                    ParamMode::Position => 0,
+                    ParamMode::Immediate => 1,
+                    ParamMode::Relative => 2,
+                }
+        });
+
+        modes * 100 + number
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Opcode::Add(..) => "add",
+            Opcode::Mul(..) => "mul",
+            Opcode::Input(..) => "in",
+            Opcode::Output(..) => "out",
+            Opcode::JmpIfNotZero(..) => "jnz",
+            Opcode::JmpIfZero(..) => "jz",
+            Opcode::LessThen(..) => "lt",
+            Opcode::Equals(..) => "eq",
+            Opcode::SetRB(..) => "arb",
+            Opcode::Halt => "hlt",
+            Opcode::Invalid => "data",
         }
     }
 }
 
+impl From<i64> for Opcode {
+    fn from(value: i64) -> Self {
+        Opcode::decode(value).unwrap_or(Opcode::Invalid)
+    }
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
-enum ParamMode {
+pub enum ParamMode {
     Position,
     Immediate,
     Relative,
 }
 
-impl From<i64> for ParamMode {
-    fn from(mode: i64) -> Self {
+impl TryFrom<i64> for ParamMode {
+    type Error = VmErrorKind;
+
+    fn try_from(mode: i64) -> Result<Self, Self::Error> {
         match mode {
-            0 => ParamMode::Position,
-            1 => ParamMode::Immediate,
-            2 => ParamMode::Relative,
-            _ => panic!("Invalid ParamMode"),
+            0 => Ok(ParamMode::Position),
+            1 => Ok(ParamMode::Immediate),
+            2 => Ok(ParamMode::Relative),
+            _ => Err(VmErrorKind::InvalidParamMode(mode)),
         }
     }
 }
 
-fn parse_instruction(instruction: i64) -> (i64, ParamMode, ParamMode, ParamMode) {
+/// Splits an instruction word into its opcode number and the three parameter modes.
+pub fn parse_instruction(
+    instruction: i64,
+) -> Result<(i64, ParamMode, ParamMode, ParamMode), VmErrorKind> {
     let opcode = instruction % 100;
     let param_mode_1 = (instruction / 100) % 10;
     let param_mode_2 = (instruction / 1000) % 10;
     let param_mode_3 = (instruction / 10000) % 10;
 
-    (
+    Ok((
         opcode,
-        ParamMode::from(param_mode_1),
-        ParamMode::from(param_mode_2),
-        ParamMode::from(param_mode_3),
-    )
+        ParamMode::try_from(param_mode_1)?,
+        ParamMode::try_from(param_mode_2)?,
+        ParamMode::try_from(param_mode_3)?,
+    ))
 }
 
+/// Whether executing `opcode` could stop execution: it does I/O, halts or
+/// is bound to fault by writing through an immediate parameter.
+fn can_stop(opcode: Opcode) -> bool {
+    match opcode {
+        Opcode::Add(.., out)
+        | Opcode::Mul(.., out)
+        | Opcode::LessThen(.., out)
+        | Opcode::Equals(.., out) => out == ParamMode::Immediate,
+        Opcode::JmpIfZero(..) | Opcode::JmpIfNotZero(..) | Opcode::SetRB(..) => false,
+        Opcode::Input(..) | Opcode::Output(..) | Opcode::Halt | Opcode::Invalid => true,
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct MemoryWrite {
+    pub address: usize,
+    pub old_value: i64,
+    pub new_value: i64,
+}
+
+/// An instruction as decoded from memory: the opcode and the raw words of
+/// its parameters.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct DecodedInstruction {
+    opcode: Opcode,
+    params: [i64; 3],
+}
+
+/// Instructions are only cached below this address, so code far out in
+/// sparse memory doesn't make the caches allocate everything before it.
+const CACHED_WORDS: usize = 1 << 20;
+
 #[derive(Clone, Default, Debug)]
 pub struct IntcodeVM {
-    memory: Vec<i64>,
+    memory: Storage,
+    /// Decoded instructions by address. An entry covers the words the
+    /// instruction occupies and is dropped whenever one of them is written.
+    decoded: Vec<Option<DecodedInstruction>>,
+    /// Whether each word has been part of a decoded or compiled instruction,
+    /// so writes to plain data can skip invalidating either cache.
+    code_words: Vec<bool>,
+    backend: Backend,
+    arithmetic: Arithmetic,
+    code: compiled::CodeCache,
+    fuel: fuel::Fuel,
     instruction_pointer: usize,
-    relative_pointer: usize,
+    relative_pointer: i64,
     current_opcode: Option<Opcode>,
     current_params: [i64; 3],
     inputs: VecDeque<i64>,
@@ -86,8 +318,23 @@ pub struct IntcodeVM {
 
 impl IntcodeVM {
     pub fn new() -> Self {
+        IntcodeVM::with_storage(Storage::Dense(DenseMemory::new()))
+    }
+
+    /// A VM that keeps its memory in `memory` instead of a `DenseMemory`.
+    pub fn with_memory<M: Memory + 'static>(memory: M) -> Self {
+        IntcodeVM::with_storage(Storage::Other(Box::new(memory)))
+    }
+
+    fn with_storage(memory: Storage) -> Self {
         IntcodeVM {
-            memory: vec![],
+            memory,
+            decoded: vec![],
+            code_words: vec![],
+            backend: Backend::Interpreter,
+            arithmetic: Arithmetic::Wrapping,
+            code: compiled::CodeCache::default(),
+            fuel: fuel::Fuel::default(),
             instruction_pointer: 0,
             relative_pointer: 0,
             current_opcode: None,
@@ -98,11 +345,17 @@ impl IntcodeVM {
     }
 
     pub fn set_program(&mut self, program: Vec<i64>) {
-        self.memory = program;
+        self.memory.get_mut().load(program);
+        self.decoded.clear();
+        self.code_words.clear();
+        self.code.clear();
     }
 
     pub fn reset(&mut self) {
-        self.memory.clear();
+        self.memory.get_mut().clear();
+        self.decoded.clear();
+        self.code_words.clear();
+        self.code.clear();
         self.instruction_pointer = 0;
         self.relative_pointer = 0;
         self.current_opcode = None;
@@ -111,167 +364,470 @@ impl IntcodeVM {
         self.jumped = false;
     }
 
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Picks how `execute` runs the program from now on.
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+    }
+
+    pub fn arithmetic(&self) -> Arithmetic {
+        self.arithmetic
+    }
+
+    /// Picks what `add` and `mul` do when their result overflows.
+    pub fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.arithmetic = arithmetic;
+    }
+
     pub fn push_input(&mut self, value: i64) {
         self.inputs.push_back(value);
     }
 
+    pub fn instruction_pointer(&self) -> usize {
+        self.instruction_pointer
+    }
+
+    /// Moves execution to `address`, which also un-halts a halted VM.
+    pub fn set_instruction_pointer(&mut self, address: usize) {
+        self.instruction_pointer = address;
+        self.current_opcode = None;
+        self.jumped = false;
+    }
+
+    /// The relative base, which may be negative as long as the addresses
+    /// relative parameters resolve to aren't.
+    pub fn relative_pointer(&self) -> i64 {
+        self.relative_pointer
+    }
+
+    pub fn set_relative_pointer(&mut self, address: i64) {
+        self.relative_pointer = address;
+    }
+
+    /// The inputs that have been pushed but not consumed yet.
+    pub fn inputs(&self) -> &VecDeque<i64> {
+        &self.inputs
+    }
+
+    pub fn memory(&self) -> &dyn Memory {
+        self.memory.get()
+    }
+
+    /// Reads `address`; memory past the end of the program reads as 0.
+    pub fn read_memory(&self, address: usize) -> i64 {
+        self.load_memory(address)
+    }
+
+    /// Writes `value` to `address`, growing memory if needed.
+    pub fn write_memory(&mut self, address: usize, value: i64) -> Result<(), VmError> {
+        self.save_memory(address, value, &mut NoopSink)
+    }
+
     fn load_memory(&self, position: usize) -> i64 {
-        match self.memory.get(position) {
-            Some(value) => *value,
-            None => 0,
+        self.memory.read(position)
+    }
+
+    fn save_memory<S: TraceSink + ?Sized>(
+        &mut self,
+        position: usize,
+        new_value: i64,
+        sink: &mut S,
+    ) -> Result<(), VmError> {
+        let old_value = match self.memory.write(position, new_value) {
+            Ok(old_value) => old_value,
+            Err(error) => return Err(self.fault(VmErrorKind::MemoryLimit(error))),
+        };
+
+        if self.code_words.get(position) == Some(&true) {
+            // Drop every cached instruction that could include this word.
+            let end = (position + 1).min(self.decoded.len());
+            let start = position.saturating_sub(Opcode::MAX_SIZE - 1).min(end);
+            for entry in &mut self.decoded[start..end] {
+                *entry = None;
+            }
+            self.code.invalidate(position);
         }
+
+        sink.memory_write(MemoryWrite {
+            address: position,
+            old_value,
+            new_value,
+        });
+        Ok(())
     }
 
-    fn load_memory_by_pointer(&self, position: usize) -> i64 {
-        self.load_memory(self.load_memory(position) as usize)
+    /// Resolves the value of an input parameter from its raw word, reporting
+    /// any memory read to `sink`.
+    #[inline]
+    fn get_param<S: TraceSink + ?Sized>(
+        &self,
+        mode: ParamMode,
+        raw: i64,
+        sink: &mut S,
+    ) -> Result<i64, VmError> {
+        let address = match mode {
+            ParamMode::Immediate => return Ok(raw),
+            ParamMode::Position => self.check_address(raw)?,
+            ParamMode::Relative => self.check_address(self.relative_address(raw)?)?,
+        };
+        let value = self.load_memory(address);
+        sink.memory_read(address, value);
+        Ok(value)
     }
 
-    fn save_memory(&mut self, position: usize, new_value: i64) {
-        match self.memory.get_mut(position) {
-            Some(value) => *value = new_value,
-            None => {
-                self.memory.resize(position + 1, 0i64);
-                self.memory[position] = new_value;
-            }
+    /// Resolves the address an output parameter writes to.
+    #[inline]
+    fn get_param_address(&self, mode: ParamMode, raw: i64) -> Result<i64, VmError> {
+        let address = match mode {
+            ParamMode::Immediate => return Err(self.fault(VmErrorKind::ImmediateWrite)),
+            ParamMode::Position => raw,
+            ParamMode::Relative => self.relative_address(raw)?,
+        };
+        self.check_address(address)?;
+        Ok(address)
+    }
+
+    #[inline]
+    fn relative_address(&self, raw: i64) -> Result<i64, VmError> {
+        self.relative_pointer
+            .checked_add(raw)
+            .ok_or_else(|| self.fault(VmErrorKind::AddressTooLarge))
+    }
+
+    #[inline]
+    fn check_address(&self, address: i64) -> Result<usize, VmError> {
+        if address < 0 {
+            return Err(self.fault(VmErrorKind::NegativeAddress(address)));
         }
+        Ok(address as usize)
     }
 
-    fn save_memory_by_pointer(&mut self, position: usize, value: i64) {
-        self.save_memory(self.load_memory(position) as usize, value)
+    #[inline]
+    fn add(&self, a: i64, b: i64) -> Result<i64, VmError> {
+        match self.arithmetic.add(a, b) {
+            Some(sum) => Ok(sum),
+            None => Err(self.fault(VmErrorKind::Overflow(a, b))),
+        }
     }
 
-    fn get_param(&self, mode: ParamMode, offset: usize) -> i64 {
-        match mode {
-            ParamMode::Immediate => self.load_memory(self.instruction_pointer + offset),
-            ParamMode::Position => self.load_memory_by_pointer(self.instruction_pointer + offset),
-            ParamMode::Relative => {
-                let relative_offset = self.load_memory(self.instruction_pointer + offset) as usize;
-                self.load_memory(self.relative_pointer + relative_offset)
-            }
+    #[inline]
+    fn mul(&self, a: i64, b: i64) -> Result<i64, VmError> {
+        match self.arithmetic.mul(a, b) {
+            Some(product) => Ok(product),
+            None => Err(self.fault(VmErrorKind::Overflow(a, b))),
         }
     }
 
-    fn set_param(&mut self, mode: ParamMode, offset: usize, value: i64) {
-        match mode {
-            ParamMode::Immediate => panic!("Immediate mode is not allowed when setting memory!"),
-            ParamMode::Position => {
-                self.save_memory_by_pointer(self.instruction_pointer + offset, value)
+    fn mark_code(&mut self, start: usize, end: usize) {
+        if self.code_words.len() < end {
+            self.code_words.resize(end, false);
+        }
+        for word in &mut self.code_words[start..end] {
+            *word = true;
+        }
+    }
+
+    fn add_relative_pointer(&mut self, offset: i64) -> Result<(), VmError> {
+        self.relative_pointer = self.relative_address(offset)?;
+        Ok(())
+    }
+
+    /// Resolves the parameters of `instruction` into `current_params`: the
+    /// value for input parameters and the target address for output
+    /// parameters. Returns the number of parameters.
+    fn resolve_params<S: TraceSink + ?Sized>(
+        &mut self,
+        instruction: DecodedInstruction,
+        sink: &mut S,
+    ) -> Result<usize, VmError> {
+        let [raw1, raw2, raw3] = instruction.params;
+        let opcode = instruction.opcode;
+        let params = match opcode {
+            Opcode::Add(in1, in2, out)
+            | Opcode::Mul(in1, in2, out)
+            | Opcode::LessThen(in1, in2, out)
+            | Opcode::Equals(in1, in2, out) => [
+                self.get_param(in1, raw1, sink)?,
+                self.get_param(in2, raw2, sink)?,
+                self.get_param_address(out, raw3)?,
+            ],
+            Opcode::JmpIfZero(in1, in2) | Opcode::JmpIfNotZero(in1, in2) => [
+                self.get_param(in1, raw1, sink)?,
+                self.get_param(in2, raw2, sink)?,
+                0,
+            ],
+            Opcode::Input(out) => [self.get_param_address(out, raw1)?, 0, 0],
+            Opcode::Output(in1) | Opcode::SetRB(in1) => [self.get_param(in1, raw1, sink)?, 0, 0],
+            Opcode::Halt | Opcode::Invalid => [0; 3],
+        };
+
+        self.current_params = params;
+        Ok(opcode.size() - 1)
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn fault(&self, kind: VmErrorKind) -> VmError {
+        VmError {
+            instruction_pointer: self.instruction_pointer,
+            word: self.load_memory(self.instruction_pointer),
+            opcode: self.current_opcode.unwrap_or(Opcode::Invalid),
+            kind,
+        }
+    }
+
+    /// Decodes the instruction at the instruction pointer, from the cache if
+    /// it has been decoded before, and makes it the current opcode.
+    fn parse_current_instruction(&mut self) -> Result<DecodedInstruction, VmError> {
+        let address = self.instruction_pointer;
+        if let Some(Some(instruction)) = self.decoded.get(address) {
+            self.current_opcode = Some(instruction.opcode);
+            return Ok(*instruction);
+        }
+
+        if address >= self.memory.len() {
+            self.current_opcode = None;
+            return Err(self.fault(VmErrorKind::InstructionPointerOutOfBounds));
+        }
+        let word = self.load_memory(address);
+
+        let opcode = match Opcode::decode(word) {
+            Ok(opcode) => opcode,
+            Err(kind) => {
+                self.current_opcode = Some(Opcode::Invalid);
+                return Err(self.fault(kind));
             }
-            ParamMode::Relative => {
-                let relative_offset = self.load_memory(self.instruction_pointer + offset) as usize;
-                self.save_memory(self.relative_pointer + relative_offset, value)
+        };
+
+        let mut params = [0; 3];
+        for (offset, param) in params.iter_mut().take(opcode.size() - 1).enumerate() {
+            *param = self.load_memory(address + offset + 1);
+        }
+
+        let instruction = DecodedInstruction { opcode, params };
+        if address < CACHED_WORDS {
+            if self.decoded.len() <= address {
+                let len = self.memory.len().min(CACHED_WORDS).max(address + 1);
+                self.decoded.resize(len, None);
             }
+            self.decoded[address] = Some(instruction);
+            self.mark_code(address, address + opcode.size());
         }
-    }
+        self.current_opcode = Some(opcode);
 
-    fn parse_current_opcode(&self) -> Opcode {
-        Opcode::from(self.memory[self.instruction_pointer])
+        Ok(instruction)
     }
 
     fn advance_parser(&mut self) {
         if !self.jumped {
-            self.instruction_pointer += match self.current_opcode.unwrap() {
-                Opcode::Add(..) | Opcode::Mul(..) | Opcode::LessThen(..) | Opcode::Equals(..) => 4,
-                Opcode::Input(..) | Opcode::Output(..) | Opcode::SetRB(..) => 2,
-                Opcode::JmpIfZero(..) | Opcode::JmpIfNotZero(..) => 3,
-                Opcode::Halt => 1,
-                Opcode::Invalid => panic!("Invalid Opcode!"),
-            };
+            self.instruction_pointer += self.current_opcode.unwrap().size();
         }
 
         self.jumped = false;
+    }
 
-        self.current_opcode = Some(self.parse_current_opcode());
+    /// Executes a single instruction. Returns the resulting state if the
+    /// instruction halted, produced an output or is waiting for input, and
+    /// `None` if execution can simply carry on.
+    pub fn step(&mut self) -> Result<Option<VMState>, VmError> {
+        self.step_traced(&mut NoopSink)
     }
 
-    pub fn execute(&mut self) -> VMState {
+    /// Like `step`, but reports the executed instruction and any memory
+    /// write to `sink`.
+    pub fn step_traced<S: TraceSink + ?Sized>(
+        &mut self,
+        sink: &mut S,
+    ) -> Result<Option<VMState>, VmError> {
         if self.current_opcode == Some(Opcode::Halt) {
-            return VMState::Halted;
+            return Ok(Some(VMState::Halted));
         }
 
-        self.current_opcode = Some(self.parse_current_opcode());
+        let instruction = self.parse_current_instruction()?;
+        let opcode = instruction.opcode;
+        if let Opcode::Input(_) = opcode {
+            if self.inputs.is_empty() {
+                return Ok(Some(VMState::NeedsInput));
+            }
+        }
+        if let Some(state) = self.fuel.charge(self.fuel.costs.cost(opcode)) {
+            return Ok(Some(state));
+        }
 
-        let mut output = None;
+        let param_count = self.resolve_params(instruction, sink)?;
+        sink.instruction(
+            self.instruction_pointer,
+            opcode,
+            &self.current_params[..param_count],
+        );
 
-        loop {
-            if let Some(output) = output {
-                return VMState::Output(output);
+        let [param1, param2, param3] = self.current_params;
+        match opcode {
+            Opcode::Add(..) => {
+                let sum = self.add(param1, param2)?;
+                self.save_memory(param3 as usize, sum, sink)?;
             }
-
-            match self.current_opcode.unwrap() {
-                Opcode::Add(in1, in2, out) => {
-                    let result = self.get_param(in1, 1) + self.get_param(in2, 2);
-                    self.set_param(out, 3, result);
-                }
-                Opcode::Mul(in1, in2, out) => {
-                    let result = self.get_param(in1, 1) * self.get_param(in2, 2);
-                    self.set_param(out, 3, result);
-                }
-                Opcode::Input(out) => {
-                    let value = match self.inputs.pop_front() {
-                        Some(x) => x,
-                        None => return VMState::NeedsInput,
-                    };
-                    self.set_param(out, 1, value);
-                }
-                Opcode::Output(in1) => {
-                    output = Some(self.get_param(in1, 1));
-                }
-                Opcode::JmpIfZero(in1, in2) => {
-                    let value = self.get_param(in1, 1);
-                    let new_pointer = self.get_param(in2, 2);
-                    if value == 0 {
-                        self.instruction_pointer = new_pointer as usize;
-                        self.jumped = true;
-                    }
-                }
-                Opcode::JmpIfNotZero(in1, in2) => {
-                    let value = self.get_param(in1, 1);
-                    let new_pointer = self.get_param(in2, 2);
-                    if value != 0 {
-                        self.instruction_pointer = new_pointer as usize;
-                        self.jumped = true;
-                    }
+            Opcode::Mul(..) => {
+                let product = self.mul(param1, param2)?;
+                self.save_memory(param3 as usize, product, sink)?;
+            }
+            Opcode::Input(_) => {
+                let value = self.inputs[0];
+                self.save_memory(param1 as usize, value, sink)?;
+                self.inputs.pop_front();
+            }
+            Opcode::Output(_) => {
+                self.advance_parser();
+                return Ok(Some(VMState::Output(param1)));
+            }
+            Opcode::JmpIfZero(..) => {
+                if param1 == 0 {
+                    self.instruction_pointer = param2 as usize;
+                    self.jumped = true;
                 }
-                Opcode::LessThen(in1, in2, out) => {
-                    let value1 = self.get_param(in1, 1);
-                    let value2 = self.get_param(in2, 2);
-                    let result = if value1 < value2 { 1 } else { 0 };
-                    self.set_param(out, 3, result);
+            }
+            Opcode::JmpIfNotZero(..) => {
+                if param1 != 0 {
+                    self.instruction_pointer = param2 as usize;
+                    self.jumped = true;
                 }
-                Opcode::Equals(in1, in2, out) => {
-                    let value1 = self.get_param(in1, 1);
-                    let value2 = self.get_param(in2, 2);
-                    let result = if value1 == value2 { 1 } else { 0 };
-                    self.set_param(out, 3, result);
+            }
+            Opcode::LessThen(..) => {
+                let result = if param1 < param2 { 1 } else { 0 };
+                self.save_memory(param3 as usize, result, sink)?;
+            }
+            Opcode::Equals(..) => {
+                let result = if param1 == param2 { 1 } else { 0 };
+                self.save_memory(param3 as usize, result, sink)?;
+            }
+            Opcode::SetRB(_) => self.add_relative_pointer(param1)?,
+            Opcode::Halt => return Ok(Some(VMState::Halted)),
+            Opcode::Invalid => unreachable!("rejected by parse_current_instruction"),
+        }
+
+        self.advance_parser();
+
+        Ok(None)
+    }
+
+    /// Runs a decoded instruction that `can_stop` says can't stop execution,
+    /// unless it runs out of fuel or time first, like `step_traced` does for
+    /// the rest.
+    #[inline(always)]
+    fn run_decoded<S: TraceSink + ?Sized>(
+        &mut self,
+        address: usize,
+        instruction: DecodedInstruction,
+        sink: &mut S,
+    ) -> Result<Option<VMState>, VmError> {
+        let opcode = instruction.opcode;
+        self.current_opcode = Some(opcode);
+        if let Some(state) = self.fuel.charge(self.fuel.costs.cost(opcode)) {
+            return Ok(Some(state));
+        }
+        let [raw1, raw2, raw3] = instruction.params;
+        let mut next = address + opcode.size();
+        match opcode {
+            Opcode::Add(in1, in2, out) => {
+                let param1 = self.get_param(in1, raw1, sink)?;
+                let param2 = self.get_param(in2, raw2, sink)?;
+                let target = self.get_param_address(out, raw3)?;
+                sink.instruction(address, opcode, &[param1, param2, target]);
+                let sum = self.add(param1, param2)?;
+                self.save_memory(target as usize, sum, sink)?;
+            }
+            Opcode::Mul(in1, in2, out) => {
+                let param1 = self.get_param(in1, raw1, sink)?;
+                let param2 = self.get_param(in2, raw2, sink)?;
+                let target = self.get_param_address(out, raw3)?;
+                sink.instruction(address, opcode, &[param1, param2, target]);
+                let product = self.mul(param1, param2)?;
+                self.save_memory(target as usize, product, sink)?;
+            }
+            Opcode::LessThen(in1, in2, out) => {
+                let param1 = self.get_param(in1, raw1, sink)?;
+                let param2 = self.get_param(in2, raw2, sink)?;
+                let target = self.get_param_address(out, raw3)?;
+                sink.instruction(address, opcode, &[param1, param2, target]);
+                self.save_memory(target as usize, (param1 < param2) as i64, sink)?;
+            }
+            Opcode::Equals(in1, in2, out) => {
+                let param1 = self.get_param(in1, raw1, sink)?;
+                let param2 = self.get_param(in2, raw2, sink)?;
+                let target = self.get_param_address(out, raw3)?;
+                sink.instruction(address, opcode, &[param1, param2, target]);
+                self.save_memory(target as usize, (param1 == param2) as i64, sink)?;
+            }
+            Opcode::JmpIfZero(in1, in2) | Opcode::JmpIfNotZero(in1, in2) => {
+                let param1 = self.get_param(in1, raw1, sink)?;
+                let param2 = self.get_param(in2, raw2, sink)?;
+                sink.instruction(address, opcode, &[param1, param2]);
+
+                if (param1 == 0) == matches!(opcode, Opcode::JmpIfZero(..)) {
+                    next = param2 as usize;
                 }
-                Opcode::SetRB(in1) => {
-                    let offset = self.get_param(in1, 1);
-                    self.relative_pointer = (self.relative_pointer as i64 + offset).max(0) as usize;
+            }
+            Opcode::SetRB(in1) => {
+                let param1 = self.get_param(in1, raw1, sink)?;
+                sink.instruction(address, opcode, &[param1]);
+                self.add_relative_pointer(param1)?;
+            }
+            _ => unreachable!("left to step_traced"),
+        }
+
+        self.instruction_pointer = next;
+        Ok(None)
+    }
 
-                    if self.relative_pointer > self.memory.len() {
-                        self.memory.resize(self.relative_pointer, 0);
+    pub fn execute(&mut self) -> Result<VMState, VmError> {
+        match self.backend {
+            Backend::Interpreter => self.execute_traced(&mut NoopSink),
+            Backend::Compiled => self.execute_compiled(),
+        }
+    }
+
+    /// Like `execute`, but reports every executed instruction and memory
+    /// write to `sink`.
+    ///
+    /// Decoded instructions that can't stop execution run straight from the
+    /// decode cache; everything else goes through `step_traced`.
+    pub fn execute_traced<S: TraceSink + ?Sized>(
+        &mut self,
+        sink: &mut S,
+    ) -> Result<VMState, VmError> {
+        loop {
+            let address = self.instruction_pointer;
+            let instruction = match self.decoded.get(address) {
+                Some(Some(instruction)) if !can_stop(instruction.opcode) => *instruction,
+                _ => {
+                    if let Some(state) = self.step_traced(sink)? {
+                        return Ok(state);
                     }
+                    continue;
                 }
-                Opcode::Halt => return VMState::Halted,
-                Opcode::Invalid => panic!("Invalid Opcode!"),
+            };
+            if let Some(state) = self.run_decoded(address, instruction, sink)? {
+                return Ok(state);
             }
-
-            self.advance_parser();
         }
     }
 
-    pub fn run_once(&mut self) -> Vec<i64> {
+    pub fn run_once(&mut self) -> Result<Vec<i64>, VmError> {
         let mut output = vec![];
 
         loop {
-            match self.execute() {
+            match self.execute()? {
                 VMState::Halted => break,
-                VMState::NeedsInput => panic!("Not enough input"),
+                VMState::NeedsInput => return Err(self.fault(VmErrorKind::NotEnoughInput)),
                 VMState::Output(value) => output.push(value),
+                VMState::OutOfFuel => return Err(self.fault(VmErrorKind::OutOfFuel)),
+                VMState::TimedOut => return Err(self.fault(VmErrorKind::TimedOut)),
             }
         }
 
-        output
+        Ok(output)
     }
 }
@@ -0,0 +1,413 @@
+//! Networks of VMs whose outputs feed each other's inputs, such as day 7's
+//! amplifiers.
+//!
+//! Every node is a VM with a single input queue. Its outputs are copied to
+//! each of its targets, which are other nodes or named ports that collect
+//! values leaving the network. A network runs in rounds: every node that
+//! can make progress runs until it halts or wants input nobody has sent yet,
+//! then its outputs are delivered. Outputs are delivered in node order
+//! after every node has run, so a network behaves the same whether its
+//! nodes run one after the other or in parallel.
+
+use std::error::Error;
+use std::fmt;
+
+use rayon::prelude::*;
+
+use super::{IntcodeVM, VMState, VmError};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct NodeId(usize);
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct PortId(usize);
+
+/// Where a node's outputs go.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Target {
+    Node(NodeId),
+    Port(PortId),
+}
+
+impl From<NodeId> for Target {
+    fn from(node: NodeId) -> Self {
+        Target::Node(node)
+    }
+}
+
+impl From<PortId> for Target {
+    fn from(port: PortId) -> Self {
+        Target::Port(port)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum NodeState {
+    /// Hasn't run yet.
+    Ready,
+    NeedsInput,
+    Halted,
+    /// Ran out of fuel or time. Runs again the next time the network runs.
+    Paused,
+}
+
+/// How a network stopped.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum NetworkState {
+    /// Every node halted.
+    Halted,
+    /// Every node that hasn't halted is waiting for input nobody will send.
+    Deadlocked,
+    /// A node ran out of fuel or time. Top it up and run the network again
+    /// to carry on.
+    Paused,
+}
+
+/// A node's VM faulted.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NetworkError {
+    pub node: String,
+    pub error: VmError,
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "node {}: {}", self.node, self.error)
+    }
+}
+
+impl Error for NetworkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    name: String,
+    vm: IntcodeVM,
+    targets: Vec<Target>,
+    state: NodeState,
+}
+
+impl Node {
+    fn can_run(&self) -> bool {
+        match self.state {
+            NodeState::Ready => true,
+            NodeState::NeedsInput => !self.vm.inputs().is_empty(),
+            NodeState::Halted | NodeState::Paused => false,
+        }
+    }
+
+    /// Runs until the VM stops, returning its outputs.
+    fn run(&mut self) -> Result<Vec<i64>, NetworkError> {
+        let mut outputs = vec![];
+        let state = self
+            .vm
+            .run_with(&mut || None, &mut outputs)
+            .map_err(|error| NetworkError {
+                node: self.name.clone(),
+                error,
+            })?;
+
+        self.state = match state {
+            VMState::Halted => NodeState::Halted,
+            VMState::OutOfFuel | VMState::TimedOut => NodeState::Paused,
+            _ => NodeState::NeedsInput,
+        };
+        Ok(outputs)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Port {
+    name: String,
+    values: Vec<i64>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Network {
+    nodes: Vec<Node>,
+    ports: Vec<Port>,
+}
+
+impl Network {
+    pub fn new() -> Self {
+        Network::default()
+    }
+
+    /// Adds a node running `vm`.
+    ///
+    /// # Panics
+    ///
+    /// If there's already a node called `name`.
+    pub fn add_node(&mut self, name: &str, vm: IntcodeVM) -> NodeId {
+        assert!(self.node(name).is_none(), "duplicate node {}", name);
+        self.nodes.push(Node {
+            name: name.to_string(),
+            vm,
+            targets: vec![],
+            state: NodeState::Ready,
+        });
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Adds a port that collects every value sent to it.
+    ///
+    /// # Panics
+    ///
+    /// If there's already a port called `name`.
+    pub fn add_port(&mut self, name: &str) -> PortId {
+        assert!(self.port(name).is_none(), "duplicate port {}", name);
+        self.ports.push(Port {
+            name: name.to_string(),
+            values: vec![],
+        });
+        PortId(self.ports.len() - 1)
+    }
+
+    pub fn node(&self, name: &str) -> Option<NodeId> {
+        self.nodes
+            .iter()
+            .position(|node| node.name == name)
+            .map(NodeId)
+    }
+
+    pub fn port(&self, name: &str) -> Option<PortId> {
+        self.ports
+            .iter()
+            .position(|port| port.name == name)
+            .map(PortId)
+    }
+
+    /// Sends every output of `from` to `to`, as well as to anything it's
+    /// already connected to.
+    pub fn connect<T: Into<Target>>(&mut self, from: NodeId, to: T) {
+        self.nodes[from.0].targets.push(to.into());
+    }
+
+    /// Connects each node to the next.
+    pub fn chain(&mut self, nodes: &[NodeId]) {
+        for pair in nodes.windows(2) {
+            self.connect(pair[0], pair[1]);
+        }
+    }
+
+    /// Connects each node to the next, and the last to the first.
+    pub fn ring(&mut self, nodes: &[NodeId]) {
+        self.chain(nodes);
+        if let (Some(&last), Some(&first)) = (nodes.last(), nodes.first()) {
+            self.connect(last, first);
+        }
+    }
+
+    /// Queues inputs for a node, ahead of anything sent to it later.
+    pub fn seed<I: IntoIterator<Item = i64>>(&mut self, node: NodeId, inputs: I) {
+        for value in inputs {
+            self.nodes[node.0].vm.push_input(value);
+        }
+    }
+
+    pub fn vm(&self, node: NodeId) -> &IntcodeVM {
+        &self.nodes[node.0].vm
+    }
+
+    pub fn vm_mut(&mut self, node: NodeId) -> &mut IntcodeVM {
+        &mut self.nodes[node.0].vm
+    }
+
+    pub fn state(&self, node: NodeId) -> NodeState {
+        self.nodes[node.0].state
+    }
+
+    /// Everything sent to a port so far.
+    pub fn values(&self, port: PortId) -> &[i64] {
+        &self.ports[port.0].values
+    }
+
+    /// Runs the nodes one after the other until they all halt or deadlock,
+    /// or one of them runs out of fuel or time.
+    pub fn run(&mut self) -> Result<NetworkState, NetworkError> {
+        self.run_rounds(|nodes| nodes.iter_mut().map(Self::run_node).collect())
+    }
+
+    /// Like `run`, but runs the nodes of every round in parallel.
+    pub fn run_parallel(&mut self) -> Result<NetworkState, NetworkError> {
+        self.run_rounds(|nodes| nodes.par_iter_mut().map(Self::run_node).collect())
+    }
+
+    fn run_node(node: &mut Node) -> Result<Vec<i64>, NetworkError> {
+        if node.can_run() {
+            node.run()
+        } else {
+            Ok(vec![])
+        }
+    }
+
+    fn run_rounds<F>(&mut self, mut run_round: F) -> Result<NetworkState, NetworkError>
+    where
+        F: FnMut(&mut [Node]) -> Vec<Result<Vec<i64>, NetworkError>>,
+    {
+        for node in &mut self.nodes {
+            if node.state == NodeState::Paused {
+                node.state = NodeState::Ready;
+            }
+        }
+
+        while self.nodes.iter().any(Node::can_run) {
+            let outputs = run_round(&mut self.nodes)
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()?;
+            for (node, values) in outputs.iter().enumerate() {
+                self.deliver(NodeId(node), values);
+            }
+        }
+
+        if self
+            .nodes
+            .iter()
+            .any(|node| node.state == NodeState::Paused)
+        {
+            Ok(NetworkState::Paused)
+        } else if self
+            .nodes
+            .iter()
+            .all(|node| node.state == NodeState::Halted)
+        {
+            Ok(NetworkState::Halted)
+        } else {
+            Ok(NetworkState::Deadlocked)
+        }
+    }
+
+    fn deliver(&mut self, from: NodeId, values: &[i64]) {
+        for index in 0..self.nodes[from.0].targets.len() {
+            match self.nodes[from.0].targets[index] {
+                Target::Node(node) => {
+                    for value in values {
+                        self.nodes[node.0].vm.push_input(*value);
+                    }
+                }
+                Target::Port(port) => self.ports[port.0].values.extend_from_slice(values),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Network, NetworkState, NodeState};
+    use crate::input;
+    use crate::intcode_vm::asm::assemble;
+    use crate::{IntcodeVM, VmErrorKind};
+
+    fn vm(source: &str) -> IntcodeVM {
+        let mut vm = IntcodeVM::new();
+        vm.set_program(assemble(source).unwrap());
+        vm
+    }
+
+    /// Adds one to its input, then halts.
+    const INCREMENT: &str = "in [x]\nadd [x], #1, [x]\nout [x]\nhlt\nx: data 0";
+
+    #[test]
+    fn test_amplifier_ring() {
+        let program = input::parse_intcode(include_str!("../../inputs/day07_test.txt")).unwrap();
+
+        for parallel in [false, true].iter() {
+            let mut network = Network::new();
+            let amps = [9, 8, 7, 6, 5]
+                .iter()
+                .enumerate()
+                .map(|(index, phase)| {
+                    let mut amp = IntcodeVM::new();
+                    amp.set_program(program.clone());
+                    let amp = network.add_node(&format!("amp {}", index), amp);
+                    network.seed(amp, vec![*phase]);
+                    amp
+                })
+                .collect::<Vec<_>>();
+            let thrusters = network.add_port("thrusters");
+            network.ring(&amps);
+            network.connect(amps[4], thrusters);
+            network.seed(amps[0], vec![0]);
+
+            let state = if *parallel {
+                network.run_parallel()
+            } else {
+                network.run()
+            };
+            assert_eq!(state.unwrap(), NetworkState::Halted);
+            assert_eq!(network.values(thrusters).last(), Some(&139629729));
+        }
+    }
+
+    #[test]
+    fn test_fan_out_and_fan_in() {
+        let mut network = Network::new();
+        let source = network.add_node("source", vm(INCREMENT));
+        let left = network.add_node("left", vm(INCREMENT));
+        let right = network.add_node("right", vm(INCREMENT));
+        let sink = network.add_node(
+            "sink",
+            vm("in [x]\nin [y]\nmul [x], [y], [x]\nout [x]\nhlt\nx: data 0\ny: data 0"),
+        );
+        let result = network.add_port("result");
+
+        network.connect(source, left);
+        network.connect(source, right);
+        network.connect(left, sink);
+        network.connect(right, sink);
+        network.connect(sink, result);
+        network.seed(source, vec![1]);
+
+        assert_eq!(network.run().unwrap(), NetworkState::Halted);
+        assert_eq!(network.values(result), &[9]);
+        assert_eq!(network.node("sink"), Some(sink));
+        assert_eq!(network.port("result"), Some(result));
+    }
+
+    #[test]
+    fn test_deadlock() {
+        let mut network = Network::new();
+        let first = network.add_node("first", vm(INCREMENT));
+        let second = network.add_node("second", vm(INCREMENT));
+        network.ring(&[first, second]);
+
+        assert_eq!(network.run_parallel().unwrap(), NetworkState::Deadlocked);
+        assert_eq!(network.state(first), NodeState::NeedsInput);
+        assert_eq!(network.state(second), NodeState::NeedsInput);
+    }
+
+    #[test]
+    fn test_paused_node_resumes_after_refuelling() {
+        let mut network = Network::new();
+        let first = network.add_node("first", vm(INCREMENT));
+        let second = network.add_node("second", vm(INCREMENT));
+        let result = network.add_port("result");
+        network.chain(&[first, second]);
+        network.connect(second, result);
+        network.seed(first, vec![1]);
+        network.vm_mut(second).set_fuel(Some(2));
+
+        assert_eq!(network.run().unwrap(), NetworkState::Paused);
+        assert_eq!(network.state(second), NodeState::Paused);
+
+        network.vm_mut(second).add_fuel(10);
+        assert_eq!(network.run().unwrap(), NetworkState::Halted);
+        assert_eq!(network.values(result), &[3]);
+    }
+
+    #[test]
+    fn test_fault_names_the_node() {
+        let mut network = Network::new();
+        let first = network.add_node("first", vm(INCREMENT));
+        let broken = network.add_node("broken", vm("in [0]\ndata 42"));
+        network.chain(&[first, broken]);
+        network.seed(first, vec![1]);
+
+        let error = network.run().unwrap_err();
+        assert_eq!(error.node, "broken");
+        assert_eq!(error.error.kind, VmErrorKind::UnknownOpcode(42));
+    }
+}
@@ -0,0 +1,328 @@
+//! Finding where an Intcode program spends its time.
+//!
+//! `Profiler` is a `TraceSink`, so it sees every instruction a program runs
+//! under `IntcodeVM::execute_traced`. It counts executions per address and
+//! per opcode, reads and writes per address, and taken jumps back to an
+//! earlier address, each of which closes a loop.
+//!
+//! Intcode has no calls, so the folded stacks `write_folded` produces for
+//! flamegraphs are built from loops instead: every instruction sits under
+//! the loops whose bodies contain it, outermost first.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use super::disasm::disassemble;
+use super::trace::TraceSink;
+use super::{MemoryWrite, Opcode};
+
+/// A loop, found from a jump that went back to an earlier address.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Loop {
+    /// The jump target, where every iteration starts.
+    pub head: usize,
+    /// The address of the jump.
+    pub tail: usize,
+    /// How often the jump was taken.
+    pub iterations: u64,
+    /// Instructions executed from `head` to `tail` inclusive, in any
+    /// iteration of this loop or any other.
+    pub instructions: u64,
+}
+
+impl Loop {
+    pub fn contains(&self, address: usize) -> bool {
+        (self.head..=self.tail).contains(&address)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Hits {
+    /// The last opcode executed at the address.
+    opcode: Opcode,
+    count: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Profiler {
+    instructions: u64,
+    hits: HashMap<usize, Hits>,
+    opcodes: HashMap<&'static str, u64>,
+    reads: HashMap<usize, u64>,
+    writes: HashMap<usize, u64>,
+    /// Taken counts by jump address and target.
+    back_edges: HashMap<(usize, usize), u64>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    /// The number of instructions executed.
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// How often the instruction at `address` was executed.
+    pub fn hits(&self, address: usize) -> u64 {
+        self.hits.get(&address).map_or(0, |hits| hits.count)
+    }
+
+    pub fn reads(&self, address: usize) -> u64 {
+        self.reads.get(&address).copied().unwrap_or(0)
+    }
+
+    pub fn writes(&self, address: usize) -> u64 {
+        self.writes.get(&address).copied().unwrap_or(0)
+    }
+
+    /// Executions per mnemonic, most executed first.
+    pub fn opcode_counts(&self) -> Vec<(&'static str, u64)> {
+        let mut counts = self
+            .opcodes
+            .iter()
+            .map(|(&mnemonic, &count)| (mnemonic, count))
+            .collect::<Vec<_>>();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        counts
+    }
+
+    /// Every address that was read or written, in order, with its read and
+    /// write counts.
+    pub fn heatmap(&self) -> Vec<(usize, u64, u64)> {
+        let mut addresses = self
+            .reads
+            .keys()
+            .chain(self.writes.keys())
+            .copied()
+            .collect::<Vec<_>>();
+        addresses.sort_unstable();
+        addresses.dedup();
+
+        addresses
+            .into_iter()
+            .map(|address| (address, self.reads(address), self.writes(address)))
+            .collect()
+    }
+
+    /// Every loop, the ones that executed the most instructions first.
+    pub fn hot_loops(&self) -> Vec<Loop> {
+        let mut loops = self
+            .back_edges
+            .iter()
+            .map(|(&(tail, head), &iterations)| Loop {
+                head,
+                tail,
+                iterations,
+                instructions: self
+                    .hits
+                    .iter()
+                    .filter(|(address, _)| (head..=tail).contains(*address))
+                    .map(|(_, hits)| hits.count)
+                    .sum(),
+            })
+            .collect::<Vec<_>>();
+        loops.sort_by(|a, b| {
+            (b.instructions, a.head, a.tail).cmp(&(a.instructions, b.head, b.tail))
+        });
+        loops
+    }
+
+    /// A disassembly of `program` with each line prefixed by how often it
+    /// ran, its share of all instructions executed, and how often its words
+    /// were read and written. Jumps that close a loop are marked.
+    pub fn annotate(&self, program: &[i64]) -> String {
+        let loops = self.hot_loops();
+        let mut listing = format!("{:>10} {:>7} {:>8} {:>8}\n", "hits", "%", "reads", "writes");
+
+        for instruction in disassemble(program) {
+            let hits = self.hits(instruction.address);
+            let share = match self.instructions {
+                0 => 0.0,
+                total => hits as f64 * 100.0 / total as f64,
+            };
+            let words = instruction.address..instruction.next_address();
+            let reads = words
+                .clone()
+                .map(|address| self.reads(address))
+                .sum::<u64>();
+            let writes = words.map(|address| self.writes(address)).sum::<u64>();
+
+            listing += &format!(
+                "{:>10} {:>6.2}% {:>8} {:>8} {}",
+                hits, share, reads, writes, instruction
+            );
+            for hot_loop in loops
+                .iter()
+                .filter(|hot_loop| hot_loop.tail == instruction.address)
+            {
+                listing += &format!(
+                    "  ; loop back to {}, taken {} times",
+                    hot_loop.head, hot_loop.iterations
+                );
+            }
+            listing.push('\n');
+        }
+
+        listing
+    }
+
+    /// Writes one line per executed address in the folded stack format
+    /// flamegraph tools read, weighted by executions.
+    pub fn write_folded<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut loops = self.hot_loops();
+        // Outermost first: earlier heads, then later tails.
+        loops.sort_by(|a, b| (a.head, b.tail).cmp(&(b.head, a.tail)));
+
+        let mut addresses = self.hits.keys().copied().collect::<Vec<_>>();
+        addresses.sort_unstable();
+
+        for address in addresses {
+            let hits = self.hits[&address];
+            let mut frames = vec!["program".to_string()];
+            for hot_loop in loops.iter().filter(|hot_loop| hot_loop.contains(address)) {
+                frames.push(format!("loop@{}", hot_loop.head));
+            }
+            frames.push(format!("{}@{}", hits.opcode.mnemonic(), address));
+
+            writeln!(writer, "{} {}", frames.join(";"), hits.count)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl TraceSink for Profiler {
+    fn instruction(&mut self, address: usize, opcode: Opcode, operands: &[i64]) {
+        self.instructions += 1;
+        let hits = self
+            .hits
+            .entry(address)
+            .or_insert(Hits { opcode, count: 0 });
+        hits.opcode = opcode;
+        hits.count += 1;
+        *self.opcodes.entry(opcode.mnemonic()).or_insert(0) += 1;
+
+        let taken = match opcode {
+            Opcode::JmpIfZero(..) => operands[0] == 0,
+            Opcode::JmpIfNotZero(..) => operands[0] != 0,
+            _ => false,
+        };
+        if taken && operands[1] >= 0 && operands[1] as usize <= address {
+            *self
+                .back_edges
+                .entry((address, operands[1] as usize))
+                .or_insert(0) += 1;
+        }
+    }
+
+    fn memory_write(&mut self, write: MemoryWrite) {
+        *self.writes.entry(write.address).or_insert(0) += 1;
+    }
+
+    fn memory_read(&mut self, address: usize, _value: i64) {
+        *self.reads.entry(address).or_insert(0) += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Loop, Profiler};
+    use crate::intcode_vm::asm::assemble;
+    use crate::{IntcodeVM, VMState};
+
+    /// Sums 3 + 2 + 1 with a nested loop that spins twice per iteration.
+    const NESTED: &str = "outer: add #2, #0, [j]
+                          inner: add [j], #-1, [j]
+                                 jnz [j], #inner
+                                 add [sum], [i], [sum]
+                                 add [i], #-1, [i]
+                                 jnz [i], #outer
+                                 out [sum]
+                                 hlt
+                          i:     data 3
+                          j:     data 0
+                          sum:   data 0";
+
+    fn profile(source: &str) -> (Vec<i64>, Profiler) {
+        let program = assemble(source).unwrap();
+        let mut vm = IntcodeVM::new();
+        vm.set_program(program.clone());
+
+        let mut profiler = Profiler::new();
+        assert_eq!(
+            vm.execute_traced(&mut profiler).unwrap(),
+            VMState::Output(6)
+        );
+        assert_eq!(vm.execute_traced(&mut profiler).unwrap(), VMState::Halted);
+        (program, profiler)
+    }
+
+    #[test]
+    fn test_counts_and_loops() {
+        let (_, profiler) = profile(NESTED);
+
+        // 3 outer iterations of 4 instructions plus 2 inner iterations of 2,
+        // then out and hlt.
+        assert_eq!(profiler.instructions(), 3 * (4 + 2 * 2) + 2);
+        assert_eq!(profiler.hits(4), 6);
+        assert_eq!(
+            profiler.opcode_counts(),
+            vec![("add", 15), ("jnz", 9), ("hlt", 1), ("out", 1)]
+        );
+        assert_eq!(
+            profiler.hot_loops(),
+            vec![
+                Loop {
+                    head: 0,
+                    tail: 19,
+                    iterations: 2,
+                    instructions: 24,
+                },
+                Loop {
+                    head: 4,
+                    tail: 8,
+                    iterations: 3,
+                    instructions: 12,
+                },
+            ]
+        );
+
+        // `sum` at 27 is read and written once per outer iteration and read
+        // again by `out`.
+        assert_eq!(profiler.reads(27), 4);
+        assert_eq!(profiler.writes(27), 3);
+        assert!(profiler.heatmap().contains(&(27, 4, 3)));
+    }
+
+    #[test]
+    fn test_annotated_listing() {
+        let (program, profiler) = profile(NESTED);
+        let listing = profiler.annotate(&program);
+        let lines = listing.lines().collect::<Vec<_>>();
+
+        assert!(lines[0].contains("hits"));
+        assert!(lines[2].starts_with("         6  23.08%"));
+        assert!(lines[3].ends_with("; loop back to 4, taken 3 times"));
+        assert!(lines[6].ends_with("; loop back to 0, taken 2 times"));
+    }
+
+    #[test]
+    fn test_folded_stacks() {
+        let (_, profiler) = profile(NESTED);
+        let mut folded = vec![];
+        profiler.write_folded(&mut folded).unwrap();
+        let folded = String::from_utf8(folded).unwrap();
+
+        assert_eq!(
+            folded.lines().take(3).collect::<Vec<_>>(),
+            vec![
+                "program;loop@0;add@0 3",
+                "program;loop@0;loop@4;add@4 6",
+                "program;loop@0;loop@4;jnz@8 6",
+            ]
+        );
+        assert!(folded.ends_with("program;hlt@24 1\n"));
+    }
+}
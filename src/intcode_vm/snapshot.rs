@@ -0,0 +1,482 @@
+//! Saving and restoring the complete state of an `IntcodeVM`.
+//!
+//! Snapshots use a small little-endian binary format:
+//!
+//! ```text
+//! magic               b"ICVM"
+//! version             u16
+//! instruction_pointer u64
+//! relative_pointer    i64
+//! current_opcode      u8 (0 = none, 1 = some) followed by the opcode word as i64 if some
+//! current_params      3 x i64
+//! jumped              u8
+//! inputs              u64 count followed by that many i64
+//! memory_kind         u8 (0 = dense, 1 = paged, 2 = bounded) followed by the limit as u64
+//!                     if bounded
+//! memory              u64 count followed by that many chunks: the start address as u64, then
+//!                     a u64 count followed by that many i64
+//! costs               10 x u64, by opcode number with halt first
+//! fuel                u8 (0 = unlimited, 1 = limited) followed by the fuel left as u64 if limited
+//! fuel_used           u64
+//! deadline            u8 (0 = none, 1 = some) followed by the nanoseconds left until it as u64
+//!                     if some
+//! backend             u8 (0 = interpreter, 1 = compiled)
+//! arithmetic          u8 (0 = wrapping, 1 = checked, 2 = saturating)
+//! ```
+//!
+//! Memory is stored as the chunks `Memory::chunks` returns, so sparse paged
+//! memory stays small. A deadline is stored as the time left until it, and
+//! a restored VM counts down from when it was loaded.
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use super::fuel::{CostTable, Fuel};
+use super::memory::{Memory, MemoryKind, Storage};
+use super::{Arithmetic, Backend, IntcodeVM, Opcode};
+
+const MAGIC: &[u8; 4] = b"ICVM";
+const VERSION: u16 = 4;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// The data doesn't start with the snapshot magic bytes.
+    NotASnapshot,
+    UnsupportedVersion(u16),
+    /// A field holds a value that no VM could have written.
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "I/O error: {}", error),
+            SnapshotError::NotASnapshot => write!(f, "not an Intcode VM snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {}", version)
+            }
+            SnapshotError::Corrupt(field) => write!(f, "corrupt snapshot: invalid {}", field),
+        }
+    }
+}
+
+impl Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SnapshotError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(error: io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+fn write_u64<W: Write>(writer: &mut W, value: u64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_i64<W: Write>(writer: &mut W, value: i64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_words<'a, W: Write, I: ExactSizeIterator<Item = &'a i64>>(
+    writer: &mut W,
+    words: I,
+) -> io::Result<()> {
+    write_u64(writer, words.len() as u64)?;
+    for word in words {
+        write_i64(writer, *word)?;
+    }
+
+    Ok(())
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> io::Result<[u8; N]> {
+    let mut buffer = [0; N];
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    Ok(read_array::<R, 1>(reader)?[0])
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    Ok(u64::from_le_bytes(read_array(reader)?))
+}
+
+fn read_i64<R: Read>(reader: &mut R) -> io::Result<i64> {
+    Ok(i64::from_le_bytes(read_array(reader)?))
+}
+
+fn read_bool<R: Read>(reader: &mut R, field: &'static str) -> Result<bool, SnapshotError> {
+    match read_u8(reader)? {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(SnapshotError::Corrupt(field)),
+    }
+}
+
+fn read_usize<R: Read>(reader: &mut R, field: &'static str) -> Result<usize, SnapshotError> {
+    let value = read_u64(reader)?;
+    if value > usize::MAX as u64 {
+        return Err(SnapshotError::Corrupt(field));
+    }
+
+    Ok(value as usize)
+}
+
+fn read_words<R: Read>(reader: &mut R, field: &'static str) -> Result<Vec<i64>, SnapshotError> {
+    let count = read_usize(reader, field)?;
+
+    // Don't trust the count for the allocation, a corrupt file could claim anything.
+    let mut words = Vec::with_capacity(count.min(1 << 16));
+    for _ in 0..count {
+        words.push(read_i64(reader)?);
+    }
+
+    Ok(words)
+}
+
+fn write_memory<W: Write>(writer: &mut W, memory: &dyn Memory) -> io::Result<()> {
+    match memory.kind() {
+        MemoryKind::Dense => writer.write_all(&[0])?,
+        MemoryKind::Paged => writer.write_all(&[1])?,
+        MemoryKind::Bounded(limit) => {
+            writer.write_all(&[2])?;
+            write_u64(writer, limit as u64)?;
+        }
+    }
+
+    let chunks = memory.chunks();
+    write_u64(writer, chunks.len() as u64)?;
+    for (start, words) in chunks {
+        write_u64(writer, start as u64)?;
+        write_words(writer, words.iter())?;
+    }
+
+    Ok(())
+}
+
+fn read_memory<R: Read>(reader: &mut R) -> Result<Storage, SnapshotError> {
+    let kind = match read_u8(reader)? {
+        0 => MemoryKind::Dense,
+        1 => MemoryKind::Paged,
+        2 => MemoryKind::Bounded(read_usize(reader, "memory limit")?),
+        _ => return Err(SnapshotError::Corrupt("memory kind")),
+    };
+
+    let count = read_usize(reader, "memory chunk count")?;
+    let mut chunks = Vec::with_capacity(count.min(1 << 16));
+    for _ in 0..count {
+        let start = read_usize(reader, "memory chunk address")?;
+        chunks.push((start, read_words(reader, "memory chunk size")?));
+    }
+
+    kind.restore(chunks)
+        .ok_or(SnapshotError::Corrupt("memory chunks"))
+}
+
+fn write_option<W: Write>(writer: &mut W, value: Option<u64>) -> io::Result<()> {
+    match value {
+        Some(value) => {
+            writer.write_all(&[1])?;
+            write_u64(writer, value)
+        }
+        None => writer.write_all(&[0]),
+    }
+}
+
+fn read_option<R: Read>(reader: &mut R, field: &'static str) -> Result<Option<u64>, SnapshotError> {
+    match read_bool(reader, field)? {
+        true => Ok(Some(read_u64(reader)?)),
+        false => Ok(None),
+    }
+}
+
+fn write_fuel<W: Write>(writer: &mut W, fuel: &Fuel) -> io::Result<()> {
+    for cost in fuel.costs.to_array().iter() {
+        write_u64(writer, *cost)?;
+    }
+    write_option(writer, fuel.remaining())?;
+    write_u64(writer, fuel.used())?;
+    let time_left = fuel
+        .time_left()
+        .map(|time_left| time_left.as_nanos().min(u64::MAX as u128) as u64);
+    write_option(writer, time_left)
+}
+
+fn read_fuel<R: Read>(reader: &mut R) -> Result<Fuel, SnapshotError> {
+    let mut costs = [0; 10];
+    for cost in costs.iter_mut() {
+        *cost = read_u64(reader)?;
+    }
+    let budget = read_option(reader, "fuel")?;
+    let used = read_u64(reader)?;
+    let time_left = read_option(reader, "deadline")?.map(Duration::from_nanos);
+    Ok(Fuel::restore(
+        CostTable::from_array(costs),
+        budget,
+        used,
+        time_left,
+    ))
+}
+
+fn read_backend<R: Read>(reader: &mut R) -> Result<Backend, SnapshotError> {
+    match read_u8(reader)? {
+        0 => Ok(Backend::Interpreter),
+        1 => Ok(Backend::Compiled),
+        _ => Err(SnapshotError::Corrupt("backend")),
+    }
+}
+
+fn read_arithmetic<R: Read>(reader: &mut R) -> Result<Arithmetic, SnapshotError> {
+    match read_u8(reader)? {
+        0 => Ok(Arithmetic::Wrapping),
+        1 => Ok(Arithmetic::Checked),
+        2 => Ok(Arithmetic::Saturating),
+        _ => Err(SnapshotError::Corrupt("arithmetic")),
+    }
+}
+
+impl IntcodeVM {
+    /// Writes the complete VM state to `writer`.
+    pub fn save_snapshot<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        write_u64(&mut writer, self.instruction_pointer as u64)?;
+        write_i64(&mut writer, self.relative_pointer)?;
+
+        match self.current_opcode {
+            Some(opcode) => {
+                writer.write_all(&[1])?;
+                write_i64(&mut writer, opcode.encode())?;
+            }
+            None => writer.write_all(&[0])?,
+        }
+
+        for param in self.current_params.iter() {
+            write_i64(&mut writer, *param)?;
+        }
+        writer.write_all(&[self.jumped as u8])?;
+        write_words(&mut writer, self.inputs.iter())?;
+        write_memory(&mut writer, self.memory())?;
+        write_fuel(&mut writer, &self.fuel)?;
+
+        let backend = match self.backend {
+            Backend::Interpreter => 0,
+            Backend::Compiled => 1,
+        };
+        let arithmetic = match self.arithmetic {
+            Arithmetic::Wrapping => 0,
+            Arithmetic::Checked => 1,
+            Arithmetic::Saturating => 2,
+        };
+        writer.write_all(&[backend, arithmetic])?;
+
+        writer.flush()
+    }
+
+    /// Restores a VM written by `save_snapshot`. Execution resumes exactly
+    /// where the saved VM left off.
+    pub fn load_snapshot<R: Read>(mut reader: R) -> Result<Self, SnapshotError> {
+        if &read_array::<R, 4>(&mut reader)? != MAGIC {
+            return Err(SnapshotError::NotASnapshot);
+        }
+
+        let version = u16::from_le_bytes(read_array(&mut reader)?);
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let instruction_pointer = read_usize(&mut reader, "instruction pointer")?;
+        let relative_pointer = read_i64(&mut reader)?;
+
+        let current_opcode = if read_bool(&mut reader, "current opcode")? {
+            let word = read_i64(&mut reader)?;
+            let opcode = Opcode::from(word);
+            if opcode.encode() != word {
+                return Err(SnapshotError::Corrupt("current opcode"));
+            }
+            Some(opcode)
+        } else {
+            None
+        };
+
+        let mut current_params = [0; 3];
+        for param in current_params.iter_mut() {
+            *param = read_i64(&mut reader)?;
+        }
+        let jumped = read_bool(&mut reader, "jumped flag")?;
+        let inputs = read_words(&mut reader, "input count")?;
+        let memory = read_memory(&mut reader)?;
+        let fuel = read_fuel(&mut reader)?;
+        let backend = read_backend(&mut reader)?;
+        let arithmetic = read_arithmetic(&mut reader)?;
+
+        Ok(IntcodeVM {
+            memory,
+            decoded: vec![],
+            code_words: vec![],
+            backend,
+            arithmetic,
+            code: Default::default(),
+            fuel,
+            instruction_pointer,
+            relative_pointer,
+            current_opcode,
+            current_params,
+            inputs: VecDeque::from(inputs),
+            jumped,
+        })
+    }
+
+    pub fn save_snapshot_to_path<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.save_snapshot(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn load_snapshot_from_path<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        Self::load_snapshot(BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::SnapshotError;
+    use crate::input;
+    use crate::intcode_vm::fuel::CostTable;
+    use crate::intcode_vm::memory::{BoundedMemory, MemoryKind, PagedMemory};
+    use crate::intcode_vm::{Arithmetic, Backend, VmErrorKind};
+    use crate::{IntcodeVM, VMState};
+
+    fn boost() -> IntcodeVM {
+        let program = input::parse_intcode(include_str!("../../inputs/day09.txt")).unwrap();
+
+        let mut vm = IntcodeVM::new();
+        vm.set_program(program);
+        vm.push_input(2);
+        vm
+    }
+
+    #[test]
+    fn test_restored_vm_resumes_identically() {
+        let mut vm = boost();
+        for _ in 0..10_000 {
+            assert_eq!(vm.step().unwrap(), None);
+        }
+
+        let mut snapshot = vec![];
+        vm.save_snapshot(&mut snapshot).unwrap();
+        let mut restored = IntcodeVM::load_snapshot(snapshot.as_slice()).unwrap();
+
+        let mut resaved = vec![];
+        restored.save_snapshot(&mut resaved).unwrap();
+        assert_eq!(snapshot, resaved);
+
+        let expected = vm.execute().unwrap();
+        assert!(matches!(expected, VMState::Output(_)));
+        assert_eq!(restored.execute().unwrap(), expected);
+        assert_eq!(restored.memory().to_vec(), vm.memory().to_vec());
+    }
+
+    fn round_trip(vm: &IntcodeVM) -> (Vec<u8>, IntcodeVM) {
+        let mut snapshot = vec![];
+        vm.save_snapshot(&mut snapshot).unwrap();
+        let restored = IntcodeVM::load_snapshot(snapshot.as_slice()).unwrap();
+        (snapshot, restored)
+    }
+
+    #[test]
+    fn test_keeps_the_memory_backend() {
+        const FAR: i64 = 1_000_000_000_000;
+
+        // add #7, #0, [FAR]; out [FAR]; hlt
+        let mut vm = IntcodeVM::with_memory(PagedMemory::new());
+        vm.set_program(vec![1101, 7, 0, FAR, 4, FAR, 99]);
+        vm.step().unwrap();
+        let (snapshot, mut restored) = round_trip(&vm);
+        assert!(snapshot.len() < 20_000, "{}", snapshot.len());
+        assert_eq!(restored.memory().kind(), MemoryKind::Paged);
+        assert_eq!(restored.memory().len(), FAR as usize + 1);
+        assert_eq!(restored.run_once().unwrap(), vec![7]);
+
+        // add #7, #0, [100]; hlt
+        let mut vm = IntcodeVM::with_memory(BoundedMemory::new(50));
+        vm.set_program(vec![1101, 7, 0, 100, 99]);
+        let (_, mut restored) = round_trip(&vm);
+        assert_eq!(restored.memory().kind(), MemoryKind::Bounded(50));
+        assert!(matches!(
+            restored.run_once().unwrap_err().kind,
+            VmErrorKind::MemoryLimit(_)
+        ));
+    }
+
+    #[test]
+    fn test_keeps_fuel_costs_and_deadline() {
+        let mut vm = boost();
+        vm.set_costs(CostTable::uniform(2).with(9, 5));
+        vm.set_fuel(Some(30_000));
+        vm.set_timeout(Duration::from_secs(3600));
+        while vm.execute().unwrap() != VMState::OutOfFuel {}
+
+        let (_, mut restored) = round_trip(&vm);
+        assert_eq!(restored.costs(), vm.costs());
+        assert_eq!(restored.fuel(), vm.fuel());
+        assert_eq!(restored.fuel_used(), vm.fuel_used());
+        assert!(restored.deadline().unwrap() > Instant::now() + Duration::from_secs(3000));
+
+        vm.add_fuel(10_000);
+        restored.add_fuel(10_000);
+        let expected = vm.execute().unwrap();
+        assert_eq!(restored.execute().unwrap(), expected);
+        assert_eq!(restored.instruction_pointer(), vm.instruction_pointer());
+        assert_eq!(restored.fuel_used(), vm.fuel_used());
+    }
+
+    #[test]
+    fn test_keeps_the_backend_and_arithmetic() {
+        // add #MAX, #1, [7]; out [7]; hlt
+        let program = vec![1101, i64::MAX, 1, 7, 4, 7, 99, 0];
+        for backend in [Backend::Interpreter, Backend::Compiled].iter() {
+            for arithmetic in [Arithmetic::Checked, Arithmetic::Saturating].iter() {
+                let mut vm = IntcodeVM::new();
+                vm.set_backend(*backend);
+                vm.set_arithmetic(*arithmetic);
+                vm.set_program(program.clone());
+
+                let (_, mut restored) = round_trip(&vm);
+                assert_eq!(restored.backend(), *backend);
+                assert_eq!(restored.arithmetic(), *arithmetic);
+                assert_eq!(restored.run_once(), vm.run_once());
+            }
+        }
+    }
+
+    #[test]
+    fn test_rejects_foreign_and_truncated_data() {
+        assert!(matches!(
+            IntcodeVM::load_snapshot(&b"PNG\x00\x01\x00"[..]),
+            Err(SnapshotError::NotASnapshot)
+        ));
+
+        let mut snapshot = vec![];
+        boost().save_snapshot(&mut snapshot).unwrap();
+        snapshot.truncate(snapshot.len() - 1);
+        assert!(matches!(
+            IntcodeVM::load_snapshot(snapshot.as_slice()),
+            Err(SnapshotError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,692 @@
+//! Conformance tests for `IntcodeVM`: every opcode in every parameter mode,
+//! the relative base, memory growth, the published day 2, 5 and 9 examples
+//! and pausing for input. Everything runs on every backend.
+
+use super::memory::{BoundedMemory, MemoryError, PagedMemory};
+use super::trace::{TraceEvent, TraceSink};
+use super::{
+    Arithmetic, Backend, IntcodeVM, MemoryWrite, Opcode, ParamMode, VMState, VmError, VmErrorKind,
+};
+use crate::input;
+
+const BACKENDS: [Backend; 2] = [Backend::Interpreter, Backend::Compiled];
+const MODES: [ParamMode; 3] = [
+    ParamMode::Position,
+    ParamMode::Immediate,
+    ParamMode::Relative,
+];
+const WRITE_MODES: [ParamMode; 2] = [ParamMode::Position, ParamMode::Relative];
+
+/// Every test program starts with `arb #BASE` and puts the instruction under
+/// test at `START`.
+const BASE: i64 = 80;
+const START: usize = 2;
+/// Where position and relative mode output parameters write to.
+const POSITION_TARGET: i64 = 60;
+const RELATIVE_TARGET: i64 = 5;
+
+#[derive(Default)]
+struct Recorder(Vec<TraceEvent>);
+
+impl TraceSink for Recorder {
+    fn instruction(&mut self, address: usize, opcode: Opcode, operands: &[i64]) {
+        self.0.push(TraceEvent::Instruction {
+            address,
+            opcode,
+            operands: operands.to_vec(),
+        });
+    }
+
+    fn memory_write(&mut self, write: MemoryWrite) {
+        self.0.push(TraceEvent::MemoryWrite(write));
+    }
+}
+
+fn mode_digit(mode: ParamMode) -> i64 {
+    match mode {
+        ParamMode::Position => 0,
+        ParamMode::Immediate => 1,
+        ParamMode::Relative => 2,
+    }
+}
+
+fn encode(opcode: i64, modes: &[ParamMode]) -> i64 {
+    modes
+        .iter()
+        .rev()
+        .fold(0, |acc, mode| acc * 10 + mode_digit(*mode))
+        * 100
+        + opcode
+}
+
+/// A 100 word program of the form `arb #BASE; <instruction>; ...` whose
+/// operands are laid out so that each reads back the value it was given.
+struct Fixture {
+    memory: Vec<i64>,
+    next_word: usize,
+}
+
+impl Fixture {
+    fn new(opcode: i64, modes: &[ParamMode]) -> Self {
+        let mut memory = vec![0; 100];
+        memory[0] = 109;
+        memory[1] = BASE;
+        memory[START] = encode(opcode, modes);
+
+        Fixture {
+            memory,
+            next_word: START + 1,
+        }
+    }
+
+    /// Adds the `index`th (1-based) input parameter so it reads as `value`.
+    /// Position mode reads go through address `50 + index`, relative mode
+    /// reads through the negative offset `-index`.
+    fn read(mut self, index: usize, mode: ParamMode, value: i64) -> Self {
+        let raw = match mode {
+            ParamMode::Immediate => value,
+            ParamMode::Position => {
+                self.memory[50 + index] = value;
+                50 + index as i64
+            }
+            ParamMode::Relative => {
+                self.memory[BASE as usize - index] = value;
+                -(index as i64)
+            }
+        };
+
+        self.push(raw)
+    }
+
+    /// Adds an output parameter.
+    fn write(self, mode: ParamMode) -> Self {
+        let raw = match mode {
+            ParamMode::Relative => RELATIVE_TARGET,
+            _ => POSITION_TARGET,
+        };
+
+        self.push(raw)
+    }
+
+    fn push(mut self, raw: i64) -> Self {
+        self.memory[self.next_word] = raw;
+        self.next_word += 1;
+        self
+    }
+
+    /// Ends the instruction with a halt.
+    fn halt(mut self) -> Self {
+        self.memory[self.next_word] = 99;
+        self
+    }
+
+    fn vms(&self) -> Vec<IntcodeVM> {
+        vms(&self.memory)
+    }
+}
+
+/// A VM for every backend, loaded with `program`.
+fn vms(program: &[i64]) -> Vec<IntcodeVM> {
+    BACKENDS
+        .iter()
+        .map(|backend| {
+            let mut vm = IntcodeVM::new();
+            vm.set_backend(*backend);
+            vm.set_program(program.to_vec());
+            vm
+        })
+        .collect()
+}
+
+/// Applies `f` to a VM for every backend and checks they all agree.
+fn on_every_backend<T: std::fmt::Debug + PartialEq>(
+    program: &[i64],
+    f: impl Fn(&mut IntcodeVM) -> T,
+) -> T {
+    let mut results = vms(program)
+        .into_iter()
+        .map(|mut vm| (vm.backend(), f(&mut vm)));
+    let (_, expected) = results.next().unwrap();
+    for (backend, result) in results {
+        assert_eq!(result, expected, "{:?} disagrees", backend);
+    }
+
+    expected
+}
+
+fn target(vm: &IntcodeVM, mode: ParamMode) -> i64 {
+    match mode {
+        ParamMode::Relative => vm.read_memory((BASE + RELATIVE_TARGET) as usize),
+        _ => vm.read_memory(POSITION_TARGET as usize),
+    }
+}
+
+fn run(program: &[i64], inputs: &[i64]) -> Vec<i64> {
+    on_every_backend(program, |vm| {
+        for input in inputs {
+            vm.push_input(*input);
+        }
+        vm.run_once().unwrap()
+    })
+}
+
+fn memory_after(program: &[i64]) -> Vec<i64> {
+    on_every_backend(program, |vm| {
+        assert_eq!(vm.run_once().unwrap(), vec![]);
+        vm.memory().to_vec()
+    })
+}
+
+fn error(program: &[i64]) -> VmError {
+    on_every_backend(program, |vm| vm.run_once().unwrap_err())
+}
+
+#[test]
+fn test_binary_operations_in_every_mode() {
+    type Operation = fn(i64, i64) -> i64;
+    let operations: [(i64, Operation); 4] = [
+        (1, |a, b| a + b),
+        (2, |a, b| a * b),
+        (7, |a, b| (a < b) as i64),
+        (8, |a, b| (a == b) as i64),
+    ];
+    let operands = [(7, 5), (-3, 4), (6, 6)];
+
+    for (opcode, expected) in operations.iter() {
+        for mode1 in MODES.iter().copied() {
+            for mode2 in MODES.iter().copied() {
+                for out in WRITE_MODES.iter().copied() {
+                    for (a, b) in operands.iter().copied() {
+                        let fixture = Fixture::new(*opcode, &[mode1, mode2, out])
+                            .read(1, mode1, a)
+                            .read(2, mode2, b)
+                            .write(out)
+                            .halt();
+                        let result = on_every_backend(&fixture.memory, |vm| {
+                            assert_eq!(vm.run_once().unwrap(), vec![]);
+                            target(vm, out)
+                        });
+                        assert_eq!(
+                            result,
+                            expected(a, b),
+                            "opcode {} with {:?} {:?} {:?} on ({}, {})",
+                            opcode,
+                            mode1,
+                            mode2,
+                            out,
+                            a,
+                            b
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_writes_through_immediate_parameters_fail() {
+    for opcode in [1, 2, 7, 8].iter() {
+        let modes = [ParamMode::Immediate; 3];
+        let fixture = Fixture::new(*opcode, &modes)
+            .read(1, ParamMode::Immediate, 1)
+            .read(2, ParamMode::Immediate, 2)
+            .push(POSITION_TARGET)
+            .halt();
+
+        let error = error(&fixture.memory);
+        assert_eq!(error.kind, VmErrorKind::ImmediateWrite);
+        assert_eq!(error.instruction_pointer, START);
+        assert_eq!(error.word, encode(*opcode, &modes));
+    }
+
+    for mut vm in vms(&[103, 0, 99]) {
+        vm.push_input(1);
+        assert_eq!(vm.run_once().unwrap_err().kind, VmErrorKind::ImmediateWrite);
+    }
+}
+
+#[test]
+fn test_input_in_every_mode() {
+    for out in WRITE_MODES.iter().copied() {
+        let fixture = Fixture::new(3, &[out]).write(out).halt();
+        for mut vm in fixture.vms() {
+            vm.push_input(-42);
+            assert_eq!(vm.run_once().unwrap(), vec![]);
+            assert_eq!(target(&vm, out), -42);
+        }
+    }
+}
+
+#[test]
+fn test_output_in_every_mode() {
+    for mode in MODES.iter().copied() {
+        let fixture = Fixture::new(4, &[mode]).read(1, mode, 1234).halt();
+        assert_eq!(run(&fixture.memory, &[]), vec![1234], "{:?}", mode);
+    }
+}
+
+#[test]
+fn test_jumps_in_every_mode() {
+    // Falling through outputs 0, jumping to 20 outputs 1.
+    const TARGET: i64 = 20;
+
+    for (opcode, jumps_on_zero) in [(5, false), (6, true)].iter() {
+        for condition_mode in MODES.iter().copied() {
+            for target_mode in MODES.iter().copied() {
+                for condition in [0, 1, -7].iter().copied() {
+                    let mut fixture = Fixture::new(*opcode, &[condition_mode, target_mode])
+                        .read(1, condition_mode, condition)
+                        .read(2, target_mode, TARGET);
+                    let fallthrough = fixture.next_word;
+                    fixture.memory[fallthrough..fallthrough + 3].copy_from_slice(&[104, 0, 99]);
+                    fixture.memory[TARGET as usize..TARGET as usize + 3]
+                        .copy_from_slice(&[104, 1, 99]);
+
+                    let jumped = (condition == 0) == *jumps_on_zero;
+                    assert_eq!(
+                        run(&fixture.memory, &[]),
+                        vec![jumped as i64],
+                        "opcode {} with {:?} {:?} on {}",
+                        opcode,
+                        condition_mode,
+                        target_mode,
+                        condition
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_set_relative_base_in_every_mode() {
+    for mode in MODES.iter().copied() {
+        for offset in [7, -7].iter().copied() {
+            let mut fixture = Fixture::new(9, &[mode]).read(1, mode, offset);
+            let next = fixture.next_word;
+            fixture.memory[next..next + 3].copy_from_slice(&[204, 0, 99]);
+            fixture.memory[(BASE + offset) as usize] = 555;
+
+            for mut vm in fixture.vms() {
+                assert_eq!(vm.run_once().unwrap(), vec![555], "{:?} {}", mode, offset);
+                assert_eq!(vm.relative_pointer(), BASE + offset);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_relative_base_accumulates() {
+    // arb #10; arb #-3; out rb+0; hlt; with 42 at address 7
+    let program = [109, 10, 109, -3, 204, 0, 99, 42];
+    assert_eq!(run(&program, &[]), vec![42]);
+}
+
+#[test]
+fn test_relative_base_past_end_of_memory() {
+    // arb #50; in rb+0; out rb+0; out rb+1; hlt
+    let program = [109, 50, 203, 0, 204, 0, 204, 1, 99];
+    assert_eq!(run(&program, &[9]), vec![9, 0]);
+}
+
+#[test]
+fn test_writes_past_end_of_memory_grow_it() {
+    let memory = memory_after(&[1101, 2, 3, 1000, 99]);
+    assert_eq!(memory.len(), 1001);
+    assert_eq!(memory[1000], 5);
+    assert!(memory[5..1000].iter().all(|word| *word == 0));
+}
+
+#[test]
+fn test_reads_past_end_of_memory_are_zero() {
+    assert_eq!(run(&[4, 1000, 99], &[]), vec![0]);
+}
+
+#[test]
+fn test_day2_examples() {
+    assert_eq!(memory_after(&[1, 0, 0, 0, 99]), vec![2, 0, 0, 0, 99]);
+    assert_eq!(memory_after(&[2, 3, 0, 3, 99]), vec![2, 3, 0, 6, 99]);
+    assert_eq!(
+        memory_after(&[2, 4, 4, 5, 99, 0]),
+        vec![2, 4, 4, 5, 99, 9801]
+    );
+    assert_eq!(
+        memory_after(&[1, 1, 1, 4, 99, 5, 6, 0, 99]),
+        vec![30, 1, 1, 4, 2, 5, 6, 0, 99]
+    );
+    assert_eq!(
+        memory_after(&[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]),
+        vec![3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50]
+    );
+}
+
+#[test]
+fn test_day5_examples() {
+    assert_eq!(run(&[3, 0, 4, 0, 99], &[77]), vec![77]);
+    assert_eq!(memory_after(&[1002, 4, 3, 4, 33]), vec![1002, 4, 3, 4, 99]);
+    assert_eq!(
+        memory_after(&[1101, 100, -1, 4, 0]),
+        vec![1101, 100, -1, 4, 99]
+    );
+
+    let equal_to_8_position = [3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+    let less_than_8_position = [3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8];
+    let equal_to_8_immediate = [3, 3, 1108, -1, 8, 3, 4, 3, 99];
+    let less_than_8_immediate = [3, 3, 1107, -1, 8, 3, 4, 3, 99];
+    for input in [-8, 7, 8, 9].iter().copied() {
+        assert_eq!(
+            run(&equal_to_8_position, &[input]),
+            vec![(input == 8) as i64]
+        );
+        assert_eq!(
+            run(&less_than_8_position, &[input]),
+            vec![(input < 8) as i64]
+        );
+        assert_eq!(
+            run(&equal_to_8_immediate, &[input]),
+            vec![(input == 8) as i64]
+        );
+        assert_eq!(
+            run(&less_than_8_immediate, &[input]),
+            vec![(input < 8) as i64]
+        );
+    }
+
+    let jump_position = [3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9];
+    let jump_immediate = [3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1];
+    for input in [0, 1, -5].iter().copied() {
+        assert_eq!(run(&jump_position, &[input]), vec![(input != 0) as i64]);
+        assert_eq!(run(&jump_immediate, &[input]), vec![(input != 0) as i64]);
+    }
+
+    let compare_to_8 = [
+        3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0, 0,
+        1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20,
+        1105, 1, 46, 98, 99,
+    ];
+    assert_eq!(run(&compare_to_8, &[7]), vec![999]);
+    assert_eq!(run(&compare_to_8, &[8]), vec![1000]);
+    assert_eq!(run(&compare_to_8, &[9]), vec![1001]);
+}
+
+#[test]
+fn test_day9_examples() {
+    let quine = [
+        109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+    ];
+    assert_eq!(run(&quine, &[]), quine.to_vec());
+
+    let sixteen_digits = run(&[1102, 34_915_192, 34_915_192, 7, 4, 7, 99, 0], &[]);
+    assert_eq!(sixteen_digits, vec![1_219_070_632_396_864]);
+    assert_eq!(sixteen_digits[0].to_string().len(), 16);
+
+    assert_eq!(
+        run(&[104, 1_125_899_906_842_624, 99], &[]),
+        vec![1_125_899_906_842_624]
+    );
+}
+
+#[test]
+fn test_pauses_and_resumes_for_input() {
+    for mut vm in vms(&[3, 0, 4, 0, 3, 0, 4, 0, 99]) {
+        assert_eq!(vm.execute().unwrap(), VMState::NeedsInput);
+        assert_eq!(vm.instruction_pointer(), 0);
+        assert_eq!(vm.execute().unwrap(), VMState::NeedsInput);
+
+        vm.push_input(5);
+        assert_eq!(vm.execute().unwrap(), VMState::Output(5));
+        assert_eq!(vm.execute().unwrap(), VMState::NeedsInput);
+
+        vm.push_input(6);
+        vm.push_input(7);
+        assert_eq!(vm.execute().unwrap(), VMState::Output(6));
+        assert_eq!(vm.execute().unwrap(), VMState::Halted);
+        assert_eq!(vm.execute().unwrap(), VMState::Halted);
+        assert_eq!(vm.inputs().iter().copied().collect::<Vec<_>>(), vec![7]);
+    }
+}
+
+#[test]
+fn test_reset_clears_state() {
+    for mut vm in vms(&[109, 5, 104, 1, 99]) {
+        vm.push_input(3);
+        assert_eq!(vm.execute().unwrap(), VMState::Output(1));
+
+        vm.reset();
+        assert_eq!(vm.instruction_pointer(), 0);
+        assert_eq!(vm.relative_pointer(), 0);
+        assert!(vm.inputs().is_empty());
+        assert!(vm.memory().is_empty());
+
+        vm.set_program(vec![104, 2, 99]);
+        assert_eq!(vm.run_once().unwrap(), vec![2]);
+    }
+}
+
+#[test]
+fn test_faults() {
+    let unknown = error(&[1, 0, 0, 0, 42]);
+    assert_eq!(unknown.kind, VmErrorKind::UnknownOpcode(42));
+    assert_eq!(unknown.instruction_pointer, 4);
+    assert_eq!(unknown.word, 42);
+    assert_eq!(unknown.opcode, Opcode::Invalid);
+
+    assert_eq!(
+        error(&[301, 0, 0, 0, 99]).kind,
+        VmErrorKind::InvalidParamMode(3)
+    );
+    assert_eq!(
+        error(&[1101, 0, 0, 0]).kind,
+        VmErrorKind::InstructionPointerOutOfBounds
+    );
+    assert_eq!(error(&[3, 0, 99]).kind, VmErrorKind::NotEnoughInput);
+}
+
+#[test]
+fn test_negative_addresses_fault() {
+    // arb #3; loop: arb #-1; add rb-1, #0, [20]; jz #0, #loop
+    // The `add` reads addresses 1 and 0, then -1.
+    let negative = error(&[109, 3, 109, -1, 1201, -1, 0, 20, 1106, 0, 2]);
+    assert_eq!(negative.kind, VmErrorKind::NegativeAddress(-1));
+    assert_eq!(negative.instruction_pointer, 4);
+    assert_eq!(
+        negative.opcode,
+        Opcode::Add(
+            ParamMode::Relative,
+            ParamMode::Immediate,
+            ParamMode::Position
+        )
+    );
+
+    assert_eq!(
+        error(&[1101, 0, 0, -5, 99]).kind,
+        VmErrorKind::NegativeAddress(-5)
+    );
+    assert_eq!(error(&[4, -1, 99]).kind, VmErrorKind::NegativeAddress(-1));
+}
+
+#[test]
+fn test_negative_relative_base() {
+    // arb #-10; out rb+12; hlt
+    assert_eq!(run(&[109, -10, 204, 12, 99], &[]), vec![204]);
+    assert_eq!(
+        error(&[109, -10, 204, 9, 99]).kind,
+        VmErrorKind::NegativeAddress(-1)
+    );
+}
+
+#[test]
+fn test_address_overflow_faults() {
+    // arb #5; out rb+MAX; hlt
+    let overflow = error(&[109, 5, 204, i64::MAX, 99]);
+    assert_eq!(overflow.kind, VmErrorKind::AddressTooLarge);
+    assert_eq!(overflow.instruction_pointer, 2);
+
+    // arb #5; arb #MAX; hlt
+    let overflow = error(&[109, 5, 109, i64::MAX, 99]);
+    assert_eq!(overflow.kind, VmErrorKind::AddressTooLarge);
+    assert_eq!(overflow.instruction_pointer, 2);
+
+    // arb #-5; add #1, #1, rb+MIN
+    assert_eq!(
+        error(&[109, -5, 21101, 1, 1, i64::MIN, 99]).kind,
+        VmErrorKind::AddressTooLarge
+    );
+}
+
+#[test]
+fn test_far_addresses_need_no_allocation() {
+    const FAR: i64 = 1_000_000_000_000;
+
+    // arb #FAR; out rb+0; hlt
+    assert_eq!(run(&[109, FAR, 204, 0, 99], &[]), vec![0]);
+
+    // add #7, #0, [FAR]; out [FAR]; hlt
+    let program = [1101, 7, 0, FAR, 4, FAR, 99];
+    for backend in BACKENDS.iter() {
+        let mut vm = IntcodeVM::with_memory(PagedMemory::new());
+        vm.set_backend(*backend);
+        vm.set_program(program.to_vec());
+        assert_eq!(vm.run_once().unwrap(), vec![7]);
+        assert_eq!(vm.read_memory(FAR as usize), 7);
+    }
+}
+
+#[test]
+fn test_bounded_memory_faults_past_its_limit() {
+    // loop: arb #4; add #1, #0, rb+6; jz #0, #loop
+    // Writes to 10 and 14, then 18.
+    let program = [109, 4, 21101, 1, 0, 6, 1106, 0, 0];
+    for backend in BACKENDS.iter() {
+        let mut vm = IntcodeVM::with_memory(BoundedMemory::new(16));
+        vm.set_backend(*backend);
+        vm.set_program(program.to_vec());
+
+        let error = vm.run_once().unwrap_err();
+        assert_eq!(
+            error.kind,
+            VmErrorKind::MemoryLimit(MemoryError {
+                address: 18,
+                limit: 16,
+            }),
+            "{:?}",
+            backend
+        );
+        assert_eq!(error.instruction_pointer, 2);
+        assert_eq!(vm.read_memory(14), 1);
+    }
+}
+
+#[test]
+fn test_arithmetic_policies() {
+    // mul #MAX, #2, [7]; out [7]; hlt; and the same with add #MIN, #-1.
+    let mul = [1102, i64::MAX, 2, 7, 4, 7, 99, 0];
+    let add = [1101, i64::MIN, -1, 7, 4, 7, 99, 0];
+    let cases = [
+        (Arithmetic::Wrapping, Some(-2), Some(i64::MAX)),
+        (Arithmetic::Saturating, Some(i64::MAX), Some(i64::MIN)),
+        (Arithmetic::Checked, None, None),
+    ];
+
+    for backend in BACKENDS.iter() {
+        for (arithmetic, product, sum) in cases.iter() {
+            for (program, expected, operands) in
+                [(mul, product, (i64::MAX, 2)), (add, sum, (i64::MIN, -1))].iter()
+            {
+                let mut vm = IntcodeVM::new();
+                vm.set_backend(*backend);
+                vm.set_arithmetic(*arithmetic);
+                vm.set_program(program.to_vec());
+
+                match expected {
+                    Some(value) => assert_eq!(vm.run_once().unwrap(), vec![*value]),
+                    None => {
+                        let error = vm.run_once().unwrap_err();
+                        assert_eq!(error.kind, VmErrorKind::Overflow(operands.0, operands.1));
+                        assert_eq!(error.instruction_pointer, 0);
+                        assert_eq!(vm.read_memory(7), 0);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_output_is_kept_when_program_runs_off_the_end() {
+    for mut vm in vms(&[104, 3]) {
+        assert_eq!(vm.execute().unwrap(), VMState::Output(3));
+        assert_eq!(
+            vm.execute().unwrap_err().kind,
+            VmErrorKind::InstructionPointerOutOfBounds
+        );
+    }
+}
+
+#[test]
+fn test_self_modifying_code_invalidates_decoded_instructions() {
+    // out #0; add [1], #1, [1]; lt [1], #3, [20]; jnz [20], #0; hlt
+    // Every iteration rewrites the operand of the already decoded `out`.
+    let program = [104, 0, 1001, 1, 1, 1, 1007, 1, 3, 20, 1005, 20, 0, 99];
+    assert_eq!(run(&program, &[]), vec![0, 1, 2]);
+
+    // The second pass over address 2 finds `out #7` turned into `hlt`.
+    // in [2]; out #7; jz #0, #0
+    for mut vm in vms(&[3, 2, 104, 7, 1106, 0, 0]) {
+        vm.push_input(104);
+        vm.push_input(99);
+        assert_eq!(vm.execute().unwrap(), VMState::Output(7));
+        assert_eq!(vm.execute().unwrap(), VMState::Halted);
+        assert_eq!(vm.instruction_pointer(), 2);
+    }
+
+    // The first instruction turns the `add` right after it into a `mul`,
+    // which a compiled block must notice before running the next step.
+    // add #2, #0, [4]; add [11], [12], [13]; out [13]; hlt; data 6, 7, 0
+    let program = [1101, 2, 0, 4, 1, 11, 12, 13, 4, 13, 99, 6, 7, 0];
+    assert_eq!(run(&program, &[]), vec![42]);
+}
+
+#[test]
+fn test_execute_matches_stepping() {
+    let program = input::parse_intcode(include_str!("../../inputs/day09.txt")).unwrap();
+
+    let mut executed = IntcodeVM::new();
+    executed.set_program(program.clone());
+    executed.push_input(1);
+    let mut stepped = executed.clone();
+    let mut compiled = executed.clone();
+    compiled.set_backend(Backend::Compiled);
+
+    let mut executed_trace = Recorder::default();
+    let mut stepped_trace = Recorder::default();
+    loop {
+        let state = executed.execute_traced(&mut executed_trace).unwrap();
+        let expected = loop {
+            if let Some(state) = stepped.step_traced(&mut stepped_trace).unwrap() {
+                break state;
+            }
+        };
+
+        assert_eq!(state, expected);
+        assert_eq!(compiled.execute().unwrap(), expected);
+        assert_eq!(
+            compiled.instruction_pointer(),
+            stepped.instruction_pointer()
+        );
+        assert_eq!(compiled.memory().to_vec(), stepped.memory().to_vec());
+        assert_eq!(
+            executed.instruction_pointer(),
+            stepped.instruction_pointer()
+        );
+        assert_eq!(executed.relative_pointer(), stepped.relative_pointer());
+        assert_eq!(executed.memory().to_vec(), stepped.memory().to_vec());
+        if state == VMState::Halted {
+            break;
+        }
+    }
+
+    assert!(!executed_trace.0.is_empty());
+    assert!(executed_trace.0 == stepped_trace.0);
+}
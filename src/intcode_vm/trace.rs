@@ -0,0 +1,233 @@
+//! Observers for `IntcodeVM::execute_traced` and `IntcodeVM::step_traced`.
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+
+use super::{MemoryWrite, Opcode, ParamMode};
+
+/// Receives a callback for every instruction the VM executes.
+pub trait TraceSink {
+    /// Called once per instruction, after its parameters are resolved and
+    /// before it takes effect. `operands` holds the value of every input
+    /// parameter and the target address of every output parameter.
+    fn instruction(&mut self, address: usize, opcode: Opcode, operands: &[i64]);
+
+    /// Called for every memory write made by an instruction.
+    fn memory_write(&mut self, write: MemoryWrite);
+
+    /// Called for every memory read made to resolve an input parameter,
+    /// before the instruction is reported.
+    fn memory_read(&mut self, _address: usize, _value: i64) {}
+}
+
+impl<T: TraceSink + ?Sized> TraceSink for &mut T {
+    fn instruction(&mut self, address: usize, opcode: Opcode, operands: &[i64]) {
+        (**self).instruction(address, opcode, operands)
+    }
+
+    fn memory_write(&mut self, write: MemoryWrite) {
+        (**self).memory_write(write)
+    }
+
+    fn memory_read(&mut self, address: usize, value: i64) {
+        (**self).memory_read(address, value)
+    }
+}
+
+/// Ignores everything; `execute` runs with this sink.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NoopSink;
+
+impl TraceSink for NoopSink {
+    fn instruction(&mut self, _address: usize, _opcode: Opcode, _operands: &[i64]) {}
+
+    fn memory_write(&mut self, _write: MemoryWrite) {}
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TraceEvent {
+    Instruction {
+        address: usize,
+        opcode: Opcode,
+        operands: Vec<i64>,
+    },
+    MemoryWrite(MemoryWrite),
+}
+
+/// Keeps the most recent `capacity` events in memory.
+#[derive(Clone, Debug)]
+pub struct RingBufferSink {
+    capacity: usize,
+    events: VecDeque<TraceEvent>,
+}
+
+impl RingBufferSink {
+    pub fn new(capacity: usize) -> Self {
+        RingBufferSink {
+            capacity,
+            events: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// The recorded events, oldest first.
+    pub fn events(&self) -> impl Iterator<Item = &TraceEvent> {
+        self.events.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    fn push(&mut self, event: TraceEvent) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+}
+
+impl TraceSink for RingBufferSink {
+    fn instruction(&mut self, address: usize, opcode: Opcode, operands: &[i64]) {
+        self.push(TraceEvent::Instruction {
+            address,
+            opcode,
+            operands: operands.to_vec(),
+        });
+    }
+
+    fn memory_write(&mut self, write: MemoryWrite) {
+        self.push(TraceEvent::MemoryWrite(write));
+    }
+}
+
+/// Writes one JSON object per event, e.g.
+///
+/// ```text
+/// {"event":"instruction","address":0,"opcode":"add","modes":["immediate","position","position"],"operands":[5,3,7]}
+/// {"event":"write","address":7,"old":0,"new":8}
+/// ```
+///
+/// The first I/O error stops further output and is returned by `finish`.
+#[derive(Debug)]
+pub struct JsonLinesSink<W: Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> JsonLinesSink<W> {
+    pub fn new(writer: W) -> Self {
+        JsonLinesSink {
+            writer,
+            error: None,
+        }
+    }
+
+    /// Flushes the writer and hands it back, or returns the first error hit
+    /// while tracing.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_line(&mut self, line: String) {
+        if self.error.is_none() {
+            if let Err(error) = writeln!(self.writer, "{}", line) {
+                self.error = Some(error);
+            }
+        }
+    }
+}
+
+fn mode_name(mode: ParamMode) -> &'static str {
+    match mode {
+        ParamMode::Position => "\"position\"",
+        ParamMode::Immediate => "\"immediate\"",
+        ParamMode::Relative => "\"relative\"",
+    }
+}
+
+fn join<T, F: Fn(&T) -> String>(values: &[T], format: F) -> String {
+    values.iter().map(format).collect::<Vec<_>>().join(",")
+}
+
+impl<W: Write> TraceSink for JsonLinesSink<W> {
+    fn instruction(&mut self, address: usize, opcode: Opcode, operands: &[i64]) {
+        let line = format!(
+            "{{\"event\":\"instruction\",\"address\":{},\"opcode\":\"{}\",\"modes\":[{}],\"operands\":[{}]}}",
+            address,
+            opcode.mnemonic(),
+            join(&opcode.param_modes(), |mode| mode_name(*mode).to_string()),
+            join(operands, |value| value.to_string()),
+        );
+        self.write_line(line);
+    }
+
+    fn memory_write(&mut self, write: MemoryWrite) {
+        let line = format!(
+            "{{\"event\":\"write\",\"address\":{},\"old\":{},\"new\":{}}}",
+            write.address, write.old_value, write.new_value
+        );
+        self.write_line(line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JsonLinesSink, RingBufferSink, TraceEvent};
+    use crate::intcode_vm::asm::assemble;
+    use crate::intcode_vm::MemoryWrite;
+    use crate::{IntcodeVM, Opcode, ParamMode, VMState};
+
+    fn vm(source: &str) -> IntcodeVM {
+        let mut vm = IntcodeVM::new();
+        vm.set_program(assemble(source).unwrap());
+        vm
+    }
+
+    #[test]
+    fn test_ring_buffer_keeps_latest_events() {
+        let mut vm = vm("add #5, #3, [7]\nout [7]\nhlt\ndata 0");
+        let mut sink = RingBufferSink::new(2);
+
+        assert_eq!(vm.execute_traced(&mut sink).unwrap(), VMState::Output(8));
+        assert_eq!(
+            sink.events().cloned().collect::<Vec<_>>(),
+            vec![
+                TraceEvent::MemoryWrite(MemoryWrite {
+                    address: 7,
+                    old_value: 0,
+                    new_value: 8,
+                }),
+                TraceEvent::Instruction {
+                    address: 4,
+                    opcode: Opcode::Output(ParamMode::Position),
+                    operands: vec![8],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_json_lines_sink() {
+        let mut vm = vm("add #5, #3, [5]\nhlt");
+        let mut sink = JsonLinesSink::new(vec![]);
+
+        assert_eq!(vm.execute_traced(&mut sink).unwrap(), VMState::Halted);
+        let output = String::from_utf8(sink.finish().unwrap()).unwrap();
+        assert_eq!(
+            output.lines().collect::<Vec<_>>(),
+            vec![
+                r#"{"event":"instruction","address":0,"opcode":"add","modes":["immediate","immediate","position"],"operands":[5,3,5]}"#,
+                r#"{"event":"write","address":5,"old":0,"new":8}"#,
+                r#"{"event":"instruction","address":4,"opcode":"hlt","modes":[],"operands":[]}"#,
+            ]
+        );
+    }
+}
@@ -1,5 +1,9 @@
-mod intcode_vm;
-pub use intcode_vm::{IntcodeVM, VMState};
+pub mod bigint;
+pub mod combinatorics;
+pub mod input;
+pub mod intcode_vm;
+pub mod solutions;
+pub use intcode_vm::{IntcodeVM, Opcode, ParamMode, VMState, VmError, VmErrorKind};
 
 #[cfg(test)]
 mod tests {
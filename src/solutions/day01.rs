@@ -0,0 +1,37 @@
+use super::{Answer, Solution, SolutionError};
+use crate::input;
+
+pub struct Day01;
+
+impl Solution for Day01 {
+    fn day(&self) -> u8 {
+        1
+    }
+
+    fn part1(&self, input: &str) -> Result<Answer, SolutionError> {
+        let sum: i32 = input::parse_lines::<i32>(input)?
+            .into_iter()
+            .map(|mass| (mass / 3 - 2).max(0))
+            .sum();
+
+        Ok(Box::new(sum))
+    }
+
+    fn part2(&self, input: &str) -> Result<Answer, SolutionError> {
+        let sum: i32 = input::parse_lines::<i32>(input)?
+            .into_iter()
+            .map(compute_fuel)
+            .sum();
+
+        Ok(Box::new(sum))
+    }
+}
+
+fn compute_fuel(mass: i32) -> i32 {
+    let fuel = (mass / 3) - 2;
+    if fuel <= 0 {
+        return 0;
+    }
+
+    fuel + compute_fuel(fuel)
+}
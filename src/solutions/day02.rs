@@ -0,0 +1,55 @@
+use super::{Answer, Solution, SolutionError};
+use crate::combinatorics;
+use crate::input;
+use crate::{IntcodeVM, VmError};
+
+pub struct Day02;
+
+/// Runs `program` with `noun` and `verb` written to addresses 1 and 2, and
+/// returns the value left at address 0 once it halts.
+pub fn run_with_noun_verb(program: &[i64], noun: i64, verb: i64) -> Result<i64, VmError> {
+    let mut vm = IntcodeVM::new();
+    vm.set_program(program.to_vec());
+    vm.write_memory(1, noun)?;
+    vm.write_memory(2, verb)?;
+    vm.run_once()?;
+
+    Ok(vm.read_memory(0))
+}
+
+impl Solution for Day02 {
+    fn day(&self) -> u8 {
+        2
+    }
+
+    fn part1(&self, input: &str) -> Result<Answer, SolutionError> {
+        let program = input::parse_intcode(input)?;
+        Ok(Box::new(run_with_noun_verb(&program, 12, 2)?))
+    }
+
+    fn part2(&self, input: &str) -> Result<Answer, SolutionError> {
+        let program = input::parse_intcode(input)?;
+
+        for pair in combinatorics::product(vec![0..=99, 0..=99]) {
+            let (noun, verb) = (pair[0], pair[1]);
+            if run_with_noun_verb(&program, noun, verb)? == 19_690_720 {
+                return Ok(Box::new(100 * noun + verb));
+            }
+        }
+
+        Err(SolutionError::NoAnswer(
+            "no noun and verb produce 19690720".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run_with_noun_verb;
+
+    #[test]
+    fn test_run_with_noun_verb() {
+        let program = [1, 0, 0, 3, 2, 3, 11, 0, 99, 30, 40, 50];
+        assert_eq!(run_with_noun_verb(&program, 9, 10).unwrap(), 3500);
+    }
+}
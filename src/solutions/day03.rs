@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+
+use rayon::prelude::*;
+
+use super::{Answer, Solution, SolutionError};
+
+pub struct Day03;
+
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+impl Point {
+    pub fn new(x: i32, y: i32) -> Self {
+        Point { x, y }
+    }
+}
+
+fn parse_points(actions: Vec<&str>) -> Result<Vec<Point>, SolutionError> {
+    let mut points: Vec<Point> = vec![];
+
+    let mut x = 0;
+    let mut y = 0;
+    for action in actions {
+        let invalid = || SolutionError::NoAnswer(format!("invalid wire segment `{}`", action));
+        if action.is_empty() {
+            return Err(invalid());
+        }
+
+        let parts = action.split_at(1);
+        let n = parts.1.parse::<i32>().map_err(|_| invalid())?;
+
+        match parts.0 {
+            "R" => {
+                for _ in 0..n {
+                    points.push(Point::new(x, y));
+                    x += 1;
+                }
+            }
+            "L" => {
+                for _ in 0..n {
+                    points.push(Point::new(x, y));
+                    x -= 1;
+                }
+            }
+            "U" => {
+                for _ in 0..n {
+                    points.push(Point::new(x, y));
+                    y += 1;
+                }
+            }
+            "D" => {
+                for _ in 0..n {
+                    points.push(Point::new(x, y));
+                    y -= 1;
+                }
+            }
+            _ => return Err(invalid()),
+        }
+    }
+
+    Ok(points)
+}
+
+/// Every crossing of the first two wires as `(steps, distance)`: the combined
+/// number of steps both wires take to reach it and its Manhattan distance
+/// from the origin.
+fn intersections(input: &str) -> Result<Vec<(usize, i32)>, SolutionError> {
+    let line_points = input
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.trim().split(',').collect::<Vec<_>>())
+        .map(parse_points)
+        .collect::<Result<Vec<Vec<Point>>, _>>()?;
+
+    if line_points.len() < 2 {
+        return Err(SolutionError::NoAnswer("expected two wires".to_string()));
+    }
+
+    // The number of steps the second wire takes to first reach each point
+    let mut second_wire = HashMap::with_capacity(line_points[1].len());
+    for (s1, p1) in line_points[1].iter().enumerate() {
+        second_wire.entry(*p1).or_insert(s1);
+    }
+
+    let result = line_points[0]
+        .par_iter()
+        .enumerate()
+        .filter(|(_, p)| p.x != 0 || p.y != 0)
+        .filter_map(|(s0, p0)| second_wire.get(p0).map(|s1| (s0, *s1, p0)))
+        .map(|(s0, s1, p)| {
+            let distance = (0 - p.x).abs() + (0 - p.y).abs();
+            let steps = s0 + s1;
+            (steps, distance)
+        })
+        .collect::<Vec<(usize, i32)>>();
+
+    if result.is_empty() {
+        return Err(SolutionError::NoAnswer("the wires never cross".to_string()));
+    }
+
+    Ok(result)
+}
+
+impl Solution for Day03 {
+    fn day(&self) -> u8 {
+        3
+    }
+
+    fn part1(&self, input: &str) -> Result<Answer, SolutionError> {
+        let closest = intersections(input)?
+            .into_iter()
+            .map(|(_, distance)| distance)
+            .min()
+            .unwrap();
+
+        Ok(Box::new(closest))
+    }
+
+    fn part2(&self, input: &str) -> Result<Answer, SolutionError> {
+        let fewest_steps = intersections(input)?
+            .into_iter()
+            .map(|(steps, _)| steps)
+            .min()
+            .unwrap();
+
+        Ok(Box::new(fewest_steps))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Day03;
+    use crate::solutions::Solution;
+
+    #[test]
+    fn test_example() {
+        let input = include_str!("../../inputs/day03_test.txt");
+        assert_eq!(Day03.part1(input).unwrap().to_string(), "135");
+        assert_eq!(Day03.part2(input).unwrap().to_string(), "410");
+    }
+}
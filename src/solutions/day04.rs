@@ -0,0 +1,115 @@
+use rayon::prelude::*;
+
+use super::{Answer, Solution, SolutionError};
+use crate::input;
+
+pub struct Day04;
+
+fn parse_range(input: &str) -> Result<(i32, i32), SolutionError> {
+    match input::parse_separated::<i32>(input, '-')?.as_slice() {
+        [start, end] => Ok((*start, *end)),
+        _ => Err(SolutionError::NoAnswer(
+            "expected a range like 123456-654321".to_string(),
+        )),
+    }
+}
+
+fn count_valid<F: Fn(i32) -> bool + Sync>(
+    input: &str,
+    is_valid: F,
+) -> Result<Answer, SolutionError> {
+    let (start, end) = parse_range(input)?;
+    let num_valid_passwords = (start..=end)
+        .into_par_iter()
+        .filter(|password| is_valid(*password))
+        .count();
+
+    Ok(Box::new(num_valid_passwords))
+}
+
+impl Solution for Day04 {
+    fn day(&self) -> u8 {
+        4
+    }
+
+    fn part1(&self, input: &str) -> Result<Answer, SolutionError> {
+        count_valid(input, is_valid_password_with_any_double)
+    }
+
+    fn part2(&self, input: &str) -> Result<Answer, SolutionError> {
+        count_valid(input, is_valid_password)
+    }
+}
+
+fn digits(password: i32) -> Vec<u32> {
+    password
+        .to_string()
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .collect::<Vec<u32>>()
+}
+
+fn is_not_descending(numbers: &[u32]) -> bool {
+    (1..(numbers.len()))
+        .into_par_iter()
+        .all(|i| numbers[i] >= numbers[i - 1])
+}
+
+fn is_valid_password_with_any_double(password: i32) -> bool {
+    let numbers = digits(password);
+    let has_double = numbers.windows(2).any(|pair| pair[0] == pair[1]);
+
+    has_double && is_not_descending(&numbers)
+}
+
+fn is_valid_password(password: i32) -> bool {
+    let numbers = digits(password);
+
+    let only_doubles_of_two = numbers
+        .par_iter()
+        .map(|n| {
+            numbers
+                .par_iter()
+                .filter_map(|x| if n == x { Some(1) } else { None })
+                .sum::<i32>()
+                == 2
+        })
+        .any(|t| t);
+
+    only_doubles_of_two && is_not_descending(&numbers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_valid_password, is_valid_password_with_any_double};
+
+    #[test]
+    fn test_is_valid_password1() {
+        assert!(is_valid_password_with_any_double(111_111));
+    }
+
+    #[test]
+    fn test_is_valid_password2() {
+        assert!(!is_valid_password_with_any_double(223_450));
+    }
+
+    #[test]
+    fn test_is_valid_password3() {
+        assert!(!is_valid_password_with_any_double(123_789));
+    }
+
+    #[test]
+    fn test_is_valid_password4() {
+        assert!(is_valid_password(112_233));
+    }
+
+    #[test]
+    fn test_is_valid_password5() {
+        assert!(!is_valid_password(123_444));
+    }
+
+    #[test]
+    fn test_is_valid_password6() {
+        assert!(is_valid_password(111_122));
+    }
+}
@@ -0,0 +1,54 @@
+use super::{Answer, Solution, SolutionError};
+use crate::input;
+use crate::IntcodeVM;
+
+pub struct Day05;
+
+/// Runs the TEST diagnostic program for `system_id`. Every output before the
+/// last one is a test result that must be 0; the last one is the diagnostic
+/// code, which is returned.
+pub fn run_diagnostic(program: &[i64], system_id: i64) -> Result<i64, SolutionError> {
+    let mut vm = IntcodeVM::new();
+    vm.set_program(program.to_vec());
+    vm.push_input(system_id);
+
+    let output = vm.run_once()?;
+    match output.split_last() {
+        Some((code, tests)) if tests.iter().all(|test| *test == 0) => Ok(*code),
+        Some((_, tests)) => Err(SolutionError::NoAnswer(format!(
+            "diagnostic tests failed: {:?}",
+            tests
+        ))),
+        None => Err(SolutionError::NoAnswer(
+            "the diagnostic program didn't output anything".to_string(),
+        )),
+    }
+}
+
+impl Solution for Day05 {
+    fn day(&self) -> u8 {
+        5
+    }
+
+    fn part1(&self, input: &str) -> Result<Answer, SolutionError> {
+        let program = input::parse_intcode(input)?;
+        Ok(Box::new(run_diagnostic(&program, 1)?))
+    }
+
+    fn part2(&self, input: &str) -> Result<Answer, SolutionError> {
+        let program = input::parse_intcode(input)?;
+        Ok(Box::new(run_diagnostic(&program, 5)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run_diagnostic;
+
+    #[test]
+    fn test_run_diagnostic_compares_to_eight() {
+        let program = [3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+        assert_eq!(run_diagnostic(&program, 8).unwrap(), 1);
+        assert_eq!(run_diagnostic(&program, 7).unwrap(), 0);
+    }
+}
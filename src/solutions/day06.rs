@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use super::{Answer, Solution, SolutionError};
+
+pub struct Day06;
+
+type Orbits<'a> = HashMap<&'a str, &'a str>;
+
+fn parse_orbits(input: &str) -> Result<Orbits<'_>, SolutionError> {
+    input
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let parts = line.trim().split(')').collect::<Vec<_>>();
+            if parts.len() != 2 {
+                return Err(SolutionError::NoAnswer(format!("invalid orbit `{}`", line)));
+            }
+
+            Ok((parts[1], parts[0]))
+        })
+        .collect::<Result<Orbits, _>>()
+}
+
+impl Solution for Day06 {
+    fn day(&self) -> u8 {
+        6
+    }
+
+    fn part1(&self, input: &str) -> Result<Answer, SolutionError> {
+        let orbits = parse_orbits(input)?;
+        Ok(Box::new(count_orbits(&orbits)))
+    }
+
+    fn part2(&self, input: &str) -> Result<Answer, SolutionError> {
+        let orbits = parse_orbits(input)?;
+        Ok(Box::new(orbits_distance(&orbits, "YOU", "SAN")?))
+    }
+}
+
+fn count_orbits(orbits: &Orbits) -> u32 {
+    orbits.keys().fold(0, |acc, mut orbit| {
+        let mut count = 0;
+        while let Some(new_orbit) = orbits.get(orbit) {
+            count += 1;
+            orbit = new_orbit
+        }
+
+        acc + count
+    })
+}
+
+fn orbits_distance(orbits: &Orbits, from: &str, to: &str) -> Result<usize, SolutionError> {
+    let missing =
+        |name: &str| SolutionError::NoAnswer(format!("`{}` doesn't orbit anything", name));
+
+    let orbit1 = orbits.get(from).ok_or_else(|| missing(from))?;
+    let list1 = orbits_list(orbits, orbit1);
+
+    let orbit2 = orbits.get(to).ok_or_else(|| missing(to))?;
+    let list2 = orbits_list(orbits, orbit2);
+
+    for (idx1, orbit1) in list1.iter().enumerate() {
+        for (idx2, orbit2) in list2.iter().enumerate() {
+            if orbit1 == orbit2 {
+                return Ok(idx1 + idx2);
+            }
+        }
+    }
+
+    Err(SolutionError::NoAnswer(format!(
+        "`{}` and `{}` don't share an orbit",
+        from, to
+    )))
+}
+
+fn orbits_list<'a>(orbits: &Orbits<'a>, mut orbit: &'a str) -> Vec<&'a str> {
+    let mut list = vec![orbit];
+    while let Some(new_orbit) = orbits.get(orbit) {
+        orbit = new_orbit;
+        list.push(new_orbit);
+    }
+
+    list
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Day06;
+    use crate::solutions::Solution;
+
+    #[test]
+    fn test_example() {
+        let input = include_str!("../../inputs/day06_test.txt");
+        assert_eq!(Day06.part1(input).unwrap().to_string(), "54");
+        assert_eq!(Day06.part2(input).unwrap().to_string(), "4");
+    }
+}
@@ -0,0 +1,152 @@
+use rayon::prelude::*;
+
+use super::{Answer, Solution, SolutionError};
+use crate::combinatorics;
+use crate::input;
+use crate::intcode_vm::network::Network;
+use crate::IntcodeVM;
+
+pub struct Day07;
+
+/// The phase settings that send the highest signal to the thrusters.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AmplifierSettings {
+    pub phases: Vec<i64>,
+    pub signal: i64,
+}
+
+/// Tries every ordering of the phase settings `start..=end` on a chain of
+/// amplifiers running `program`, one per setting, each on its own copy of
+/// the VM, in parallel. With `feedback` the last amplifier feeds back into
+/// the first until they halt; without it the signal passes through them
+/// once. The range must hold at least one setting.
+pub fn best_phase_settings(
+    program: &[i64],
+    start: i64,
+    end: i64,
+    feedback: bool,
+) -> Result<AmplifierSettings, SolutionError> {
+    if start > end {
+        return Err(SolutionError::NoAnswer(format!(
+            "there are no phase settings from {} to {}",
+            start, end
+        )));
+    }
+
+    let mut amp = IntcodeVM::new();
+    amp.set_program(program.to_vec());
+
+    let results = combinatorics::par_permutations(start..=end)
+        .map(|phases| {
+            let signal = run_amplifiers(&amp, &phases, feedback)?;
+            Ok(AmplifierSettings { phases, signal })
+        })
+        .collect::<Result<Vec<_>, SolutionError>>()?;
+
+    results
+        .into_iter()
+        .max_by_key(|settings| settings.signal)
+        .ok_or_else(|| SolutionError::NoAnswer("there are no phase settings to try".to_string()))
+}
+
+/// Returns the last signal sent to the thrusters by a copy of `amp` per
+/// phase setting, or an error if they never send one.
+fn run_amplifiers(amp: &IntcodeVM, phases: &[i64], feedback: bool) -> Result<i64, SolutionError> {
+    let mut network = Network::new();
+    let amps = phases
+        .iter()
+        .enumerate()
+        .map(|(index, phase)| {
+            let node = network.add_node(&format!("amp {}", index), amp.clone());
+            network.seed(node, vec![*phase]);
+            node
+        })
+        .collect::<Vec<_>>();
+    let thrusters = network.add_port("thrusters");
+    if feedback {
+        network.ring(&amps);
+    } else {
+        network.chain(&amps);
+    }
+    network.connect(amps[amps.len() - 1], thrusters);
+    network.seed(amps[0], vec![0]);
+
+    network.run()?;
+    network.values(thrusters).last().copied().ok_or_else(|| {
+        SolutionError::NoAnswer(format!(
+            "the amplifiers with phases {:?} sent no signal to the thrusters",
+            phases
+        ))
+    })
+}
+
+impl Solution for Day07 {
+    fn day(&self) -> u8 {
+        7
+    }
+
+    fn part1(&self, input: &str) -> Result<Answer, SolutionError> {
+        let opcodes = input::parse_intcode(input)?;
+        Ok(Box::new(best_phase_settings(&opcodes, 0, 4, false)?.signal))
+    }
+
+    fn part2(&self, input: &str) -> Result<Answer, SolutionError> {
+        let opcodes = input::parse_intcode(input)?;
+        Ok(Box::new(best_phase_settings(&opcodes, 5, 9, true)?.signal))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{best_phase_settings, AmplifierSettings, Day07};
+    use crate::input;
+    use crate::solutions::{Solution, SolutionError};
+
+    #[test]
+    fn test_best_phase_settings() {
+        let program =
+            input::parse_intcode("3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0").unwrap();
+        assert_eq!(
+            best_phase_settings(&program, 0, 4, false).unwrap(),
+            AmplifierSettings {
+                phases: vec![4, 3, 2, 1, 0],
+                signal: 43210,
+            }
+        );
+
+        let program = input::parse_intcode(include_str!("../../inputs/day07_test.txt")).unwrap();
+        assert_eq!(
+            best_phase_settings(&program, 5, 9, true).unwrap(),
+            AmplifierSettings {
+                phases: vec![9, 8, 7, 6, 5],
+                signal: 139629729,
+            }
+        );
+    }
+
+    #[test]
+    fn test_feedback_loop_example() {
+        let input = include_str!("../../inputs/day07_test.txt");
+        assert_eq!(Day07.part2(input).unwrap().to_string(), "139629729");
+    }
+
+    #[test]
+    fn test_no_signal_is_an_error() {
+        // Reads its phase and input, then halts without output.
+        let program = input::parse_intcode("3,9,3,9,99").unwrap();
+        assert!(matches!(
+            best_phase_settings(&program, 0, 4, false),
+            Err(SolutionError::NoAnswer(_))
+        ));
+    }
+
+    #[test]
+    fn test_empty_phase_range_is_an_error() {
+        let program =
+            input::parse_intcode("3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0").unwrap();
+        assert!(matches!(
+            best_phase_settings(&program, 5, 4, false),
+            Err(SolutionError::NoAnswer(_))
+        ));
+    }
+}
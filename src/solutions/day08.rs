@@ -0,0 +1,70 @@
+use super::{Answer, Solution, SolutionError};
+use crate::input;
+
+pub struct Day08;
+
+const WIDTH: usize = 25;
+const HEIGHT: usize = 6;
+
+fn parse_layers(input: &str) -> Result<Vec<Vec<u8>>, SolutionError> {
+    let digits = input::parse_digits(input)?;
+    if digits.len() % (WIDTH * HEIGHT) != 0 {
+        return Err(SolutionError::NoAnswer(format!(
+            "{} pixels don't make whole {}x{} layers",
+            digits.len(),
+            WIDTH,
+            HEIGHT
+        )));
+    }
+
+    Ok(digits
+        .chunks(WIDTH * HEIGHT)
+        .map(|layer| layer.to_vec())
+        .collect())
+}
+
+fn count(layer: &[u8], digit: u8) -> usize {
+    layer.iter().filter(|pixel| **pixel == digit).count()
+}
+
+impl Solution for Day08 {
+    fn day(&self) -> u8 {
+        8
+    }
+
+    fn part1(&self, input: &str) -> Result<Answer, SolutionError> {
+        let layers = parse_layers(input)?;
+        let layer = layers.iter().min_by_key(|layer| count(layer, 0)).unwrap();
+
+        Ok(Box::new(count(layer, 1) * count(layer, 2)))
+    }
+
+    fn part2(&self, input: &str) -> Result<Answer, SolutionError> {
+        let layers = parse_layers(input)?;
+
+        // Generate the message
+        let mut new_image = [2; WIDTH * HEIGHT];
+        layers.iter().for_each(|layer| {
+            layer.iter().enumerate().for_each(|(i, pixel)| {
+                if new_image[i] == 2 {
+                    new_image[i] = *pixel;
+                }
+            })
+        });
+
+        // Render the message, one line per row
+        let mut message = String::new();
+        for y in 0..HEIGHT {
+            message.push('\n');
+            for x in 0..WIDTH {
+                message.push(if new_image[x + (y * WIDTH)] == 1 {
+                    '#'
+                } else {
+                    ' '
+                });
+            }
+        }
+
+        Ok(Box::new(message))
+    }
+}
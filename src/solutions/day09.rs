@@ -0,0 +1,52 @@
+use super::{Answer, Solution, SolutionError};
+use crate::input;
+use crate::IntcodeVM;
+
+pub struct Day09;
+
+fn run_boost(input: &str, mode: i64) -> Result<Answer, SolutionError> {
+    let opcodes = input::parse_intcode(input)?;
+
+    let mut vm = IntcodeVM::new();
+    vm.set_program(opcodes);
+    vm.push_input(mode);
+
+    let output = vm.run_once()?;
+    match output.as_slice() {
+        [keycode] => Ok(Box::new(*keycode)),
+        _ => Err(SolutionError::NoAnswer(format!(
+            "BOOST reported malfunctioning opcodes: {:?}",
+            output
+        ))),
+    }
+}
+
+impl Solution for Day09 {
+    fn day(&self) -> u8 {
+        9
+    }
+
+    fn part1(&self, input: &str) -> Result<Answer, SolutionError> {
+        run_boost(input, 1)
+    }
+
+    fn part2(&self, input: &str) -> Result<Answer, SolutionError> {
+        run_boost(input, 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::input;
+    use crate::IntcodeVM;
+
+    #[test]
+    fn test_relative_base_example() {
+        let program = input::parse_intcode(include_str!("../../inputs/day09_test.txt")).unwrap();
+
+        let mut vm = IntcodeVM::new();
+        vm.set_program(program);
+        vm.push_input(42);
+        assert_eq!(vm.run_once().unwrap(), vec![42]);
+    }
+}
@@ -0,0 +1,114 @@
+//! One `Solution` per puzzle day, plus the registry the `aoc` runner uses.
+
+pub mod day01;
+pub mod day02;
+pub mod day03;
+pub mod day04;
+pub mod day05;
+pub mod day06;
+pub mod day07;
+pub mod day08;
+pub mod day09;
+
+use std::error::Error;
+use std::fmt;
+
+use crate::input::InputError;
+use crate::intcode_vm::network::NetworkError;
+use crate::VmError;
+
+pub type Answer = Box<dyn fmt::Display>;
+
+#[derive(Debug)]
+pub enum SolutionError {
+    Input(InputError),
+    Vm(VmError),
+    Network(NetworkError),
+    /// The input is well formed but doesn't lead to an answer.
+    NoAnswer(String),
+}
+
+impl fmt::Display for SolutionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SolutionError::Input(error) => write!(f, "invalid input: {}", error),
+            SolutionError::Vm(error) => write!(f, "Intcode error: {}", error),
+            SolutionError::Network(error) => write!(f, "Intcode network error: {}", error),
+            SolutionError::NoAnswer(reason) => write!(f, "no answer: {}", reason),
+        }
+    }
+}
+
+impl Error for SolutionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SolutionError::Input(error) => Some(error),
+            SolutionError::Vm(error) => Some(error),
+            SolutionError::Network(error) => Some(error),
+            SolutionError::NoAnswer(_) => None,
+        }
+    }
+}
+
+impl From<InputError> for SolutionError {
+    fn from(error: InputError) -> Self {
+        SolutionError::Input(error)
+    }
+}
+
+impl From<VmError> for SolutionError {
+    fn from(error: VmError) -> Self {
+        SolutionError::Vm(error)
+    }
+}
+
+impl From<NetworkError> for SolutionError {
+    fn from(error: NetworkError) -> Self {
+        SolutionError::Network(error)
+    }
+}
+
+pub trait Solution: Sync {
+    fn day(&self) -> u8;
+
+    fn part1(&self, input: &str) -> Result<Answer, SolutionError>;
+
+    fn part2(&self, input: &str) -> Result<Answer, SolutionError>;
+
+    /// Runs part 1 or 2.
+    fn part(&self, part: u8, input: &str) -> Result<Answer, SolutionError> {
+        match part {
+            1 => self.part1(input),
+            2 => self.part2(input),
+            _ => Err(SolutionError::NoAnswer(format!(
+                "there is no part {}",
+                part
+            ))),
+        }
+    }
+}
+
+static SOLUTIONS: [&dyn Solution; 9] = [
+    &day01::Day01,
+    &day02::Day02,
+    &day03::Day03,
+    &day04::Day04,
+    &day05::Day05,
+    &day06::Day06,
+    &day07::Day07,
+    &day08::Day08,
+    &day09::Day09,
+];
+
+/// The solution for `day`, if there is one.
+pub fn get(day: u8) -> Option<&'static dyn Solution> {
+    SOLUTIONS
+        .iter()
+        .copied()
+        .find(|solution| solution.day() == day)
+}
+
+/// Every solution, in day order.
+pub fn all() -> impl Iterator<Item = &'static dyn Solution> {
+    SOLUTIONS.iter().copied()
+}